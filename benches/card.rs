@@ -11,7 +11,7 @@ use criterion::Criterion;
 use neovim_lib::{neovim_api::Buffer, Value};
 
 use nvimpam_lib::{
-  bufdata::BufData,
+  bufdata::{highlights::Highlights, BufData},
   card::{ges::GesType, keyword::Keyword},
   lines::Lines,
   linesiter::LinesIter,
@@ -70,11 +70,12 @@ fn bench_skip_ges(c: &mut Criterion) {
     lines.parse_strs(&GES);
 
     b.iter(|| {
+      let mut highlights = Highlights::new();
       let mut li: LinesIter<_> = lines.iter();
       let mut tmp = li.next().unwrap();
-      let mut _a = li.skip_ges(g, &tmp);
+      let mut _a = li.skip_ges(g, &tmp, &mut highlights);
       tmp = li.next().unwrap();
-      _a = li.skip_ges(g, &tmp);
+      _a = li.skip_ges(g, &tmp, &mut highlights);
     });
   });
 }
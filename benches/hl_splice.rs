@@ -14,14 +14,17 @@ use nvimpam_lib::{bufdata::BufData, linenr::LineNr};
 
 macro_rules! hl_bench {
   ($fn: ident; lines: ($start: expr, $end: expr);
-   spliceto: ($sstart: expr, $ssend: expr, $added: expr)) => {
+   spliceto: ($sstart: expr, $ssend: expr)) => {
     fn $fn(c: &mut Criterion) {
       use neovim_lib::{neovim_api::Buffer, Value};
-      use nvimpam_lib::bufdata::highlights::Highlights;
 
       c.bench_function(stringify!($fn), move |b| {
         let buf = Buffer::new(Value::from(0_usize));
         let origlines = fs::read("files/example.pc").expect("1");
+        let strings: Vec<String> = String::from_utf8_lossy(&origlines)
+          .lines()
+          .map(String::from)
+          .collect();
         let mut bufdata = BufData::new(&buf);
         bufdata.parse_slice(&origlines).expect("2");
 
@@ -30,29 +33,22 @@ macro_rules! hl_bench {
         assert!($end < 20587);
         assert!($ssend < 20587);
 
-        let v: Vec<_> = bufdata
-          .highlights
-          .iter()
-          .filter(|((l, _, _), _)| {
-            LineNr::from_usize($start) <= *l && *l < LineNr::from_usize($end)
-          })
-          .map(|((l, s, e), h)| ((*l, *s, *e), *h))
-          .collect();
+        let newlines = strings[$start..$end].to_vec();
+        let oldlines = strings[$sstart..$ssend].to_vec();
+        let newend = LineNr::from_usize($sstart + newlines.len());
+
+        // Replace the lines, and restore them afterwards to keep the buffer
+        // stable
+        b.iter(|| {
+          let changed = bufdata
+            .update($sstart.into(), $ssend.into(), newlines.clone())
+            .expect("3");
+
+          let _calls = black_box(bufdata.highlight_lines_calls(1, &changed));
 
-        b.iter(move || {
-          let newhls: Highlights = Highlights(v.clone());
-          let range = bufdata.highlights.splice(
-            newhls,
-            $sstart.into(),
-            $ssend.into(),
-            $added,
-          );
-
-          let _calls = black_box(bufdata.highlight_region_calls(
-            range.clone(),
-            range.start.into(),
-            range.end.into(),
-          ));
+          let _ = bufdata
+            .update($sstart.into(), newend, oldlines.clone())
+            .expect("4");
         })
       });
     }
@@ -81,6 +77,7 @@ fn bench_bufdata_readonly(c: &mut Criterion) {
 
     b.iter(|| {
       let _calls = black_box(bufdata.highlight_region_calls(
+        1,
         1000..10000,
         1000.into(),
         10000.into(),
@@ -92,49 +89,49 @@ fn bench_bufdata_readonly(c: &mut Criterion) {
 hl_bench!(
   bench_bufdata_change_line_start;
   lines: (28, 29);
-  spliceto: (28, 29, 0)
+  spliceto: (28, 29)
   );
 
 hl_bench!(
   bench_bufdata_change_line_end;
   lines: (20500, 20501);
-  spliceto: (20500, 20501, 0)
+  spliceto: (20500, 20501)
   );
 
 hl_bench!(
   bench_bufdata_add_line_start;
   lines: (28, 30);
-  spliceto: (28, 29, 1)
+  spliceto: (28, 29)
   );
 
 hl_bench!(
   bench_bufdata_add_line_end;
   lines: (20500, 20502);
-  spliceto: (20500, 20501, 1)
+  spliceto: (20500, 20501)
   );
 
 hl_bench!(
   bench_bufdata_delete_line_start;
   lines: (1, 1);
-  spliceto: (28, 29, -1_isize)
+  spliceto: (28, 29)
   );
 
 hl_bench!(
   bench_bufdata_delete_line_end;
   lines: (1, 1);
-  spliceto: (20500, 20501, -1_isize)
+  spliceto: (20500, 20501)
   );
 
 hl_bench!(
   bench_bufdata_paste_after;
   lines: (28, 35);
-  spliceto: (20586, 20586, 7)
+  spliceto: (20586, 20586)
   );
 
 hl_bench!(
   bench_bufdata_paste_before;
   lines: (28, 35);
-  spliceto: (0, 0, 7)
+  spliceto: (0, 0)
   );
 
 criterion_group!(
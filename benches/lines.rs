@@ -0,0 +1,209 @@
+extern crate nvimpam_lib;
+
+#[macro_use]
+extern crate criterion;
+extern crate neovim_lib;
+
+use std::fs;
+
+use criterion::{black_box, Criterion};
+
+use neovim_lib::{neovim_api::Buffer, Value};
+
+use nvimpam_lib::{
+  bufdata::BufData,
+  lines::{Lines, CHUNK_SIZE},
+};
+
+// The number of lines of the buffer, comments included
+const NUMLINES: usize = 5_000_000;
+
+// The number of lines pasted or deleted at once, spanning several chunks
+const PASTE: usize = 3 * CHUNK_SIZE;
+
+/// The lines that are pasted
+fn paste() -> Vec<String> {
+  (0..PASTE)
+    .map(|i| {
+      if i % 5 == 0 {
+        "SHELL / ".to_string()
+      } else {
+        "        END".to_string()
+      }
+    })
+    .collect()
+}
+
+/// Create the contents of a file with `NUMLINES` lines, every tenth one being a
+/// comment.
+fn huge_file() -> Vec<u8> {
+  let mut v = Vec::with_capacity(NUMLINES * 12);
+
+  for i in 0..NUMLINES {
+    if i % 10 == 9 {
+      v.extend_from_slice(b"$Comment\n");
+    } else if i % 5 == 0 {
+      v.extend_from_slice(b"NODE  / \n");
+    } else {
+      v.extend_from_slice(b"        END\n");
+    }
+  }
+
+  v
+}
+
+macro_rules! lines_bench {
+  ($fn: ident; line: $line: expr; change) => {
+    fn $fn(c: &mut Criterion) {
+      c.bench_function(stringify!($fn), move |b| {
+        let origlines = huge_file();
+        let mut lines = Lines::new();
+        lines.parse_slice(&origlines);
+
+        b.iter(|| {
+          let _ = black_box(lines.update(
+            vec!["SHELL / ".to_string()],
+            $line.into(),
+            ($line + 1).into(),
+            0,
+          ));
+        })
+      });
+    }
+  };
+  ($fn: ident; line: $line: expr; insert_delete) => {
+    fn $fn(c: &mut Criterion) {
+      c.bench_function(stringify!($fn), move |b| {
+        let origlines = huge_file();
+        let mut lines = Lines::new();
+        lines.parse_slice(&origlines);
+
+        // Insert a line, and delete it again to keep the size stable
+        b.iter(|| {
+          let _ = black_box(lines.update(
+            vec!["SHELL / ".to_string()],
+            $line.into(),
+            $line.into(),
+            1,
+          ));
+          let _ = black_box(lines.update(
+            vec![],
+            $line.into(),
+            ($line + 1).into(),
+            -1,
+          ));
+        })
+      });
+    }
+  };
+  ($fn: ident; line: $line: expr; paste_delete) => {
+    fn $fn(c: &mut Criterion) {
+      c.bench_function(stringify!($fn), move |b| {
+        let origlines = huge_file();
+        let mut lines = Lines::new();
+        lines.parse_slice(&origlines);
+        let pasted = paste();
+
+        // Paste lines across a chunk boundary, and delete them again. The
+        // first paste adds chunks, all later ones reuse them.
+        b.iter(|| {
+          let _ = black_box(lines.update(
+            pasted.clone(),
+            $line.into(),
+            $line.into(),
+            PASTE as isize,
+          ));
+          let _ = black_box(lines.update(
+            vec![],
+            $line.into(),
+            ($line + PASTE).into(),
+            -(PASTE as isize),
+          ));
+        })
+      });
+    }
+  };
+}
+
+lines_bench!(bench_lines_change_top; line: 1000; change);
+lines_bench!(bench_lines_change_middle; line: NUMLINES / 2; change);
+lines_bench!(bench_lines_change_bottom; line: NUMLINES - 1000; change);
+
+lines_bench!(bench_lines_insert_delete_top; line: 1000; insert_delete);
+lines_bench!(bench_lines_insert_delete_middle; line: NUMLINES / 2;
+             insert_delete);
+lines_bench!(bench_lines_insert_delete_bottom; line: NUMLINES - 1000;
+             insert_delete);
+
+lines_bench!(bench_lines_paste_delete_top; line: CHUNK_SIZE - 100;
+             paste_delete);
+lines_bench!(bench_lines_paste_delete_middle;
+             line: NUMLINES / 2 - 100; paste_delete);
+lines_bench!(bench_lines_paste_delete_bottom;
+             line: NUMLINES - 2 * CHUNK_SIZE - 100; paste_delete);
+
+// The number of lines of files/example.pc
+const EXAMPLE_NUMLINES: usize = 20586;
+
+macro_rules! bufdata_bench {
+  ($fn: ident; line: $line: expr) => {
+    fn $fn(c: &mut Criterion) {
+      c.bench_function(stringify!($fn), move |b| {
+        let buf = Buffer::new(Value::from(0_usize));
+        let origlines = fs::read("files/example.pc").expect("1");
+        let mut bufdata = BufData::new(&buf);
+        bufdata.parse_slice(&origlines).expect("2");
+        assert_eq!(bufdata.numlines(), EXAMPLE_NUMLINES);
+
+        let node =
+          "NODE  /        1              0.            50.5              0.";
+
+        // Insert a line, and delete it again to keep the buffer stable. Both
+        // updates reparse the cards around the line.
+        b.iter(|| {
+          let _ = black_box(
+            bufdata
+              .update($line.into(), $line.into(), vec![node.to_string()])
+              .expect("3"),
+          );
+          let _ = black_box(
+            bufdata
+              .update($line.into(), ($line + 1).into(), vec![])
+              .expect("4"),
+          );
+        })
+      });
+    }
+  };
+}
+
+bufdata_bench!(bench_bufdata_insert_delete_first; line: 0);
+bufdata_bench!(bench_bufdata_insert_delete_last; line: EXAMPLE_NUMLINES - 1);
+
+fn bench_lines_iter(c: &mut Criterion) {
+  c.bench_function("bench_lines_iter", move |b| {
+    let origlines = huge_file();
+    let mut lines = Lines::new();
+    lines.parse_slice(&origlines);
+
+    b.iter(|| black_box(lines.iter().filter(|l| l.keyword.is_some()).count()))
+  });
+}
+
+criterion_group!(
+  name = lines;
+  config = Criterion::default().sample_size(10).without_plots();
+  targets = bench_lines_change_top,
+            bench_lines_change_middle,
+            bench_lines_change_bottom,
+            bench_lines_insert_delete_top,
+            bench_lines_insert_delete_middle,
+            bench_lines_insert_delete_bottom,
+            bench_lines_paste_delete_top,
+            bench_lines_paste_delete_middle,
+            bench_lines_paste_delete_bottom,
+            bench_lines_iter,
+            bench_bufdata_insert_delete_first,
+            bench_bufdata_insert_delete_last,
+);
+criterion_main!(lines);
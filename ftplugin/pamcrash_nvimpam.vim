@@ -13,6 +13,13 @@ command -buffer NvimPamHighlightScreen call luaeval(
       \ 'require("nvimpam").highlight_region(_A.b, _A.f, _A.l)',
       \ { 'b': bufnr('%'), 'f': line('w0')-1, 'l': line('w$')-1 }
       \ )
+command -buffer NvimPamStats call luaeval('require("nvimpam").show_stats()')
+command -buffer -nargs=1 -complete=file NvimPamExportVtk call luaeval(
+      \ 'require("nvimpam").export_vtk(_A)', <q-args>
+      \ )
+command -buffer -nargs=1 -complete=file NvimPamImportNastran call luaeval(
+      \ 'require("nvimpam").import_nastran(_A)', <q-args>
+      \ )
 command -buffer NvimPamMenu call luaeval('require("nvimpam.cardmenu").cardmenu()')
 
 augroup nvimpam_leave
@@ -42,6 +49,15 @@ else
   highlight default PamKeyword cterm=bold ctermfg=94 gui=bold guifg=#875f00
 endif
 
+" The groups of the cell types look like plain cells unless a colorscheme sets
+" them
+for s:type in ['Id', 'Integer', 'Float', 'Str', 'Binary', 'Fixed', 'Cont']
+  execute 'highlight default link Pam'.s:type.'Even PamCellEven'
+  execute 'highlight default link Pam'.s:type.'Odd PamCellOdd'
+endfor
+unlet s:type
+highlight default link PamGesKeyword PamKeyword
+
 if !exists('b:undo_ftplugin')
   let b:undo_ftplugin = ''
 endif
@@ -50,4 +66,7 @@ let b:undo_ftplugin .= '|setlocal foldtext='.s:save_foldtext
       \ . '|delcommand NvimPamAttach'
       \ . '|delcommand NvimPamUpdateFolds'
       \ . '|delcommand NvimPamHighlightScreen'
+      \ . '|delcommand NvimPamStats'
+      \ . '|delcommand NvimPamExportVtk'
+      \ . '|delcommand NvimPamImportNastran'
       \ . '|delcommand NvimPamMenu'
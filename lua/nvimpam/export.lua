@@ -0,0 +1,26 @@
+local curbuf = vim.api.nvim_get_current_buf
+local call = vim.api.nvim_call_function
+local command = vim.api.nvim_command
+
+local nvimpam_err = require('nvimpam.job').nvimpam_err
+local jobids = require('nvimpam.job').jobids
+
+local function export_vtk(file, buf)
+  buf = buf or curbuf()
+
+  if not jobids[buf] then
+    nvimpam_err("ExportVtk failed: No jobid entry for buffer "..tostring(buf).."!")
+    return false
+  end
+
+  local path = call("fnamemodify", { file, ":p" })
+  -- the reply is {changedtick, message}
+  local msg = call("rpcrequest", { jobids[buf], "ExportVtk", buf, path })[2]
+
+  command("echomsg '"..string.gsub(msg, "'", "''").."'")
+  return true
+end
+
+return {
+  export_vtk = export_vtk,
+}
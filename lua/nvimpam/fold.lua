@@ -7,6 +7,7 @@ local eval = vim.api.nvim_eval
 
 local nvimpam_err = require('nvimpam.job').nvimpam_err
 local jobids = require('nvimpam.job').jobids
+local highlight_region = require('nvimpam.highlight').highlight_region
 
 -- Holds the foldtexts, values of the form {start, end, text}
 local foldtexts = {}
@@ -57,14 +58,37 @@ local function refresh_folds(buf)
     return false
   end
 
-  local newfolds = eval("rpcrequest("..jobids[buf]..", 'RefreshFolds')")
-  update_folds(newfolds)
+  -- the reply is {changedtick, folds}
+  local res = eval("rpcrequest("..jobids[buf]..", 'RefreshFolds', "..buf..")")
+
+  -- discard folds computed for an outdated buffer state
+  if res[1] ~= call("getbufvar", { buf, "changedtick" }) then
+    return false
+  end
+
+  update_folds(res[2])
+  return true
+end
+
+-- Called by nvimpam after reattaching to a reloaded buffer, with the reply
+-- {changedtick, folds} it would have sent for RefreshFolds
+local function on_reload(buf, res)
+  if buf ~= curbuf() then
+    return false
+  end
+
+  if res[1] == call("getbufvar", { buf, "changedtick" }) then
+    update_folds(res[2])
+  end
+
+  highlight_region(buf, call("line", { "w0" }) - 1, call("line", { "w$" }) - 1)
   return true
 end
 
 return {
   update_folds = update_folds,
   refresh_folds = refresh_folds,
+  on_reload = on_reload,
   foldtext = foldtext,
   printfolds = printfolds,
 }
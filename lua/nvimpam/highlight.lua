@@ -13,7 +13,7 @@ local function highlight_region(buf, firstline, lastline)
     return false
   end
 
-  call("rpcnotify", { jobids[buf], "HighlightRegion", firstline, lastline })
+  call("rpcnotify", { jobids[buf], "HighlightRegion", buf, firstline, lastline })
   return true
 end
 
@@ -0,0 +1,35 @@
+local curbuf = vim.api.nvim_get_current_buf
+local call = vim.api.nvim_call_function
+local command = vim.api.nvim_command
+local get_cursor = vim.api.nvim_win_get_cursor
+local set_lines = vim.api.nvim_buf_set_lines
+
+local nvimpam_err = require('nvimpam.job').nvimpam_err
+local jobids = require('nvimpam.job').jobids
+
+-- Convert a Nastran file and insert the cards below the cursor
+local function import_nastran(file, buf)
+  buf = buf or curbuf()
+
+  if not jobids[buf] then
+    nvimpam_err("ImportNastran failed: No jobid entry for buffer "..tostring(buf).."!")
+    return false
+  end
+
+  local path = call("fnamemodify", { file, ":p" })
+  local res = call("rpcrequest", { jobids[buf], "ImportNastran", path })
+  local lines, messages = res[1], res[2]
+
+  local row = get_cursor(0)[1]
+  set_lines(buf, row, row, false, lines)
+
+  for _, msg in ipairs(messages) do
+    command("echomsg '"..string.gsub(msg, "'", "''").."'")
+  end
+
+  return true
+end
+
+return {
+  import_nastran = import_nastran,
+}
@@ -2,11 +2,15 @@ local utils = require('nvimpam.utils')
 local highlight = require('nvimpam.highlight')
 local fold = require('nvimpam.fold')
 local job = require('nvimpam.job')
+local stats = require('nvimpam.stats')
+local export = require('nvimpam.export')
+local import = require('nvimpam.import')
 
 return {
   -- job
   attach = job.attach,
   detach = job.detach,
+  register = job.register,
   detach_all = job.detach_all,
   on_stderr = job.on_stderr,
   on_exit = job.on_exit,
@@ -14,9 +18,16 @@ return {
   -- fold
   update_folds = fold.update_folds,
   refresh_folds = fold.refresh_folds,
+  on_reload = fold.on_reload,
   foldtext = fold.foldtext,
   -- utils
   locate_binary = utils.locate_binary,
   -- highlight
   highlight_region = highlight.highlight_region,
+  -- stats
+  show_stats = stats.show_stats,
+  -- export
+  export_vtk = export.export_vtk,
+  -- import
+  import_nastran = import.import_nastran,
 }
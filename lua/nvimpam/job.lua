@@ -40,17 +40,12 @@ local function nvimpam_err(msg, id)
 end
 
 local function on_exit(id, exitcode)
-  local bufname
-
+  -- one job serves all attached buffers
   for buffer, jobid in pairs(jobids) do
     if jobid == id then
-      bufname = buffer
+      jobids[buffer] = nil
     end
   end
-  
-  if bufname then
-    jobids[bufname] = nil
-  end
 end
 
 local function attach(filename)
@@ -62,6 +57,19 @@ local function attach(filename)
     return false
   end
 
+  -- reuse a running job if there is one
+  local _, running = next(jobids)
+  if running then
+    local ok, err = pcall(call, "rpcrequest", { running, "AttachBuffer", buf })
+    if not ok then
+      nvimpam_err("Attach to buffer "..tostring(buf).." failed: "
+                  ..tostring(err))
+      return false
+    end
+    jobids[buf] = running
+    return true
+  end
+
   if binary == nil then
     binary = locate_binary()
   end
@@ -72,11 +80,11 @@ local function attach(filename)
     return false
   end
 
-  local binlist
-  if filename == nil or filename == "" then
-    binlist = { binary }
-  else
-    binlist = { binary, filename }
+  -- pass the buffer, since it might not be the current one anymore when
+  -- nvimpam has started up
+  local binlist = { binary, "--buffer", tostring(buf) }
+  if filename ~= nil and filename ~= "" then
+    table.insert(binlist, filename)
   end
 
   if not callbacks_defined["onexit"] then
@@ -130,6 +138,12 @@ local function attach(filename)
   end
 end
 
+-- Register a running nvimpam that connected on its own (see
+-- |nvimpam-connect|) as the job serving the buffer
+local function register(chan, buf)
+  jobids[buf] = chan
+end
+
 local function detach(buf)
   buf = buf or curbuf()
   local jobid = jobids[buf]
@@ -138,7 +152,13 @@ local function detach(buf)
     nvimpam_err("Detach failed: No jobid entry for buffer "..tostring(buf).."!")
     return false
   else
-    call("rpcnotify", { jobids[buf], "quit" })
+    local ok, err = pcall(call, "rpcrequest", { jobid, "DetachBuffer", buf })
+    if not ok then
+      nvimpam_err("Detach from buffer "..tostring(buf).." failed: "
+                  ..tostring(err))
+      return false
+    end
+    jobids[buf] = nil
     return true
   end
 end
@@ -190,6 +210,7 @@ end
 return {
   attach = attach,
   detach = detach,
+  register = register,
   detach_all = detach_all,
   on_stderr = on_stderr,
   on_exit = on_exit,
@@ -0,0 +1,28 @@
+local curbuf = vim.api.nvim_get_current_buf
+local call = vim.api.nvim_call_function
+local command = vim.api.nvim_command
+local set_lines = vim.api.nvim_buf_set_lines
+
+local nvimpam_err = require('nvimpam.job').nvimpam_err
+local jobids = require('nvimpam.job').jobids
+
+local function show_stats(buf)
+  buf = buf or curbuf()
+
+  if not jobids[buf] then
+    nvimpam_err("Stats failed: No jobid entry for buffer "..tostring(buf).."!")
+    return false
+  end
+
+  -- the reply is {changedtick, report}
+  local report = call("rpcrequest", { jobids[buf], "Stats", buf })[2]
+
+  command("new")
+  command("setlocal buftype=nofile bufhidden=wipe noswapfile nobuflisted")
+  set_lines(0, 0, -1, false, report)
+  return true
+end
+
+return {
+  show_stats = show_stats,
+}
@@ -10,38 +10,124 @@
 //! It will automatically notify neovim of its activity, request the whole
 //! buffer and parse it for folds. After that, it sends the folds to neovim.
 //!
+//! The buffer to attach to should be passed with `--buffer`:
+//!
+//! ```text
+//! let s:bin = 'nvimpam'
+//! let s:id = jobstart([s:bin, '--buffer', bufnr('%')], { 'rpc': v:true }
+//! ```
+//!
+//! Otherwise, nvimpam attaches to the buffer that is current when it has
+//! started up, which might not be the one it was started for.
+//!
 //! As a performance optimization, pass the filename as a first argument:
 //!
 //! ```text
 //! let s:bin = 'nvimpam'
 //! let s:file = expand('%:p')
-//! let s:id = jobstart([s:bin.' '.s:file], { 'rpc': v:true }
+//! let s:id = jobstart([s:bin, '--buffer', bufnr('%'), s:file], { 'rpc': v:true }
 //! ```
 //!
 //! Nvimpam will load the file from disc instead of requesting it over RPC,
 //! which is quite a bit faster (mostly probably because the file is cached by
 //! your OS since it was loaded by neovim just before).
 //!
+//! To print the statistics of a file (see
+//! [`Stats`](nvimpam_lib::bufdata::stats::Stats)) without starting neovim, run
+//!
+//! ```text
+//! nvimpam stats FILE
+//! ```
+//!
+//! To export the mesh of a file to VTK (see
+//! [`write_vtk`](nvimpam_lib::bufdata::BufData::write_vtk)), e.g. to look at
+//! it in ParaView, run
+//!
+//! ```text
+//! nvimpam vtk FILE OUTFILE
+//! ```
+//!
+//! To convert a Nastran bulk data file to Pamcrash cards (see
+//! [`nastran`](nvimpam_lib::nastran)), run
+//!
+//! ```text
+//! nvimpam nastran FILE [OUTFILE]
+//! ```
+//!
+//! To check files for errors without starting neovim, e.g. in a pre-commit
+//! hook, run
+//!
+//! ```text
+//! nvimpam check [--allow=RULE,...] FILE...
+//! ```
+//!
+//! This prints the
+//! [`Diagnostic`](nvimpam_lib::bufdata::diagnostics::Diagnostic)s as
+//! `file:line:col: severity: message` and exits with a non-zero status if any
+//! errors were found. The line-level [`lint
+//! rules`](nvimpam_lib::bufdata::lint::Rule) given to `--allow` are not
+//! checked.
+//!
+//! To write the keywords, folds and highlights nvimpam computes for a file as
+//! JSON (see [`to_json`](nvimpam_lib::bufdata::BufData::to_json)), e.g. for
+//! regression tests against golden files, run
+//!
+//! ```text
+//! nvimpam json FILE [OUTFILE]
+//! ```
+//!
+//! To use nvimpam from other editors, run it as a language server (see
+//! [`lsp`](nvimpam_lib::lsp)) communicating over stdin/stdout:
+//!
+//! ```text
+//! nvimpam lsp
+//! ```
+//!
+//! To debug or profile nvimpam, it can connect to a running neovim instance
+//! instead, either over TCP or a unix socket:
+//!
+//! ```text
+//! nvimpam --connect=127.0.0.1:6666 --buffer 1
+//! nvimpam --connect=/tmp/nvimsocket --buffer 1
+//! ```
+//!
+//! Without an address, `--connect` uses `$NVIM_LISTEN_ADDRESS`. If no buffer
+//! is given, nvimpam attaches to the current buffer.
+//!
 //! If you want logging, set the following environment variables:
 //!
 //! * `NVIMPAM_LOG_FILE` is the path to the log file (no logging if this is
 //!   empty)
 //! * `NVIMPAM_LOG_LEVEL` can be one of `error`, `warn`, `info`, `debug` and
 //!   `trace`, in ascending order of verbosity. The default is `warn`.
-use std::{env::args_os, sync::mpsc};
+use std::{
+  env::{self, args_os},
+  ffi::OsString,
+  fs,
+  io::{self, Write},
+  net::SocketAddr,
+  process,
+  sync::mpsc,
+};
 
-use failure::{Error, ResultExt};
+use failure::{self, Error, ResultExt};
 use log::error;
 use neovim_lib::{
-  neovim::Neovim, neovim_api::NeovimApi, session::Session, Value,
+  neovim::Neovim,
+  neovim_api::{Buffer, NeovimApi},
+  session::Session,
+  Value,
 };
 use simplelog::{Config, Level, LevelFilter, WriteLogger};
 
-use nvimpam_lib::{event::Event, handler::NeovimHandler};
+use nvimpam_lib::{
+  bufdata::{diagnostics::Severity, lint::Lints, BufData},
+  event::Event,
+  handler::NeovimHandler,
+  lsp, nastran,
+};
 
 fn main() {
-  use std::process;
-
   match init_logging() {
     Err(e) => {
       eprintln!("Nvimpam: Error initializing logger: {}", e);
@@ -54,7 +140,23 @@ fn main() {
     Ok(()) => {}
   }
 
-  match start_program() {
+  let mut args = args_os().skip(1);
+  let arg = args.next();
+
+  match arg.as_ref().and_then(|a| a.to_str()) {
+    Some("stats") => exit_cli(print_stats(args.next())),
+    Some("vtk") => exit_cli(write_vtk(args.next(), args.next())),
+    Some("nastran") => exit_cli(convert_nastran(args.next(), args.next())),
+    Some("check") => exit_cli(check_files(args.collect())),
+    Some("json") => exit_cli(dump_json(args.next(), args.next())),
+    Some("lsp") => {
+      let stdin = io::stdin();
+      exit_cli(lsp::run(stdin.lock(), io::stdout()))
+    }
+    _ => {}
+  }
+
+  match start_program(arg, args) {
     Ok(_) => process::exit(0),
     Err(e) => {
       error!("Nvimpam encountered an error: {}", e);
@@ -67,6 +169,168 @@ fn main() {
   };
 }
 
+/// Exit after running a command line mode, printing the error (if any) to
+/// stderr.
+fn exit_cli(res: Result<(), Error>) -> ! {
+  match res {
+    Ok(()) => process::exit(0),
+    Err(e) => {
+      eprintln!("Nvimpam: {}", e);
+      for cause in e.iter_chain().skip(1) {
+        eprintln!("Caused by: {}", cause)
+      }
+      process::exit(1);
+    }
+  }
+}
+
+/// Print the [`Stats`](nvimpam_lib::bufdata::stats::Stats) of a file to
+/// stdout.
+fn print_stats(file: Option<OsString>) -> Result<(), Error> {
+  let file =
+    file.ok_or_else(|| failure::err_msg("Usage: nvimpam stats FILE"))?;
+  let origlines = fs::read(&file)
+    .with_context(|_| format!("Could not read '{}'", file.to_string_lossy()))?;
+
+  let buf = Buffer::new(Value::from(0_usize));
+  let mut bufdata = BufData::new(&buf);
+  bufdata.parse_slice(&origlines)?;
+
+  print!("{}", bufdata.stats());
+
+  Ok(())
+}
+
+/// Export the mesh of a file to VTK, see
+/// [`write_vtk`](nvimpam_lib::bufdata::BufData::write_vtk).
+fn write_vtk(
+  file: Option<OsString>,
+  outfile: Option<OsString>,
+) -> Result<(), Error> {
+  let usage = || failure::err_msg("Usage: nvimpam vtk FILE OUTFILE");
+  let file = file.ok_or_else(usage)?;
+  let outfile = outfile.ok_or_else(usage)?;
+  let origlines = fs::read(&file)
+    .with_context(|_| format!("Could not read '{}'", file.to_string_lossy()))?;
+
+  let buf = Buffer::new(Value::from(0_usize));
+  let mut bufdata = BufData::new(&buf);
+  bufdata.parse_slice(&origlines)?;
+
+  println!("{}", bufdata.export_vtk(&outfile)?);
+
+  Ok(())
+}
+
+/// Write the keywords, folds and highlights of a file as JSON, see
+/// [`to_json`](nvimpam_lib::bufdata::BufData::to_json). The JSON is written
+/// to `outfile`, or to stdout if it is not given.
+fn dump_json(
+  file: Option<OsString>,
+  outfile: Option<OsString>,
+) -> Result<(), Error> {
+  let file = file
+    .ok_or_else(|| failure::err_msg("Usage: nvimpam json FILE [OUTFILE]"))?;
+  let origlines = fs::read(&file)
+    .with_context(|_| format!("Could not read '{}'", file.to_string_lossy()))?;
+
+  let buf = Buffer::new(Value::from(0_usize));
+  let mut bufdata = BufData::new(&buf);
+  bufdata.parse_slice(&origlines)?;
+
+  let mut out: Box<dyn Write> = match outfile {
+    Some(ref f) => Box::new(fs::File::create(f).with_context(|_| {
+      format!("Could not create '{}'", f.to_string_lossy())
+    })?),
+    None => Box::new(io::stdout()),
+  };
+  serde_json::to_writer_pretty(&mut out, &bufdata.to_json())?;
+  writeln!(out)?;
+
+  Ok(())
+}
+
+/// Convert a Nastran file to Pamcrash cards, see
+/// [`nastran`](nvimpam_lib::nastran). The cards are written to `outfile`, or
+/// to stdout if it is not given. Cards that could not be converted are
+/// reported on stderr.
+fn convert_nastran(
+  file: Option<OsString>,
+  outfile: Option<OsString>,
+) -> Result<(), Error> {
+  let file = file
+    .ok_or_else(|| failure::err_msg("Usage: nvimpam nastran FILE [OUTFILE]"))?;
+  let input = fs::read(&file)
+    .with_context(|_| format!("Could not read '{}'", file.to_string_lossy()))?;
+
+  let conversion = nastran::convert(&String::from_utf8_lossy(&input));
+
+  let mut out: Box<dyn Write> = match outfile {
+    Some(ref f) => Box::new(fs::File::create(f).with_context(|_| {
+      format!("Could not create '{}'", f.to_string_lossy())
+    })?),
+    None => Box::new(io::stdout()),
+  };
+  for line in conversion.lines() {
+    writeln!(out, "{}", line)?;
+  }
+
+  for (linenr, msg) in &conversion.errors {
+    eprintln!("{}:{}: {}", file.to_string_lossy(), linenr, msg);
+  }
+  eprint!("{}", conversion);
+
+  Ok(())
+}
+
+/// Print the [`Diagnostic`](nvimpam_lib::bufdata::diagnostics::Diagnostic)s
+/// of the given files to stdout as `file:line:col: severity: message`. Fails
+/// if any errors were found. Arguments of the form `--allow=RULE[,RULE...]`
+/// turn off the given [`lint rules`](nvimpam_lib::bufdata::lint::Rule).
+fn check_files(args: Vec<OsString>) -> Result<(), Error> {
+  let mut lints = Lints::default();
+  let mut files = vec![];
+
+  for arg in args {
+    match arg.to_str() {
+      Some(a) if a.starts_with("--allow=") => lints.allow(&a[8..])?,
+      _ => files.push(arg),
+    }
+  }
+
+  if files.is_empty() {
+    return Err(failure::err_msg(
+      "Usage: nvimpam check [--allow=RULE,...] FILE...",
+    ));
+  }
+
+  let mut errors = 0;
+
+  for file in &files {
+    let origlines = fs::read(file).with_context(|_| {
+      format!("Could not read '{}'", file.to_string_lossy())
+    })?;
+
+    let buf = Buffer::new(Value::from(0_usize));
+    let mut bufdata = BufData::new(&buf);
+    bufdata.lints = lints;
+    bufdata.parse_slice(&origlines)?;
+
+    for diagnostic in bufdata.diagnostics() {
+      if diagnostic.severity == Severity::Error {
+        errors += 1;
+      }
+      println!("{}:{}", file.to_string_lossy(), diagnostic);
+    }
+  }
+
+  if errors > 0 {
+    return Err(failure::err_msg(format!("Found {} errors", errors)));
+  }
+
+  Ok(())
+}
+
 fn send_err(nvim: &mut Neovim, err: &Error) {
   let luafn = "require('nvimpam').nvimpam_err(...)";
   let luaargs = Value::from(format!("Nvimpam ecountered an error: {:?}!", err));
@@ -149,15 +413,55 @@ fn send_client_info(nvim: &mut Neovim) -> Result<(), Error> {
     (
       "RefreshFolds".into(),
       vec![
-        Value::from(vec![Value::from("nargs"), Value::from(0_u8)]),
+        Value::from(vec![Value::from("nargs"), Value::from(1_u8)]),
         Value::from(vec![Value::from("async"), Value::from(false)]),
       ]
       .into(),
     ),
     (
-      "HighlightRegion".into(),
+      "Stats".into(),
+      vec![
+        Value::from(vec![Value::from("nargs"), Value::from(1_u8)]),
+        Value::from(vec![Value::from("async"), Value::from(false)]),
+      ]
+      .into(),
+    ),
+    (
+      "AttachBuffer".into(),
+      vec![
+        Value::from(vec![Value::from("nargs"), Value::from(1_u8)]),
+        Value::from(vec![Value::from("async"), Value::from(false)]),
+      ]
+      .into(),
+    ),
+    (
+      "DetachBuffer".into(),
+      vec![
+        Value::from(vec![Value::from("nargs"), Value::from(1_u8)]),
+        Value::from(vec![Value::from("async"), Value::from(false)]),
+      ]
+      .into(),
+    ),
+    (
+      "ExportVtk".into(),
       vec![
         Value::from(vec![Value::from("nargs"), Value::from(2_u8)]),
+        Value::from(vec![Value::from("async"), Value::from(false)]),
+      ]
+      .into(),
+    ),
+    (
+      "ImportNastran".into(),
+      vec![
+        Value::from(vec![Value::from("nargs"), Value::from(1_u8)]),
+        Value::from(vec![Value::from("async"), Value::from(false)]),
+      ]
+      .into(),
+    ),
+    (
+      "HighlightRegion".into(),
+      vec![
+        Value::from(vec![Value::from("nargs"), Value::from(3_u8)]),
         Value::from(vec![Value::from("async"), Value::from(true)]),
       ]
       .into(),
@@ -186,10 +490,170 @@ fn send_client_info(nvim: &mut Neovim) -> Result<(), Error> {
   Ok(())
 }
 
-fn start_program() -> Result<(), Error> {
+const RPC_USAGE: &str =
+  "Usage: nvimpam [--connect[=ADDRESS]] [--buffer BUFNR] [FILE]";
+
+/// The arguments of the rpc mode
+#[derive(Default)]
+struct RpcArgs {
+  /// The address of the neovim instance to connect to, or `None` to talk to
+  /// the parent over stdin/stdout
+  address: Option<String>,
+  /// The buffer to attach to
+  buf: Option<Buffer>,
+  /// The file to read the buffer's contents from
+  file: Option<OsString>,
+}
+
+impl RpcArgs {
+  /// Parse the arguments of the rpc mode, i.e.
+  /// `[--connect[=ADDRESS]] [--buffer BUFNR] [FILE]`. If `--connect` is given
+  /// without an address, `$NVIM_LISTEN_ADDRESS` is used.
+  fn parse(
+    arg: Option<OsString>,
+    mut args: impl Iterator<Item = OsString>,
+  ) -> Result<Self, Error> {
+    let mut rpc_args = RpcArgs::default();
+    let mut next = arg;
+
+    while let Some(arg) = next.take() {
+      match arg.to_str() {
+        Some("--connect") => {
+          let address = env::var("NVIM_LISTEN_ADDRESS")
+            .context("'--connect' needs NVIM_LISTEN_ADDRESS to be set")?;
+          rpc_args.address = Some(address);
+        }
+        Some(s) if s.starts_with("--connect=") => {
+          rpc_args.address = Some(s["--connect=".len()..].to_owned());
+        }
+        Some("--buffer") => {
+          let bufnr = args
+            .next()
+            .and_then(|a| a.to_str().and_then(|s| s.parse::<u64>().ok()))
+            .ok_or_else(|| failure::err_msg(RPC_USAGE))?;
+          rpc_args.buf = Some(Buffer::new(Value::from(bufnr)));
+        }
+        Some(s) if s.starts_with("--") => {
+          return Err(failure::err_msg(RPC_USAGE));
+        }
+        _ => {
+          if rpc_args.file.is_some() {
+            return Err(failure::err_msg(RPC_USAGE));
+          }
+          rpc_args.file = Some(arg);
+        }
+      }
+      next = args.next();
+    }
+
+    Ok(rpc_args)
+  }
+}
+
+/// Create the session to neovim. Without an address, we talk to the parent
+/// process over stdin/stdout. Otherwise, the address is either a TCP address
+/// like `127.0.0.1:6666`, or the path of a unix socket.
+fn new_session(address: Option<String>) -> Result<Session, Error> {
+  let address = match address {
+    None => return Ok(Session::new_parent()?),
+    Some(a) => a,
+  };
+
+  if address.parse::<SocketAddr>().is_ok() {
+    return Ok(
+      Session::new_tcp(address.as_str())
+        .with_context(|_| format!("Could not connect to '{}'", address))?,
+    );
+  }
+
+  #[cfg(unix)]
+  {
+    Ok(
+      Session::new_unix_socket(&address)
+        .with_context(|_| format!("Could not connect to '{}'", address))?,
+    )
+  }
+
+  #[cfg(not(unix))]
+  {
+    Err(failure::err_msg(format!(
+      "Not a TCP address: '{}'",
+      address
+    )))
+  }
+}
+
+/// The api level of neovim 0.9.0. The highlights need the `strict` option of
+/// `nvim_buf_set_extmark`, and the healthcheck needs `vim.health.start`.
+const MIN_API_LEVEL: i64 = 11;
+
+/// Check that neovim is recent enough for nvimpam, and return the id of the
+/// channel neovim talks to us on. Both are read from the api info.
+fn check_api_info(nvim: &mut Neovim) -> Result<i64, Error> {
+  let info = nvim.get_api_info().context("could not get api info")?;
+
+  let chan = info
+    .first()
+    .and_then(Value::as_i64)
+    .ok_or_else(|| failure::err_msg("Could not get the channel id!"))?;
+  let api_level = info
+    .get(1)
+    .and_then(|m| map_get(m, "version"))
+    .and_then(|v| map_get(v, "api_level"))
+    .and_then(Value::as_i64)
+    .ok_or_else(|| failure::err_msg("Could not get the api level!"))?;
+
+  if api_level < MIN_API_LEVEL {
+    return Err(failure::err_msg(format!(
+      "Nvimpam needs neovim 0.9.0 or later (api level {}), but this one has \
+       api level {}!",
+      MIN_API_LEVEL, api_level
+    )));
+  }
+
+  Ok(chan)
+}
+
+/// Get the value of a key of a msgpack map
+fn map_get<'a>(map: &'a Value, key: &str) -> Option<&'a Value> {
+  map
+    .as_map()?
+    .iter()
+    .find(|(k, _)| k.as_str() == Some(key))
+    .map(|(_, v)| v)
+}
+
+/// Register the channel of a process started with `--connect` as the job
+/// serving the buffer, so the commands of the lua plugin talk to it.
+fn register_channel(
+  nvim: &mut Neovim,
+  chan: i64,
+  buf: &Option<Buffer>,
+) -> Result<(), Error> {
+  let buf = match buf {
+    Some(buf) => buf.get_value().clone(),
+    None => nvim.get_current_buf()?.get_value().clone(),
+  };
+
+  nvim
+    .execute_lua(
+      "require('nvimpam').register(...)",
+      vec![Value::from(chan), buf],
+    )
+    .context("could not register channel")?;
+
+  Ok(())
+}
+
+fn start_program(
+  arg: Option<OsString>,
+  args: impl Iterator<Item = OsString>,
+) -> Result<(), Error> {
+  let RpcArgs { address, buf, file } = RpcArgs::parse(arg, args)?;
+  let connected = address.is_some();
   let (handler_to_main, main_from_handler) = mpsc::channel();
   let (main_to_handler, handler_from_main) = mpsc::channel();
-  let mut session = Session::new_parent()?;
+  let mut session = new_session(address)?;
 
   session.start_event_loop_handler(NeovimHandler {
     to_main: handler_to_main,
@@ -197,11 +661,19 @@ fn start_program() -> Result<(), Error> {
   });
   let mut nvim = Neovim::new(session);
 
-  send_client_info(&mut nvim)?;
-
-  let file = args_os().nth(1);
+  let res = check_api_info(&mut nvim).and_then(|chan| {
+    send_client_info(&mut nvim)?;
+    if connected {
+      register_channel(&mut nvim, chan, &buf)?;
+    }
+    Ok(())
+  });
+  if let Err(e) = res {
+    send_err(&mut nvim, &e);
+    return Err(e);
+  }
 
-  Event::event_loop(&main_from_handler, &main_to_handler, &mut nvim, file)
+  Event::event_loop(&main_from_handler, &main_to_handler, &mut nvim, buf, file)
     .map_err(|e| {
       send_err(&mut nvim, &e);
       e
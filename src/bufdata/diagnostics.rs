@@ -0,0 +1,356 @@
+//! Diagnostics for the contents of a buffer, e.g. to check a file without
+//! opening it in neovim.
+use std::fmt;
+
+use crate::{
+  bufdata::{lint::Rule, structure::Issue, BufData},
+  card::keyword::Keyword,
+  lineid::LineId,
+  linenr::LineNr,
+};
+
+/// The severity of a [`Diagnostic`](crate::bufdata::diagnostics::Diagnostic)
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub enum Severity {
+  Error,
+  Warning,
+  Info,
+}
+
+impl fmt::Display for Severity {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Severity::Error => write!(f, "error"),
+      Severity::Warning => write!(f, "warning"),
+      Severity::Info => write!(f, "info"),
+    }
+  }
+}
+
+/// A problem found in the buffer. The line number is zero-indexed, `start`
+/// and `end` give the byte range of the affected columns. The
+/// [`LineId`](crate::lineid::LineId) of the line is kept as well, so the
+/// diagnostic can be followed when lines are added or removed before it.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Diagnostic {
+  pub id: LineId,
+  pub line: LineNr,
+  pub start: u8,
+  pub end: u8,
+  pub severity: Severity,
+  pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+  /// Formats as `line:col: severity: message`, with 1-indexed line and column
+  /// numbers, so a file name can be put in front to get the usual compiler
+  /// format.
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(
+      f,
+      "{}:{}: {}: {}",
+      usize::from(self.line) + 1,
+      self.start + 1,
+      self.severity,
+      self.message
+    )
+  }
+}
+
+impl<'a> BufData<'a> {
+  /// Collect the [`Diagnostic`](crate::bufdata::diagnostics::Diagnostic)s of
+  /// the buffer, ordered by position. Right now, these are the cells that did
+  /// not [`verify`](crate::card::cell::Cell::verify), the keywords of cards
+  /// that fall short of their definition and values straddling two cells
+  /// (see [`Issue`](crate::bufdata::structure::Issue)), the keywords of cards
+  /// nvimpam doesn't know, and the violations of the [`lints`] turned on.
+  ///
+  /// [`lints`]: crate::bufdata::lint::Lints
+  pub fn diagnostics(&self) -> Vec<Diagnostic> {
+    let mut diagnostics = vec![];
+
+    for ((id, start, end), _) in
+      self.highlights.iter().filter(|(_, h)| h.is_error())
+    {
+      let line = match self.lines.get_by_id(*id) {
+        Some(l) => l,
+        None => continue,
+      };
+      let text = line
+        .text
+        .get(*start as usize..*end as usize)
+        .map(String::from_utf8_lossy)
+        .unwrap_or_default();
+
+      let (idx, _) = self.lines.first_before(line.number);
+      let card = self
+        .lines
+        .get(idx)
+        .and_then(|l| l.keyword)
+        .map(|kw| format!(" in {:?} card", kw))
+        .unwrap_or_default();
+
+      diagnostics.push(Diagnostic {
+        id: *id,
+        line: line.number,
+        start: *start,
+        end: *end,
+        severity: Severity::Error,
+        message: format!("Invalid value '{}'{}", text.trim(), card),
+      });
+    }
+
+    for (id, (kw, issue)) in self.structure.iter() {
+      let line = match self.lines.get_by_id(*id) {
+        Some(l) => l,
+        None => continue,
+      };
+
+      let (start, end, severity) = match issue {
+        Issue::Misaligned { start, end, .. } => {
+          (*start, *end, Severity::Warning)
+        }
+        Issue::TrailingText { start, end } => {
+          if !self.lints.enabled(Rule::TrailingText) {
+            continue;
+          }
+          (*start, *end, Severity::Warning)
+        }
+        _ => (0, kw.len(), Severity::Error),
+      };
+
+      diagnostics.push(Diagnostic {
+        id: *id,
+        line: line.number,
+        start,
+        end,
+        severity,
+        message: issue.message(*kw, line.text),
+      });
+    }
+
+    for line in self.lines.iter() {
+      for (rule, start, end, message) in self.lints.check(&line) {
+        diagnostics.push(Diagnostic {
+          id: line.id,
+          line: line.number,
+          start,
+          end,
+          severity: Severity::Warning,
+          message: format!("{} [{}]", message, rule),
+        });
+      }
+
+      if line.keyword != Some(Keyword::Unknown) {
+        continue;
+      }
+      let name = String::from_utf8_lossy(Keyword::name(line.text));
+
+      diagnostics.push(Diagnostic {
+        id: line.id,
+        line: line.number,
+        start: 0,
+        end: Keyword::Unknown.len(),
+        severity: Severity::Info,
+        message: format!("Unknown card '{}', its lines are not checked", name),
+      });
+    }
+    diagnostics.sort_by_key(|d| (d.line, d.start));
+
+    diagnostics
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::bufdata::BufData;
+
+  fn diagnostics(bufdata: &BufData) -> Vec<String> {
+    bufdata
+      .diagnostics()
+      .iter()
+      .map(|d| d.to_string())
+      .collect()
+  }
+
+  const LINES: [&'static str; 6] = [
+    "NODE  /        1              0.             0.5              0.",
+    "#Comment",
+    "NODE  /        2              0.             abc              0.",
+    "OCTRL /  ",
+    "END_OCTRL",
+    "SHELL /       10       1       1       2       3       7     1.0 1.0.0",
+  ];
+
+  #[test]
+  fn diagnostics_invalid_cells() {
+    parse_bufdata!(bufdata, LINES);
+
+    assert_eq!(
+      diagnostics(&bufdata),
+      vec![
+        "3:33: error: Invalid value 'abc' in Node card",
+        "4:1: info: Unknown card 'OCTRL', its lines are not checked",
+        "6:65: error: Invalid value '1.0.0' in Shell card",
+      ]
+    );
+  }
+
+  #[test]
+  fn diagnostics_lints() {
+    let long = format!("{:80}x", LINES[0]);
+    let shell = format!(
+      "{:72}  x",
+      "SHELL /     3129       1    2967    2971    2970    2972"
+    );
+    let lines = [
+      long.as_ref(),
+      "#Comment with ä\t",
+      "GROUP / Gruppe ä",
+      shell.as_ref(),
+    ];
+    parse_bufdata!(bufdata, lines);
+
+    assert_eq!(
+      diagnostics(&bufdata),
+      vec![
+        "1:81: warning: Text after column 80 is ignored [long-line]",
+        "3:1: error: Group card is missing its GES",
+        "3:16: warning: Non-ASCII character shifts the columns after it \
+         [non-ascii]",
+        "4:75: warning: Text after the last cell of the line is ignored \
+         [trailing-text]",
+      ]
+    );
+
+    bufdata
+      .lints
+      .allow("long-line,non-ascii,trailing-text")
+      .unwrap();
+    assert_eq!(
+      diagnostics(&bufdata),
+      vec!["3:1: error: Group card is missing its GES"]
+    );
+  }
+
+  #[test]
+  fn diagnostics_misaligned() {
+    let lines = [
+      "SHELL /     3129       1       1    2967    2971    2970",
+      "SHELL /     3130       1    2967      12971 2970    2972",
+      "SHELL /     3131       1       12967    2971    2970    2972",
+    ];
+    // Moving '12967' in the last line would leave one of its cells blank, so
+    // no shift is suggested
+    parse_bufdata!(bufdata, lines);
+
+    assert_eq!(
+      diagnostics(&bufdata),
+      vec![
+        "2:39: warning: '12971' spans two cells of the Shell card, probably \
+         it needs to be moved 3 columns to the left: \
+         'SHELL /     3130       1    2967   12971    2970    2972'"
+      ]
+    );
+  }
+
+  #[test]
+  fn diagnostics_constrained_cells() {
+    let lines = [
+      "MASS  /        1       0",
+      "NAME mass",
+      "             -1.              1.              1.",
+      "                      0.              0.              0.",
+      "        NOD 1",
+      "        END",
+      "PLINK /        1       1       1       2       0",
+      "SIGNAL      YES",
+      "SIGNAL    MAYBE",
+      "SIGNAL     <var>",
+    ];
+    parse_bufdata!(bufdata, lines);
+
+    assert_eq!(
+      diagnostics(&bufdata),
+      vec![
+        "3:1: error: Invalid value '-1.' in Mass card",
+        "7:33: error: Invalid value '2' in Plink card",
+        "9:9: error: Invalid value 'MAYBE' in Signal card",
+      ]
+    );
+  }
+
+  #[test]
+  fn diagnostics_plink_ges() {
+    let lines = [
+      "PLINK /        1       1       1       0       0",
+      "PLINK /        2       1       1       1       0",
+      "PLINK /        3       1       1       1       0",
+      "        PART 45",
+      "        END",
+    ];
+    parse_bufdata!(bufdata, lines);
+
+    assert_eq!(
+      diagnostics(&bufdata),
+      vec!["2:1: error: Plink card is missing its GES"]
+    );
+  }
+
+  const INCOMPLETE: [&'static str; 16] = [
+    "MASS  /        1       0",
+    "NAME mass",
+    "              0.              0.              0.",
+    "                0.              0.              0.",
+    "GROUP / TitleOfTheGroup",
+    "        PART 14 ",
+    "OTMCO /        1",
+    "NAME otmco",
+    "PART  /        1   SOLID       1       0       0       0",
+    "NAME PART_1",
+    "                              ",
+    "                              ",
+    "                                        ",
+    "                                        ",
+    "$#      ",
+    "#Comment",
+  ];
+
+  #[test]
+  fn diagnostics_incomplete_cards() {
+    parse_bufdata!(bufdata, INCOMPLETE);
+
+    assert_eq!(
+      diagnostics(&bufdata),
+      vec![
+        "1:1: error: Mass card is missing its GES",
+        "5:1: error: GES in Group card is missing END",
+        "7:1: error: Otmco card is missing its END_OTMCO line",
+        "9:1: error: PartSolid card is missing its END_PART line",
+      ]
+    );
+
+    bufdata
+      .update(
+        13.into(),
+        14.into(),
+        vec![INCOMPLETE[13].to_string(), "END_PART".to_string()],
+      )
+      .unwrap();
+    bufdata
+      .update(
+        5.into(),
+        6.into(),
+        vec![INCOMPLETE[5].to_string(), "        END".to_string()],
+      )
+      .unwrap();
+
+    assert_eq!(
+      diagnostics(&bufdata),
+      vec![
+        "1:1: error: Mass card is missing its GES",
+        "8:1: error: Otmco card is missing its END_OTMCO line",
+      ]
+    );
+  }
+}
@@ -1,24 +1,71 @@
 //! Holds the `Folds` datastructure for the fold data associated with a buffer
-use std::collections::{btree_map::Entry, BTreeMap};
+use std::{cmp::Ordering, ops::Range};
 
 use failure::Error;
 use itertools::Itertools;
 use neovim_lib::Value;
 
-use crate::{card::keyword::Keyword, linenr::LineNr};
-
-/// Folds are saved as the **end-inclusive** interval [start, end] of line
-/// numbers, the corresponding [`Keyword`](::card::keyword::Keyword) and a
-/// `String` for usage in nvims foldtext.
+use crate::{
+  card::{keyword::Keyword, Card},
+  lineid::LineId,
+  linenr::LineNr,
+  lines::{Lines, ParsedLine},
+};
+
+/// Folds are saved as the **end-inclusive** interval [start, end] of the
+/// [`LineId`](crate::lineid::LineId)s of their first and last line, the
+/// corresponding [`Keyword`](::card::keyword::Keyword) and a `String` for
+/// usage in nvims foldtext. The `Vec` is ordered by the position of the lines
+/// in the buffer.
 ///
-/// TODO(KillTheMule): Check out other data structures for this, especially wrt
-/// usage in [`splice`](::bufdata::folds::Folds::splice)
+/// Since the folds don't hold line numbers, the ones after a change do not
+/// need to be shifted. Updates only touch the folds around the changed lines,
+/// see [`splice`](::bufdata::folds::Folds::splice) and
+/// [`splice_level2`](::bufdata::folds::Folds::splice_level2).
 #[derive(Default, Debug)]
-pub(super) struct Folds(BTreeMap<[LineNr; 2], (Keyword, String)>);
+pub(super) struct Folds(Vec<([LineId; 2], (Keyword, String))>);
+
+/// Collect the level 2 folds of the level 1 folds given, i.e. one fold for
+/// each run of at least 2 consecutive folds with the same keyword. Folds of
+/// [`Unknown`](crate::card::keyword::Keyword::Unknown) cards are left out,
+/// since consecutive unknown cards aren't related in general.
+fn level2_folds(
+  folds: &[([LineId; 2], (Keyword, String))],
+) -> Vec<([LineId; 2], (Keyword, String))> {
+  let mut level2 = vec![];
+  let grouped = folds.iter().group_by(|(_, (kw, _))| *kw);
+
+  for (kw, group) in &grouped {
+    if kw == Keyword::Unknown {
+      continue;
+    }
+
+    let mut group = group.enumerate();
+    let firstfold = group.next().expect("Empty group from group_by!").1;
+    let (nr, lastfold) = match group.last() {
+      None => continue, // only 1 fold in group
+      Some((i, e)) => (i, e),
+    };
+
+    level2.push((
+      [firstfold.0[0], lastfold.0[1]],
+      (kw, format!(" {} {:?}s ", nr + 1, kw)),
+    ));
+  }
+
+  level2
+}
+
+/// The foldtext for a level 1 fold of the card `kw` from `start` to `end`.
+fn foldtext(start: LineNr, end: LineNr, kw: Keyword) -> String {
+  // TODO: Maybe use a &'static str without #lines for cards with ownfold
+  // = true?
+  format!(" {} lines: {:?} ", end - start + 1, kw)
+}
 
 impl Folds {
   pub(super) fn new() -> Self {
-    Self(BTreeMap::new())
+    Self(Vec::new())
   }
 
   pub(super) fn clear(&mut self) {
@@ -27,7 +74,7 @@ impl Folds {
 
   pub(super) fn iter(
     &self,
-  ) -> impl Iterator<Item = (&[LineNr; 2], &(Keyword, String))> {
+  ) -> impl Iterator<Item = &([LineId; 2], (Keyword, String))> {
     self.0.iter()
   }
 
@@ -35,24 +82,23 @@ impl Folds {
     self.0.len()
   }
 
-  /// Insert a fold `([start, end], (Keyword, String))`.  Returns an error if
+  /// Append a fold `([start, end], (Keyword, String))`. Returns an error if
   /// that fold is already in the list.
   fn insert(
     &mut self,
-    start: LineNr,
-    end: LineNr,
+    start: &ParsedLine,
+    end: &ParsedLine,
     kw: Keyword,
   ) -> Result<(), Error> {
-    match self.0.entry([start, end]) {
-      Entry::Occupied(_) => {
-        return Err(failure::err_msg("Fold already in foldlist!"));
-      }
-      Entry::Vacant(entry) => {
-        // TODO: Maybe use a &'static str without #lines for cards with ownfold
-        // = true?
-        entry.insert((kw, format!(" {} lines: {:?} ", end - start + 1, kw)));
-      }
+    let range = [start.id, end.id];
+
+    if self.0.last().map_or(false, |(r, _)| *r == range) {
+      return Err(failure::err_msg("Fold already in foldlist!"));
     }
+
+    self
+      .0
+      .push((range, (kw, foldtext(start.number, end.number, kw))));
     Ok(())
   }
 
@@ -61,11 +107,11 @@ impl Folds {
   /// returns an error if the fold is already in the list.
   pub(super) fn checked_insert(
     &mut self,
-    start: LineNr,
-    end: LineNr,
+    start: &ParsedLine,
+    end: &ParsedLine,
     kw: Keyword,
   ) -> Result<(), Error> {
-    if start <= end {
+    if start.number <= end.number {
       self.insert(start, end, kw)
     } else {
       Err(failure::err_msg("Need start <= end to insert a fold!"))
@@ -75,11 +121,17 @@ impl Folds {
   // Copy the elements of a FoldList into a Vec, containing
   // the tuples (start, end, Keyword). Only needed for tests.
   #[cfg(test)]
-  pub fn to_vec(&self) -> Vec<(usize, usize, Keyword)> {
+  pub fn to_vec(&self, lines: &Lines) -> Vec<(usize, usize, Keyword)> {
     self
       .0
       .iter()
-      .map(|(r, (k, _))| (r[0].into(), r[1].into(), *k))
+      .map(|([s, e], (k, _))| {
+        (
+          lines.linenr(*s).unwrap().into(),
+          lines.linenr(*e).unwrap().into(),
+          *k,
+        )
+      })
       .collect()
   }
 
@@ -92,191 +144,223 @@ impl Folds {
       return Ok(());
     }
 
-    let grouped = folds.iter().group_by(|(_, &(kw, _))| kw);
-
-    for (kw, group) in &grouped {
-      let mut group = group.enumerate();
-      let firstfold = group.next().expect("Empty group from group_by!").1;
-      let (nr, lastfold) = match group.last() {
-        None => continue, // only 1 fold in group
-        Some((i, e)) => (i, e),
-      };
-
-      let firstline = firstfold.0[0];
-      let lastline = lastfold.0[1];
-
-      // TODO(KillTheMule): This is sort of redundant wrt checked_insert, but we
-      // want our own foldtext here.
-      if firstline < lastline {
-        match self.0.entry([firstline, lastline]) {
-          Entry::Occupied(_) => {
-            return Err(failure::err_msg("Fold already in foldlist_level2!"));
-          }
-          Entry::Vacant(entry) => {
-            entry.insert((kw, format!(" {} {:?}s ", nr + 1, kw)));
-          }
-        }
-      }
-    }
+    self.0 = level2_folds(&folds.0);
     Ok(())
   }
 
-  /// Splices a new set of folds, existing in the range `firstline..lastline` of
-  /// lines, into self. Needs the number of added lines to work. **NOTE**: This
-  /// has some assumptions on `firstline`/`lastline`, which I'm not entirely
-  /// sure of right now. In effect though, if we got `firstline` from a
-  /// [`LinesEvent`](::event::Event::LinesEvent), we need to call
-  /// [`last_before`](::bufdata::BufData::last_before) on it before passing it
-  /// here. Same for `lastline` and
-  /// [`first_after`](::bufdata::Bufdata::first_after).
+  /// Update the level 2 folds after the level 1 folds `folds` have been
+  /// [`splice`](::bufdata::folds::Folds::splice)d. Here, `range` is the range
+  /// of indices of the level 1 folds that have been replaced, and
+  /// `firstline..lastline` the range of lines given to `splice`.
   ///
-  /// Note: The major pain point here is fusing folds at the boundary. This will
-  /// stay somewhat complicated no matter what, but the code might be
-  /// complicated by our use of a `HashMap`.
-  /// TODO(KillTheMule): This needs to return a result, propagated from insert
-  pub(super) fn splice(
+  /// Only the level 2 folds touching the replaced level 1 folds are recreated,
+  /// i.e. the runs of folds with the same keyword reaching into `range`, or
+  /// directly neighbouring it.
+  pub(super) fn splice_level2(
     &mut self,
-    newfolds: Self,
+    folds: &Self,
+    range: Range<usize>,
     firstline: LineNr,
     lastline: LineNr,
-    added: isize,
+    lines: &Lines,
   ) {
-    let mut to_delete = vec![];
-    let mut to_split = vec![];
-    let mut last_before = None;
-    let mut first_after = None;
-
-    for (k, v) in &self.0 {
-      if k[0] < firstline {
-        last_before = Some((*k, v.0));
-      }
-      if lastline <= k[1] && first_after.is_none() {
-        first_after = Some((*k, v.0));
-      }
-
-      if firstline <= k[0] && k[0] < lastline {
-        if k[1] < lastline {
-          to_delete.push(*k);
-        } else {
-          to_split.push((*k, v.0));
-        }
-      } else if firstline <= k[1] && k[1] < lastline {
-        // from the if above, we can assume k[0] < firstline
-        to_split.push((*k, v.0));
-      } else if k[0] < firstline && lastline <= k[1] {
-        to_split.push((*k, v.0))
-      }
+    let kw = |i: usize| (folds.0[i].1).0;
+    let (mut start, mut end) = (range.start, range.end);
 
-      if lastline <= k[0] {
-        break;
+    if start > 0 {
+      start -= 1;
+      while start > 0 && kw(start - 1) == kw(start) {
+        start -= 1;
       }
     }
-
-    for k in to_delete {
-      self.0.remove(&k);
+    if end < folds.len() {
+      end += 1;
+      while end < folds.len() && kw(end) == kw(end - 1) {
+        end += 1;
+      }
     }
 
-    for (k, v) in to_split {
-      self.0.remove(&k);
-
-      if k[0] < firstline {
-        let _ = self.checked_insert(k[0], firstline.prev(), v);
-        last_before = Some(([k[0], firstline.prev()], v))
+    // The lines covered by the runs, and the ones that have been reparsed
+    let (mut first, mut last) = (firstline, lastline);
+    if start < end {
+      if let Some(l) = lines.linenr(folds.0[start].0[0]) {
+        first = first.min(l);
       }
-
-      if lastline <= k[1] {
-        let _ = self.checked_insert(lastline, k[1], v);
-        first_after = Some(([lastline, k[1]], v));
+      if let Some(l) = lines.linenr(folds.0[end - 1].0[1]) {
+        last = last.max(l + 1);
       }
     }
 
-    let first_new = match newfolds.0.iter().next() {
-      Some((k, v)) => Some((*k, v.0)),
-      None => None,
-    };
-    let mut merge_to_first = None;
-    let _ = last_before.map(|(k1, v1)| {
-      first_new.map(|(_, v2)| {
-        if v1 == v2 {
-          self.0.remove(&k1);
-          merge_to_first = last_before;
-        }
-      })
-    });
+    let oldrange = self.linerange(first, last, lines);
+    let _ = self.0.splice(oldrange, level2_folds(&folds.0[start..end]));
+  }
 
-    let last_new = match newfolds
+  /// Return the range of indices of the folds reaching into the lines
+  /// `firstline..lastline`. Folds of lines that are not part of `lines` anymore
+  /// are counted as being in that range.
+  fn linerange(
+    &self,
+    firstline: LineNr,
+    lastline: LineNr,
+    lines: &Lines,
+  ) -> Range<usize> {
+    // The first fold not ending before firstline
+    let start = self
       .0
-      .range([0_usize.into(), 0_usize.into()]..)
-      .next_back()
-    {
-      Some((k, v)) => Some((*k, v.0)),
-      None => None,
-    };
-    let mut merge_to_last = None;
-    let _ = first_after.map(|(k1, v1)| {
-      last_new.map(|(_, v2)| {
-        if v1 == v2 {
-          self.0.remove(&k1);
-          merge_to_last = first_after;
-        }
+      .binary_search_by(|([_, e], _)| match lines.linenr(*e) {
+        Some(e) if e < firstline => Ordering::Less,
+        _ => Ordering::Greater,
       })
-    });
-
-    let first_fold_to_move =
-      match self.0.range([lastline, 0_usize.into()]..).next() {
-        Some((i, k)) => Some((*i, k.0)),
-        None => None,
-      };
-
-    if let Some((f, _)) = first_fold_to_move {
-      let to_move = self.0.split_off(&f);
+      .unwrap_or_else(|e| e);
+    // The first fold starting at or after lastline
+    let end = start
+      + self.0[start..]
+        .binary_search_by(|([s, _], _)| match lines.linenr(*s) {
+          Some(s) if s >= lastline => Ordering::Greater,
+          _ => Ordering::Less,
+        })
+        .unwrap_or_else(|e| e);
+
+    start..end
+  }
 
-      for (k, v) in &to_move {
-        let _ = self.insert(k[0] + added, k[1] + added, v.0);
+  /// Splices a new set of folds, existing in the range `firstline..lastline` of
+  /// lines, into self. This needs to be called after `lines` have been
+  /// updated, so `firstline` and `lastline` refer to the new line numbers.
+  /// Folds of lines that have been removed are counted as being in that
+  /// range. In effect, if we got `firstline` from a
+  /// [`LinesEvent`](::event::Event::LinesEvent), we need to call
+  /// [`first_before`](::bufdata::BufData::first_before) on it before passing
+  /// it here. Same for `lastline` and
+  /// [`first_after`](::bufdata::Bufdata::first_after), which also needs to be
+  /// shifted by the number of added lines.
+  ///
+  /// The folds after the range refer to their lines by
+  /// [`LineId`](crate::lineid::LineId), so they do not need to be touched.
+  /// Folds reaching into the range are cut at its boundary, and the new folds
+  /// are fused with their neighbours if a fresh parse would have done so.
+  /// Returns the range of indices of the folds that replaced the old ones,
+  /// which includes the neighbours that have been touched.
+  ///
+  /// The new folds need to lie inside `firstline..lastline`, since they're the
+  /// result of reparsing those lines. In debug builds, this panics otherwise.
+  pub(super) fn splice(
+    &mut self,
+    newfolds: Self,
+    firstline: LineNr,
+    lastline: LineNr,
+    lines: &Lines,
+  ) -> Range<usize> {
+    debug_assert!(
+      newfolds.0.iter().all(|([s, e], _)| {
+        lines.linenr(*s).map_or(false, |s| s >= firstline)
+          && lines.linenr(*e).map_or(false, |e| e < lastline)
+      }),
+      "New folds outside of the reparsed lines {}..{}",
+      firstline,
+      lastline
+    );
+    let Range { start: i0, end: i1 } =
+      self.linerange(firstline, lastline, lines);
+    let (mut start, mut end) = (i0, i1);
+
+    // The part before firstline of a fold reaching into the range, or the
+    // last fold before the range
+    let mut before = None;
+    if i0 < i1 {
+      let ([s, _], (kw, _)) = &self.0[i0];
+      if lines.linenr(*s).map_or(false, |s| s < firstline) {
+        before = lines.last_line_before(firstline).map(|l| ([*s, l.id], *kw));
       }
     }
+    if before.is_none() && i0 > 0 {
+      start -= 1;
+      let (range, (kw, _)) = &self.0[start];
+      before = Some((*range, *kw));
+    }
 
-    let mut last_added = None;
-    for (k, v) in &newfolds.0 {
-      if let Some((k1, _)) = merge_to_first {
-        let _ = self.insert(k1[0], k[1], v.0);
-        last_added = Some([k1[0], k[1]]);
-        merge_to_first = None;
-      } else {
-        let _ = self.insert(k[0], k[1], v.0);
-        last_added = Some([k[0], k[1]]);
+    // The part from lastline on of a fold reaching into the range, or the
+    // first fold after the range
+    let mut after = None;
+    if i0 < i1 {
+      let ([_, e], (kw, _)) = &self.0[i1 - 1];
+      if lines.linenr(*e).map_or(false, |e| e >= lastline) {
+        after = lines.first_line_from(lastline).map(|l| ([l.id, *e], *kw));
       }
     }
+    if after.is_none() && i1 < self.0.len() {
+      let (range, (kw, _)) = &self.0[i1];
+      after = Some((*range, *kw));
+      end += 1;
+    }
+
+    // Whether a fresh parse would have gathered the fold ending on line `end`
+    // and the one starting on line `start` into one
+    let fuses = |end: LineId, start: LineId, kw: Keyword| {
+      !<&Card>::from(&kw).ownfold
+        && lines
+          .linenr(start)
+          .and_then(|s| lines.last_line_before(s))
+          .map(|l| l.id)
+          == Some(end)
+    };
 
-    if let Some(i) = last_added {
-      if let Some((k2, v2)) = merge_to_last {
-        self.0.remove(&i);
-        let _ = self.insert(i[0], k2[1] + added, v2);
+    let mut newfolds = newfolds.0.into_iter().map(|(r, (kw, _))| (r, kw));
+    let mut folds = Vec::with_capacity(newfolds.len() + 2);
+
+    match (before, newfolds.next()) {
+      (Some(([s, e], kw)), Some(([s2, e2], kw2)))
+        if kw == kw2 && fuses(e, s2, kw) =>
+      {
+        folds.push(([s, e2], kw))
       }
-    } else {
-      // last_added is `None`, so newfolds is empty. Check if we need to reunite
-      // last_before and first_after
-      if let Some((k1, v1)) = last_before {
-        if let Some((k2, v2)) = first_after {
-          if v1 == v2 {
-            self.0.remove(&k1);
-            self.0.remove(&k2);
-            let _ = self.insert(k1[0], k2[1] + added, v1);
-          }
+      (b, n) => {
+        folds.extend(b);
+        folds.extend(n);
+      }
+    }
+    folds.extend(newfolds);
+
+    // If there were no new folds, this might reunite the parts of the folds
+    // before and after the range
+    if let Some((range, kw)) = after {
+      match folds.last_mut() {
+        Some(([_, e], kw1)) if *kw1 == kw && fuses(*e, range[0], kw) => {
+          *e = range[1]
         }
+        _ => folds.push((range, kw)),
       }
     }
+
+    let folds: Vec<_> = folds
+      .into_iter()
+      .filter_map(|([s, e], kw)| {
+        let text = foldtext(lines.linenr(s)?, lines.linenr(e)?, kw);
+        Some(([s, e], (kw, text)))
+      })
+      .collect();
+    let num_new = folds.len();
+
+    let _ = self.0.splice(start..end, folds);
+
+    start..(start + num_new)
   }
 
-  pub(super) fn fold_calls(&self) -> Value {
+  /// Pack up the folds into a `Value` suitable to send to neovim, looking up
+  /// the line numbers in `lines`.
+  pub(super) fn fold_calls(&self, lines: &Lines) -> Value {
     let mut luaargs = vec![];
 
-    for (range, (_, text)) in self.iter() {
-      luaargs.push(Value::from(vec![
-        Value::from(range[0] + 1),
-        Value::from(range[1] + 1),
-        Value::from(text.to_string()),
-      ]));
+    for ([start, end], (_, text)) in self.iter() {
+      if let (Some(start), Some(end)) =
+        (lines.linenr(*start), lines.linenr(*end))
+      {
+        luaargs.push(Value::from(vec![
+          Value::from(start + 1),
+          Value::from(end + 1),
+          Value::from(text.to_string()),
+        ]));
+      }
     }
 
     Value::from(luaargs)
@@ -286,6 +370,7 @@ impl Folds {
 #[cfg(test)]
 macro_rules! splicetest {
   (
+    $(#[$attr: meta])*
     $fn: ident;
     existing: $([$s: expr, $e: expr, $t: expr]),+;
     new: $([$sn: expr, $en: expr, $tn: expr]),+;
@@ -293,37 +378,55 @@ macro_rules! splicetest {
     expected: $([$($g: expr),+]),+
   ) => {
     #[test]
+    $(#[$attr])*
     fn $fn() {
       use crate::bufdata::folds::Folds;
       use crate::card::keyword::Keyword::*;
-      use crate::linenr::LineNr;
+      use crate::{linenr::LineNr, lines::Lines};
+
+      let mut lines = Lines::new();
+      lines.parse_vec(vec!["x".to_string(); 30]);
 
       let mut oldfolds = Folds::new();
       $(let _ = oldfolds.insert(
-          LineNr::from_usize($s),
-          LineNr::from_usize($e),
+          &lines.get_line(LineNr::from_usize($s)).unwrap(),
+          &lines.get_line(LineNr::from_usize($e)).unwrap(),
           $t
           );
         )+
 
+      let mut level2 = Folds::new();
+      let _ = level2.recreate_level2(&oldfolds);
+
+      let newlen = ($last as isize - $first as isize + $added) as usize;
+      let _ = lines.update(
+        vec!["x".to_string(); newlen],
+        LineNr::from_usize($first),
+        LineNr::from_usize($last),
+        $added,
+      );
+
       let mut newfolds = Folds::new();
       $(let _ = newfolds.insert(
-          LineNr::from_usize($sn),
-          LineNr::from_usize($en),
+          &lines.get_line(LineNr::from_usize($sn)).unwrap(),
+          &lines.get_line(LineNr::from_usize($en)).unwrap(),
           $tn
           );
         )+
 
-      oldfolds.splice(
-        newfolds,
-        LineNr::from_usize($first),
-        LineNr::from_usize($last),
-        $added
-      );
+      let first = LineNr::from_usize($first);
+      let last = LineNr::from_usize(($last as isize + $added) as usize);
+      let range = oldfolds.splice(newfolds, first, last, &lines);
 
       let v = vec![$( ($($g),+ ),)+];
 
-      assert_eq!(v, oldfolds.to_vec());
+      assert_eq!(v, oldfolds.to_vec(&lines));
+
+      level2.splice_level2(&oldfolds, range, first, last, &lines);
+      let mut expected_level2 = Folds::new();
+      let _ = expected_level2.recreate_level2(&oldfolds);
+
+      assert_eq!(expected_level2.to_vec(&lines), level2.to_vec(&lines));
     }
   };
 }
@@ -513,6 +616,17 @@ mod tests {
   );
 
   splicetest!(splice_folds_inbetween;
+    existing: [0, 6, Node], [8, 10, Shell];
+    new: [2, 5, Node];
+    2, 2, 4;
+    expected: [0, 10, Node], [12, 14, Shell]
+  );
+
+  // New folds need to lie in the reparsed lines, here 2..6
+  splicetest!(
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "New folds outside of the reparsed lines 2..6")]
+    splice_folds_before_range;
     existing: [0, 6, Node], [8, 10, Shell];
     new: [0, 3, Node];
     2, 2, 4;
@@ -520,6 +634,18 @@ mod tests {
   );
 
   splicetest!(splice_folds_inbetween_functionaltest;
+    existing: [0, 3, Node], [5, 14, Shell], [17, 19, Node], [20, 23, Shell];
+    new: [6, 7, Node], [8, 9, Shell];
+    6, 10, 0;
+    expected: [0, 3, Node], [5, 5, Shell], [6, 7, Node], [8, 14, Shell],
+              [17, 19, Node], [20, 23, Shell]
+  );
+
+  // The reparsed lines are 6..10, so a fold can't start on line 10
+  splicetest!(
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "New folds outside of the reparsed lines 6..10")]
+    splice_folds_after_range;
     existing: [0, 3, Node], [5, 14, Shell], [17, 19, Node], [20, 23, Shell];
     new: [6, 7, Node], [10, 10, Shell];
     6, 10, 0;
@@ -548,4 +674,20 @@ mod tests {
     expected: [0, 2, Node], [3, 5, Shell], [6, 13, Node]
   );
 
+  splicetest!(splice_folds_join_level2;
+    existing: [0, 2, Rbody0], [3, 5, Rbody0], [6, 7, Shell], [8, 10, Rbody0],
+              [11, 13, Rbody0];
+    new: [6, 8, Rbody0];
+    6, 8, 1;
+    expected: [0, 2, Rbody0], [3, 5, Rbody0], [6, 8, Rbody0], [9, 11, Rbody0],
+              [12, 14, Rbody0]
+  );
+
+  splicetest!(splice_folds_split_level2;
+    existing: [0, 2, Rbody0], [3, 5, Rbody0], [6, 8, Rbody0], [9, 11, Rbody0];
+    new: [3, 5, Shell];
+    3, 6, 0;
+    expected: [0, 2, Rbody0], [3, 5, Shell], [6, 8, Rbody0], [9, 11, Rbody0]
+  );
+
 }
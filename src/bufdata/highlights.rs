@@ -1,15 +1,22 @@
 //! The highlight module
-use std::{self, cmp, convert::From, ops::Range};
+use std::{
+  self, cmp, cmp::Ordering, collections::HashMap, convert::From, ops::Range,
+};
 
 use neovim_lib::{neovim_api::Buffer, Value};
 
 use crate::{
   bufdata::highlights::HighlightGroup as Hl,
   card::{cell::Cell, line::Line as CardLine},
+  lineid::LineId,
   linenr::LineNr,
+  lines::{Lines, ParsedLine},
 };
 
-/// An enum to denote the nvim highlight groups within nvimpam
+/// An enum to denote the nvim highlight groups within nvimpam. Valid cells
+/// are highlighted according to their type, alternating between the `Even`
+/// and `Odd` variant. Blank cells get the plain `CellEven` and `CellOdd`
+/// groups.
 #[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
 pub enum HighlightGroup {
   CellEven,
@@ -17,6 +24,62 @@ pub enum HighlightGroup {
   ErrorCellEven,
   ErrorCellOdd,
   Keyword,
+  /// An integer on the line of the keyword, those are the ID of the entity
+  /// or IDs of other entities it refers to
+  IdEven,
+  IdOdd,
+  IntegerEven,
+  IntegerOdd,
+  FloatEven,
+  FloatOdd,
+  StrEven,
+  StrOdd,
+  BinaryEven,
+  BinaryOdd,
+  /// A [`FixedStr`](crate::card::cell::FixedStr) like `NAME` or `END_PART`
+  FixedEven,
+  FixedOdd,
+  /// The continuation character `&`
+  ContEven,
+  ContOdd,
+  /// The keyword of an entry in a
+  /// [GES](crate::card::ges::GesType)
+  GesKeyword,
+}
+
+impl HighlightGroup {
+  /// If the highlight marks a cell with invalid content
+  pub fn is_error(self) -> bool {
+    use self::HighlightGroup::*;
+
+    self == ErrorCellEven || self == ErrorCellOdd
+  }
+
+  /// The highlight group of a valid cell that is not a keyword. Integers are
+  /// highlighted as IDs if `idline` is true, unless their range is
+  /// constrained.
+  fn valid_cell(cell: &Cell, idline: bool, even: bool) -> Self {
+    use self::HighlightGroup::*;
+
+    let (e, o) = match cell {
+      Cell::Integer(_) | Cell::IntegerorBlank(_) if idline => (IdEven, IdOdd),
+      Cell::Integer(_) | Cell::IntegerorBlank(_) | Cell::IntegerRange(..) => {
+        (IntegerEven, IntegerOdd)
+      }
+      Cell::Float(_) | Cell::FloatRange(..) => (FloatEven, FloatOdd),
+      Cell::Str(_) | Cell::Choice(..) => (StrEven, StrOdd),
+      Cell::Binary(_) => (BinaryEven, BinaryOdd),
+      Cell::Fixed(_) => (FixedEven, FixedOdd),
+      Cell::Cont => (ContEven, ContOdd),
+      Cell::Kw(_) | Cell::Blank(_) => (CellEven, CellOdd),
+    };
+
+    if even {
+      e
+    } else {
+      o
+    }
+  }
 }
 
 impl From<HighlightGroup> for &'static str {
@@ -29,6 +92,21 @@ impl From<HighlightGroup> for &'static str {
       ErrorCellEven => "PamErrorCellEven",
       ErrorCellOdd => "PamErrorCellOdd",
       Keyword => "PamKeyword",
+      IdEven => "PamIdEven",
+      IdOdd => "PamIdOdd",
+      IntegerEven => "PamIntegerEven",
+      IntegerOdd => "PamIntegerOdd",
+      FloatEven => "PamFloatEven",
+      FloatOdd => "PamFloatOdd",
+      StrEven => "PamStrEven",
+      StrOdd => "PamStrOdd",
+      BinaryEven => "PamBinaryEven",
+      BinaryOdd => "PamBinaryOdd",
+      FixedEven => "PamFixedEven",
+      FixedOdd => "PamFixedOdd",
+      ContEven => "PamContEven",
+      ContOdd => "PamContOdd",
+      GesKeyword => "PamGesKeyword",
     }
   }
 }
@@ -36,7 +114,7 @@ impl From<HighlightGroup> for &'static str {
 /// The Iterator for a [`HlLine`](::bufdata::highlights::HlLine).
 #[derive(Debug)]
 struct HlIter<'a> {
-  num: LineNr,
+  id: LineId,
   cardline: &'a CardLine,
   text: &'a [u8],
   linelen: u8,
@@ -46,7 +124,7 @@ struct HlIter<'a> {
 }
 
 impl<'a> Iterator for HlIter<'a> {
-  type Item = ((LineNr, u8, u8), Hl);
+  type Item = ((LineId, u8, u8), Hl);
 
   fn next(&mut self) -> Option<Self::Item> {
     if self.until >= self.linelen {
@@ -66,7 +144,7 @@ impl<'a> Iterator for HlIter<'a> {
     self.odd = !odd;
 
     if let Cell::Kw(_) = cell {
-      Some(((self.num, range.start, range.end), Hl::Keyword))
+      Some(((self.id, range.start, range.end), Hl::Keyword))
     } else {
       match self
         .text
@@ -74,17 +152,15 @@ impl<'a> Iterator for HlIter<'a> {
         .map(|s| cell.verify(s))
       {
         Some(true) => {
-          if odd {
-            Some(((self.num, range.start, range.end), Hl::CellEven))
-          } else {
-            Some(((self.num, range.start, range.end), Hl::CellOdd))
-          }
+          let idline = self.cardline.keyword().is_some();
+          let hl = Hl::valid_cell(cell, idline, odd);
+          Some(((self.id, range.start, range.end), hl))
         }
         Some(false) => {
           if odd {
-            Some(((self.num, range.start, range.end), Hl::ErrorCellEven))
+            Some(((self.id, range.start, range.end), Hl::ErrorCellEven))
           } else {
-            Some(((self.num, range.start, range.end), Hl::ErrorCellOdd))
+            Some(((self.id, range.start, range.end), Hl::ErrorCellOdd))
           }
         }
         None => None,
@@ -93,12 +169,79 @@ impl<'a> Iterator for HlIter<'a> {
   }
 }
 
-/// The struct to hold the highlights for a buffer. The internal `Vec` needs to
-/// stay ordered on the first tuple.
+/// The struct to hold the highlights for a buffer. The lines are referred to
+/// by their [`LineId`](crate::lineid::LineId)s, so the highlights don't need
+/// to be changed when lines are added or removed before them. The internal
+/// `Vec` needs to stay ordered by the position of the lines, and the start
+/// column.
 ///
 /// TODO(KillTheMule): Don't expose the internal `Vec`
 #[derive(Default, Debug)]
-pub struct Highlights(pub Vec<((LineNr, u8, u8), Hl)>);
+pub struct Highlights(pub Vec<((LineId, u8, u8), Hl)>);
+
+/// A highlight of the columns `(start, end)` of the line with the given ID.
+type HlEntry = ((LineId, u8, u8), Hl);
+
+/// Keeps track of the lines whose highlights have been sent to neovim, by
+/// their [`LineId`](crate::lineid::LineId). For those, we know which
+/// highlights neovim shows, so we do not need to send them again unless they
+/// change.
+#[derive(Default, Debug)]
+pub(super) struct SentLines(Vec<bool>);
+
+impl SentLines {
+  pub(super) fn new() -> Self {
+    SentLines(Vec::new())
+  }
+
+  pub(super) fn clear(&mut self) {
+    self.0.clear()
+  }
+
+  pub(super) fn contains(&self, id: LineId) -> bool {
+    self.0.get(usize::from(id)).cloned().unwrap_or(false)
+  }
+
+  pub(super) fn insert(&mut self, id: LineId) {
+    let idx = usize::from(id);
+    if idx >= self.0.len() {
+      self.0.resize(idx + 1, false);
+    }
+    self.0[idx] = true;
+  }
+}
+
+/// Return the index of the first highlight whose line is not before `line`.
+/// Highlights of lines that are not part of `lines` anymore are counted as
+/// being at `line`.
+fn first_not_before(
+  hls: &[((LineId, u8, u8), Hl)],
+  line: LineNr,
+  lines: &Lines,
+) -> usize {
+  hls
+    .binary_search_by(|((id, _, _), _)| match lines.linenr(*id) {
+      Some(l) if l < line => Ordering::Less,
+      _ => Ordering::Greater,
+    })
+    .unwrap_or_else(|e| e)
+}
+
+/// Return the index of the first highlight whose line is not before `line`.
+/// Highlights of lines that are not part of `lines` anymore are counted as
+/// being before `line`.
+fn first_at_or_after(
+  hls: &[((LineId, u8, u8), Hl)],
+  line: LineNr,
+  lines: &Lines,
+) -> usize {
+  hls
+    .binary_search_by(|((id, _, _), _)| match lines.linenr(*id) {
+      Some(l) if l >= line => Ordering::Greater,
+      _ => Ordering::Less,
+    })
+    .unwrap_or_else(|e| e)
+}
 
 impl Highlights {
   pub(super) fn clear(&mut self) {
@@ -109,46 +252,111 @@ impl Highlights {
     Self(Vec::new())
   }
 
-  pub fn iter(&self) -> impl Iterator<Item = &((LineNr, u8, u8), Hl)> {
+  pub fn iter(&self) -> impl Iterator<Item = &((LineId, u8, u8), Hl)> {
     self.0.iter()
   }
 
-  /// Remove all the highlights with linenumbers in `firstline..lastline`, and
-  /// paste in the ones given in `newhls`. Keeps the `Vec` ordered. Returns the
-  /// range of indices with new highlight entries (note that all the elements
-  /// above that range have been modified, as their line numbers had to be
-  /// shifted).
+  /// After `lines` have been updated, remove all the highlights of lines in
+  /// `firstline..lastline`, as well as those of lines that have been removed,
+  /// and paste in the ones given in `newhls`. Keeps the `Vec` ordered. Returns
+  /// the range of indices with new highlight entries, and the highlights that
+  /// have been removed. The highlights after that range are left alone, since
+  /// their line IDs did not change.
   pub fn splice(
     &mut self,
     newhls: Self,
     firstline: LineNr,
     lastline: LineNr,
-    added: isize,
-  ) -> Range<usize> {
-    let start = self
-      .0
-      .binary_search_by_key(&(firstline, 0), |&((l, s, _), _)| (l, s))
-      // error contains index where ele could be inserted preserving Order
-      .unwrap_or_else(|e| e);
-    let end = self.0[start..]
-      .iter()
-      .enumerate()
-      .find(|(_, ((l, _, _), _))| *l >= lastline)
-      .map_or_else(|| self.0.len(), |(i, ((_, _, _), _))| i + start);
+    lines: &Lines,
+  ) -> (Range<usize>, Vec<HlEntry>) {
+    let start = first_not_before(&self.0, firstline, lines);
+    let end = start + first_at_or_after(&self.0[start..], lastline, lines);
 
     let num_new = newhls.0.len();
-    let _ = self.0.splice(
-      start..end,
-      newhls.0.into_iter().map(|((l, s, e), h)| ((l, s, e), h)),
-    );
+    let old = self.0.splice(start..end, newhls.0).collect();
+
+    (start..(start + num_new), old)
+  }
 
-    if added != 0 {
-      for t in self.0[start + num_new..].iter_mut() {
-        ((*t).0).0 += added;
+  /// After a [`splice`](crate::bufdata::highlights::Highlights::splice),
+  /// return the ranges of lines whose highlights need to be sent to neovim.
+  /// `newrange` and `old` are the values returned by `splice`, and `region`
+  /// are the lines that have been reparsed. Those are
+  ///
+  ///   * the lines in `changed`, which neovim reported as changed,
+  ///   * the lines whose highlights differ from the ones `old` that have been
+  ///     sent to neovim before, or that have not been sent at all.
+  ///
+  /// All lines of `region` are marked as sent in `sent`.
+  pub(super) fn changed_lines<'a, I>(
+    &self,
+    newrange: Range<usize>,
+    old: &[HlEntry],
+    region: I,
+    changed: Range<LineNr>,
+    sent: &mut SentLines,
+  ) -> Vec<Range<LineNr>>
+  where
+    I: Iterator<Item = ParsedLine<'a>>,
+  {
+    // The highlights of a line are contiguous in the `Vec`
+    let mut oldhls: HashMap<LineId, &[HlEntry]> = HashMap::new();
+    let mut i = 0;
+    while i < old.len() {
+      let id = (old[i].0).0;
+      let mut j = i + 1;
+      while j < old.len() && (old[j].0).0 == id {
+        j += 1;
       }
+      let _ = oldhls.insert(id, &old[i..j]);
+      i = j;
     }
 
-    start..(start + num_new)
+    let same = |a: &[HlEntry], b: &[HlEntry]| {
+      a.len() == b.len()
+        && a.iter().zip(b.iter()).all(
+          |(((_, s1, e1), h1), ((_, s2, e2), h2))| (s1, e1, h1) == (s2, e2, h2),
+        )
+    };
+
+    let new = &self.0[newrange];
+    let mut idx = 0;
+    let mut ranges: Vec<Range<LineNr>> = vec![];
+
+    if changed.start < changed.end {
+      ranges.push(changed.clone());
+    }
+
+    for line in region {
+      let start = idx;
+      while idx < new.len() && (new[idx].0).0 == line.id {
+        idx += 1;
+      }
+
+      let outside = line.number < changed.start || line.number >= changed.end;
+      let uptodate = sent.contains(line.id)
+        && same(
+          oldhls.get(&line.id).cloned().unwrap_or(&[]),
+          &new[start..idx],
+        );
+
+      if outside && !uptodate {
+        ranges.push(line.number..line.number + 1);
+      }
+      sent.insert(line.id);
+    }
+
+    // Merge adjacent ranges
+    ranges.sort_by_key(|r| r.start);
+    let mut merged: Vec<Range<LineNr>> = Vec::with_capacity(ranges.len());
+    for r in ranges {
+      match merged.last_mut() {
+        Some(m) if m.end >= r.start => m.end = cmp::max(m.end, r.end),
+        _ => merged.push(r),
+      }
+    }
+
+    merged
   }
 
   /// Add the highlights of a line by pushing them to the end of the `Vec`. Be
@@ -156,7 +364,7 @@ impl Highlights {
   #[inline]
   pub fn add_line_highlights(
     &mut self,
-    num: LineNr,
+    id: LineId,
     text: &[u8],
     cardline: &CardLine,
   ) {
@@ -165,7 +373,7 @@ impl Highlights {
     let linelen = cmp::min(text.len(), 81) as u8;
     let cells = cardline.cells().unwrap_or(&[]).iter();
     let it = HlIter {
-      num,
+      id,
       cardline,
       text,
       linelen,
@@ -176,23 +384,34 @@ impl Highlights {
     self.0.extend(it);
   }
 
-  /// Return an iterator over the highlights of the lines with linenumber in the
-  /// range `firstline..lastline`.
+  /// Add the highlight of the keyword of a line in a
+  /// [GES](crate::card::ges::GesType), i.e. of the word after the 8 leading
+  /// blanks.
+  pub fn add_ges_highlights(&mut self, id: LineId, text: &[u8]) {
+    // We only highlight until column 81
+    #![allow(clippy::cast_possible_truncation)]
+    let end = text
+      .iter()
+      .skip(8)
+      .position(|&b| b == b' ')
+      .map_or(text.len(), |p| p + 8);
+    let end = cmp::min(end, 81);
+
+    if end > 8 {
+      self.0.push(((id, 8, end as u8), Hl::GesKeyword));
+    }
+  }
+
+  /// Return the range of indices of the highlights of the lines with
+  /// linenumber in the range `firstline..lastline`.
   pub(super) fn linerange(
     &self,
     firstline: LineNr,
     lastline: LineNr,
+    lines: &Lines,
   ) -> Range<usize> {
-    let start = self
-      .0
-      .binary_search_by_key(&(firstline, 0), |&((l, s, _), _)| (l, s))
-      // error contains index where ele could be inserted preserving Order
-      .unwrap_or_else(|e| e);
-    let end = self.0[start..]
-      .iter()
-      .enumerate()
-      .find(|(_, ((l, _, _), _))| *l >= lastline)
-      .map_or_else(|| self.0.len(), |(i, ((_, _, _), _))| i + start);
+    let start = first_not_before(&self.0, firstline, lines);
+    let end = start + first_at_or_after(&self.0[start..], lastline, lines);
 
     start..end
   }
@@ -200,59 +419,116 @@ impl Highlights {
   /// Construct the necessary calls to neovim to highlight the region given by
   /// `firstline..lastline`. Here, `indexrange` gives the index of the
   /// highlights to send. All existing highlights in this linerange are cleare
-  /// beforehand.
+  /// beforehand. The highlights are placed as extmarks in the namespace `ns`.
   ///
   /// TODO(KillTheMule): efficient? maybe not send strings for the hl groups,
   /// but small ints, and have a mapping ready?
   pub(super) fn highlight_region_calls(
     &self,
     buf: &Buffer,
+    ns: i64,
     indexrange: Range<usize>,
     firstline: LineNr,
     lastline: LineNr,
+    lines: &Lines,
   ) -> Option<Vec<Value>> {
     if indexrange.start == indexrange.end && firstline == lastline {
       return None;
     }
 
     let mut calls: Vec<Value> = vec![];
+    self.push_region_calls(
+      &mut calls,
+      buf,
+      ns,
+      indexrange,
+      firstline..lastline,
+      lines,
+    );
+
+    Some(calls)
+  }
+
+  /// Construct the necessary calls to neovim to highlight the lines in the
+  /// given ranges. The existing highlights of those lines are cleared
+  /// beforehand. The highlights are placed as extmarks in the namespace `ns`.
+  pub(super) fn highlight_lines_calls(
+    &self,
+    buf: &Buffer,
+    ns: i64,
+    ranges: &[Range<LineNr>],
+    lines: &Lines,
+  ) -> Option<Vec<Value>> {
+    if ranges.is_empty() {
+      return None;
+    }
+
+    let mut calls: Vec<Value> = vec![];
+
+    for range in ranges {
+      let indexrange = self.linerange(range.start, range.end, lines);
+      let linerange = range.clone();
+      self.push_region_calls(&mut calls, buf, ns, indexrange, linerange, lines);
+    }
+
+    Some(calls)
+  }
 
+  /// Push the calls to clear the highlights of the lines in `linerange`, and
+  /// to add the highlights with indices in `indexrange`, to `calls`.
+  ///
+  /// The highlights are extmarks, so neovim moves them along when lines are
+  /// added or removed before them. They're placed non-strictly, since the
+  /// cells of a card may extend beyond the end of a line.
+  fn push_region_calls(
+    &self,
+    calls: &mut Vec<Value>,
+    buf: &Buffer,
+    ns: i64,
+    indexrange: Range<usize>,
+    linerange: Range<LineNr>,
+    lines: &Lines,
+  ) {
     calls.push(
       vec![
-        Value::from("nvim_buf_clear_highlight".to_string()),
+        Value::from("nvim_buf_clear_namespace".to_string()),
         vec![
           buf.get_value().clone(),
-          Value::from(5),
-          Value::from(firstline),
-          Value::from(lastline),
+          Value::from(ns),
+          Value::from(linerange.start),
+          Value::from(linerange.end),
         ]
         .into(),
       ]
       .into(),
     );
 
-    calls.extend(self.0[indexrange].iter().map(|((l, s, e), t)| {
+    calls.extend(self.0[indexrange].iter().filter_map(|((id, s, e), t)| {
+      let l = lines.linenr(*id)?;
       let st: &'static str = (*t).into();
-      vec![
-        Value::from("nvim_buf_add_highlight".to_string()),
+      Some(
         vec![
-          buf.get_value().clone(),
-          Value::from(5),
-          Value::from(st.to_string()),
-          Value::from(*l),
-          Value::from(u64::from(*s)),
-          Value::from(u64::from(*e)),
+          Value::from("nvim_buf_set_extmark".to_string()),
+          vec![
+            buf.get_value().clone(),
+            Value::from(ns),
+            Value::from(l),
+            Value::from(u64::from(*s)),
+            Value::Map(vec![
+              (Value::from("end_col"), Value::from(u64::from(*e))),
+              (Value::from("hl_group"), Value::from(st.to_string())),
+              (Value::from("strict"), Value::from(false)),
+            ]),
+          ]
+          .into(),
         ]
         .into(),
-      ]
-      .into()
+      )
     }));
-
-    Some(calls)
   }
 
   #[cfg(test)]
-  pub fn add_highlight(&mut self, line: LineNr, start: u8, end: u8, hl: Hl) {
+  pub fn add_highlight(&mut self, line: LineId, start: u8, end: u8, hl: Hl) {
     self.0.push(((line, start, end), hl))
   }
 }
@@ -268,18 +544,36 @@ macro_rules! splicetest {
   ) => {
     #[test]
     fn $fn() {
-      use crate::linenr::LineNr;
+      use crate::{linenr::LineNr, lines::Lines};
+
+      let id = |lines: &Lines, l: usize| {
+        lines.get_line(LineNr::from_usize(l)).unwrap().id
+      };
+
+      let mut lines = Lines::new();
+      lines.parse_vec(vec!["x".to_string(); 10]);
 
       let mut h = Highlights::new();
-      $(let _ = h.add_highlight(LineNr::from_usize($l), $($e),+);)+
+      $(let _ = h.add_highlight(id(&lines, $l), $($e),+);)+
+
+      let newlen = ($last as isize - $first as isize + $added) as usize;
+      let _ = lines.update(
+        vec!["x".to_string(); newlen],
+        LineNr::from_usize($first),
+        LineNr::from_usize($last),
+        $added,
+      );
 
       let mut h1 = Highlights::new();
-      $(let _ = h1.add_highlight(LineNr::from_usize($ll), $($f),+);)+
+      $(let _ = h1.add_highlight(id(&lines, $ll), $($f),+);)+
 
-      h.splice(h1, LineNr::from_usize($first), LineNr::from_usize($last), $added);
+      let newlast = ($last as isize + $added) as usize;
+      h.splice(h1, LineNr::from_usize($first), LineNr::from_usize(newlast), &lines);
       let v = vec![$( (LineNr::from_usize($lll), $($g),+ ),)+];
 
-      let w:Vec<_> = h.0.iter().map(|((l, s, e), h)| (*l, *s, *e, *h)).collect();
+      let w:Vec<_> = h.0.iter()
+        .map(|((l, s, e), h)| (lines.linenr(*l).unwrap(), *s, *e, *h))
+        .collect();
       assert_eq!(v, w);
     }
   };
@@ -287,7 +581,10 @@ macro_rules! splicetest {
 
 #[cfg(test)]
 mod tests {
-  use crate::bufdata::highlights::{HighlightGroup::*, Highlights};
+  use crate::{
+    bufdata::highlights::{HighlightGroup::*, Highlights},
+    carddata::node::MASS,
+  };
 
   // adding 3 lines before the buffer
   splicetest!(hl_splice_before;
@@ -508,4 +805,35 @@ mod tests {
     assert_eq!(v, w);
   }
 
+  #[test]
+  fn hl_cell_types() {
+    let mut h = Highlights::new();
+
+    let first =
+      b"MASS  /        1       0          1.0e-3 1.0e-3            x.0";
+    let third = format!("{:>24}{:>57}", "1.", "&");
+    h.add_line_highlights(0.into(), first, &MASS.lines[0]);
+    h.add_line_highlights(1.into(), b"NAME Mass", &MASS.lines[1]);
+    h.add_line_highlights(2.into(), third.as_bytes(), &MASS.lines[3]);
+
+    let w: Vec<_> = h.0.iter().map(|((l, s, e), h)| (*l, *s, *e, *h)).collect();
+    let v = vec![
+      (0.into(), 0, 8, Keyword),
+      (0.into(), 8, 16, IdEven),
+      (0.into(), 16, 24, IdOdd),
+      (0.into(), 24, 40, FloatEven),
+      (0.into(), 40, 56, FloatOdd),
+      (0.into(), 56, 62, ErrorCellEven),
+      (1.into(), 0, 4, FixedOdd),
+      (1.into(), 4, 9, StrEven),
+      (2.into(), 0, 8, CellOdd),
+      (2.into(), 8, 24, FloatEven),
+      (2.into(), 24, 40, FloatOdd),
+      (2.into(), 40, 56, FloatEven),
+      (2.into(), 56, 80, CellOdd),
+      (2.into(), 80, 81, ContEven),
+    ];
+
+    assert_eq!(v, w);
+  }
 }
@@ -0,0 +1,112 @@
+//! Dump the results of parsing a buffer as JSON, e.g. to compare them against
+//! golden files in tests, or to use them in other tools.
+use serde_json::{json, Value};
+
+use crate::bufdata::BufData;
+
+impl<'a> BufData<'a> {
+  /// Collect the detected keywords, the level 1 and level 2 folds and the
+  /// highlights of the buffer into a JSON object of the form
+  ///
+  /// ```text
+  /// {
+  ///   "keywords": [{ "line": 0, "keyword": "Node" }, ...],
+  ///   "folds": [
+  ///     { "level": 1, "start": 0, "end": 2, "keyword": "Node", "text": ... },
+  ///     ...
+  ///   ],
+  ///   "highlights": [
+  ///     { "line": 0, "start": 0, "end": 8, "group": "PamKeyword" }, ...
+  ///   ]
+  /// }
+  /// ```
+  ///
+  /// Line numbers are zero-indexed, folds are end-inclusive and highlights
+  /// end-exclusive.
+  pub fn to_json(&self) -> Value {
+    let keywords: Vec<_> = self
+      .lines
+      .iter()
+      .filter_map(|l| l.keyword.map(|kw| (l.number, kw)))
+      .map(|(line, kw)| {
+        json!({ "line": usize::from(line), "keyword": format!("{:?}", kw) })
+      })
+      .collect();
+
+    let level1 = self.folds.iter().map(|f| (1, f));
+    let level2 = self.folds_level2.iter().map(|f| (2, f));
+    let folds: Vec<_> = level1
+      .chain(level2)
+      .filter_map(|(level, ([start, end], (kw, text)))| {
+        let start = self.lines.linenr(*start)?;
+        let end = self.lines.linenr(*end)?;
+        Some(json!({
+          "level": level,
+          "start": usize::from(start),
+          "end": usize::from(end),
+          "keyword": format!("{:?}", kw),
+          "text": text,
+        }))
+      })
+      .collect();
+
+    let highlights: Vec<_> = self
+      .highlights
+      .iter()
+      .filter_map(|((id, start, end), hl)| {
+        let line = self.lines.linenr(*id)?;
+        Some(json!({
+          "line": usize::from(line),
+          "start": start,
+          "end": end,
+          "group": <&'static str>::from(*hl),
+        }))
+      })
+      .collect();
+
+    json!({
+      "keywords": keywords,
+      "folds": folds,
+      "highlights": highlights,
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use serde_json::json;
+
+  const LINES: [&'static str; 3] = [
+    "NODE  /        1              0.             0.5              0.",
+    "#Comment",
+    "NODE  /        2              0.             abc              0.",
+  ];
+
+  #[test]
+  fn json_dump() {
+    parse_bufdata!(bufdata, LINES);
+
+    let dump = bufdata.to_json();
+
+    assert_eq!(
+      dump["keywords"],
+      json!([
+        { "line": 0, "keyword": "Node" },
+        { "line": 2, "keyword": "Node" },
+      ])
+    );
+    assert_eq!(dump["folds"].as_array().unwrap().len(), 1);
+    assert_eq!(dump["folds"][0]["level"], 1);
+    assert_eq!(dump["folds"][0]["start"], 0);
+    assert_eq!(dump["folds"][0]["end"], 2);
+    assert_eq!(
+      dump["highlights"][0],
+      json!({ "line": 0, "start": 0, "end": 8, "group": "PamKeyword" })
+    );
+    assert!(dump["highlights"]
+      .as_array()
+      .unwrap()
+      .iter()
+      .any(|h| h["line"] == 2 && h["group"] == "PamErrorCellEven"));
+  }
+}
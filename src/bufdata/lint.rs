@@ -0,0 +1,232 @@
+//! Line-level lint rules. Most only look at the text of a
+//! [`ParsedLine`](crate::lines::ParsedLine), not at the card it belongs to,
+//! and catch what makes Pamcrash silently misread a line. The exception is
+//! [`TrailingText`](crate::bufdata::lint::Rule::TrailingText), which needs
+//! the layout of the line, so it's found while parsing the cards and only
+//! turned on or off here.
+use std::{cmp, fmt, str::FromStr};
+
+use failure::{self, Error};
+
+use crate::lines::ParsedLine;
+
+/// The number of columns Pamcrash reads of a line
+const COLUMNS: usize = 80;
+
+/// The line-level lint rules
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Rule {
+  /// Text after column 80, except a continuation character `&` in column 81
+  LongLine,
+  /// Tab characters, which break the column alignment of the cells
+  Tab,
+  /// Non-ASCII characters, which take more than one byte and thereby shift the
+  /// cells after them
+  NonAscii,
+  /// Text after the last cell of a line of a card, but before column 80
+  TrailingText,
+}
+
+impl Rule {
+  /// All rules, in the order they are checked
+  pub const ALL: [Rule; 4] = [
+    Rule::LongLine,
+    Rule::Tab,
+    Rule::NonAscii,
+    Rule::TrailingText,
+  ];
+
+  /// The name of the rule, used to turn it off, e.g. in `nvimpam check
+  /// --allow=long-line FILE`
+  pub fn name(self) -> &'static str {
+    match self {
+      Rule::LongLine => "long-line",
+      Rule::Tab => "tab",
+      Rule::NonAscii => "non-ascii",
+      Rule::TrailingText => "trailing-text",
+    }
+  }
+
+  /// The message for a violation of the rule
+  pub fn message(self) -> &'static str {
+    match self {
+      Rule::LongLine => "Text after column 80 is ignored",
+      Rule::Tab => "Tab character breaks the column alignment",
+      Rule::NonAscii => "Non-ASCII character shifts the columns after it",
+      Rule::TrailingText => "Text after the last cell of the line is ignored",
+    }
+  }
+
+  /// Find the columns `start..end` violating the rule in `text`, if any. Only
+  /// the first occurence is reported. `TrailingText` can't be checked without
+  /// the card, so it's never found here.
+  fn check(self, text: &[u8]) -> Option<(usize, usize)> {
+    match self {
+      Rule::LongLine => {
+        let start = if text.get(COLUMNS) == Some(&b'&') {
+          COLUMNS + 1
+        } else {
+          COLUMNS
+        };
+        let rest = text.get(start..)?;
+        let first = rest.iter().position(|b| !b.is_ascii_whitespace())?;
+        Some((start + first, text.len()))
+      }
+      Rule::Tab => {
+        let start = text.iter().position(|b| *b == b'\t')?;
+        Some((start, start + 1))
+      }
+      Rule::NonAscii => {
+        let start = text.iter().position(|b| !b.is_ascii())?;
+        let len = text[start..].iter().take_while(|b| !b.is_ascii()).count();
+        Some((start, start + len))
+      }
+      Rule::TrailingText => None,
+    }
+  }
+}
+
+impl fmt::Display for Rule {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}", self.name())
+  }
+}
+
+impl FromStr for Rule {
+  type Err = Error;
+
+  fn from_str(s: &str) -> Result<Self, Error> {
+    Rule::ALL
+      .iter()
+      .find(|r| r.name() == s)
+      .cloned()
+      .ok_or_else(|| failure::err_msg(format!("Unknown lint rule '{}'", s)))
+  }
+}
+
+/// The lint rules that are turned on. By default, that's all of them.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Lints {
+  pub long_line: bool,
+  pub tab: bool,
+  pub non_ascii: bool,
+  pub trailing_text: bool,
+}
+
+impl Default for Lints {
+  fn default() -> Self {
+    Lints {
+      long_line: true,
+      tab: true,
+      non_ascii: true,
+      trailing_text: true,
+    }
+  }
+}
+
+impl Lints {
+  /// Whether `rule` is turned on
+  pub fn enabled(&self, rule: Rule) -> bool {
+    match rule {
+      Rule::LongLine => self.long_line,
+      Rule::Tab => self.tab,
+      Rule::NonAscii => self.non_ascii,
+      Rule::TrailingText => self.trailing_text,
+    }
+  }
+
+  /// Turn `rule` on or off
+  pub fn set(&mut self, rule: Rule, on: bool) {
+    match rule {
+      Rule::LongLine => self.long_line = on,
+      Rule::Tab => self.tab = on,
+      Rule::NonAscii => self.non_ascii = on,
+      Rule::TrailingText => self.trailing_text = on,
+    }
+  }
+
+  /// Turn off the rules named in the comma-separated list `names`, see
+  /// [`Rule::name`](crate::bufdata::lint::Rule::name).
+  pub fn allow(&mut self, names: &str) -> Result<(), Error> {
+    for name in names.split(',').filter(|n| !n.is_empty()) {
+      self.set(name.parse()?, false);
+    }
+
+    Ok(())
+  }
+
+  /// Check `line` against all rules that are turned on. Returns the rule, the
+  /// columns `start..end` violating it and a message for each violation.
+  pub fn check<'a>(
+    &'a self,
+    line: &'a ParsedLine<'a>,
+  ) -> impl Iterator<Item = (Rule, u8, u8, &'static str)> + 'a {
+    // Diagnostics can't point after column 255
+    let col = |c: usize| cmp::min(c, 255) as u8;
+
+    Rule::ALL
+      .iter()
+      .filter(move |r| self.enabled(**r))
+      .filter_map(move |r| {
+        let (start, end) = r.check(line.text)?;
+        Some((*r, col(start), col(end), r.message()))
+      })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{
+    bufdata::lint::{Lints, Rule},
+    lineid::LineId,
+    lines::ParsedLine,
+  };
+
+  fn check(lints: &Lints, text: &str) -> Vec<(Rule, u8, u8)> {
+    let line = ParsedLine {
+      id: LineId::from_usize(0),
+      number: 0.into(),
+      text: text.as_ref(),
+      keyword: None,
+    };
+
+    lints.check(&line).map(|(r, s, e, _)| (r, s, e)).collect()
+  }
+
+  #[test]
+  fn lints_lines() {
+    let lints = Lints::default();
+    let cont = format!("{:>24}{:>57}", "1.", "&");
+    let long = format!("{:>24}{:>59}", "1.", "& x");
+
+    assert_eq!(check(&lints, "        0.      1."), vec![]);
+    assert_eq!(check(&lints, &cont), vec![]);
+    assert_eq!(check(&lints, &format!("{:80}   ", "")), vec![]);
+    assert_eq!(check(&lints, &long), vec![(Rule::LongLine, 82, 83)]);
+    assert_eq!(check(&lints, "   \t 0.  1.\t"), vec![(Rule::Tab, 3, 4)]);
+    assert_eq!(check(&lints, "NAME Füße"), vec![(Rule::NonAscii, 6, 10)]);
+  }
+
+  #[test]
+  fn lints_can_be_turned_off() {
+    let mut lints = Lints::default();
+    let text = format!("\t{:>80}ä", "x");
+
+    assert_eq!(
+      check(&lints, &text),
+      vec![
+        (Rule::LongLine, 80, 83),
+        (Rule::Tab, 0, 1),
+        (Rule::NonAscii, 81, 83)
+      ]
+    );
+
+    lints.allow("tab,non-ascii").unwrap();
+    assert_eq!(check(&lints, &text), vec![(Rule::LongLine, 80, 83)]);
+
+    lints.set(Rule::LongLine, false);
+    assert_eq!(check(&lints, &text), vec![]);
+
+    assert!(lints.allow("tabs").is_err());
+  }
+}
@@ -1,8 +1,14 @@
 //! This module provides the [`BufData`](crate::bufdata::BufData) struct to
 //! manage the lines, folds and highlights in a buffer.
 
+pub mod diagnostics;
 pub mod folds;
 pub mod highlights;
+pub mod json;
+pub mod lint;
+pub mod stats;
+pub mod structure;
+pub mod vtk;
 
 use std::ops::Range;
 
@@ -11,15 +17,19 @@ use failure::Error;
 use neovim_lib::{neovim_api::Buffer, Value};
 
 use crate::{
-  bufdata::{folds::Folds, highlights::Highlights},
+  bufdata::{
+    folds::Folds,
+    highlights::{Highlights, SentLines},
+    lint::Lints,
+    structure::Structure,
+  },
+  card::keyword::Keyword,
+  lineid::LineId,
   linenr::LineNr,
   lines::{Lines, ParsedLine},
   linesiter::LinesIter,
 };
 
-#[cfg(test)]
-use crate::card::keyword::Keyword;
-
 macro_rules! unwrap_or_ok {
   ($option:expr) => {
     match $option {
@@ -35,10 +45,29 @@ macro_rules! unwrap_or_ok {
   };
 }
 
+/// How the `changedtick` of an update relates to the one of the last update
+/// applied to a [`BufData`](crate::bufdata::BufData).
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Tick {
+  /// The update is already part of the contents read at the last resync
+  Stale,
+  /// The update is newer than the last one. Neovim can increase the
+  /// `changedtick` by more than 1, e.g. when writing the buffer, so that's
+  /// not a sign of a missed update.
+  Next,
+  /// The update is older than the last one, but newer than the last resync,
+  /// so it arrived out of order
+  Behind,
+}
+
 /// The datastructure to hold all the information of a buffer.
 pub struct BufData<'a> {
   /// The buffer the plugin is attached to
-  pub buf: &'a Buffer,
+  pub buf: Buffer,
+  /// The `changedtick` of the last update applied. 0 if unknown.
+  pub changedtick: u64,
+  /// The `changedtick` of the contents read at the last resync. 0 if unknown.
+  pub synctick: u64,
   /// The lines of the buffer
   lines: Lines<'a>,
   /// The level 1 folds.
@@ -47,25 +76,70 @@ pub struct BufData<'a> {
   folds_level2: Folds,
   /// The highlights of the buffer
   pub highlights: Highlights,
+  /// The cards that fall short of their definition
+  structure: Structure,
+  /// The line-level lint rules turned on for the
+  /// [`diagnostics`](crate::bufdata::BufData::diagnostics)
+  pub lints: Lints,
+  /// The lines whose highlights have been sent to neovim
+  sent: SentLines,
 }
 
 impl<'a> BufData<'a> {
   /// Create a new BufData instance for a given buffer.
-  pub fn new(buf: &'a Buffer) -> Self {
+  pub fn new(buf: &Buffer) -> Self {
     BufData {
-      buf,
+      buf: buf.clone(),
+      changedtick: 0,
+      synctick: 0,
       lines: Lines::new(),
       folds: Folds::new(),
       folds_level2: Folds::new(),
       highlights: Highlights::new(),
+      structure: Structure::new(),
+      lints: Lints::default(),
+      sent: SentLines::new(),
     }
   }
 
+  /// Classify the `changedtick` of an update, see
+  /// [`Tick`](crate::bufdata::Tick). If no `changedtick` is known yet, any
+  /// update is accepted.
+  pub fn check_tick(&self, changedtick: u64) -> Tick {
+    if self.changedtick == 0 || changedtick > self.changedtick {
+      Tick::Next
+    } else if changedtick <= self.synctick {
+      Tick::Stale
+    } else {
+      Tick::Behind
+    }
+  }
+
+  /// Record that the contents have been read as of `changedtick`, so updates
+  /// up to it are already contained.
+  pub fn synced(&mut self, changedtick: u64) {
+    self.changedtick = changedtick;
+    self.synctick = changedtick;
+  }
+
+  /// The number of lines of the buffer
+  pub fn numlines(&self) -> usize {
+    self.lines.numlines()
+  }
+
+  /// Pack a value up with the `changedtick` it was computed for, so the
+  /// receiver can discard it if the buffer changed in the meantime.
+  pub fn with_tick<V: Into<Value>>(&self, v: V) -> Value {
+    Value::from(vec![Value::from(self.changedtick), v.into()])
+  }
+
   pub fn clear(&mut self) {
     self.lines.clear();
     self.folds.clear();
     self.folds_level2.clear();
     self.highlights.clear();
+    self.structure.clear();
+    self.sent.clear();
   }
 
   /// Extend the lines of the buffer by splitting the slice on newlines. Parse
@@ -109,6 +183,7 @@ impl<'a> BufData<'a> {
     self.folds.clear();
     self.folds_level2.clear();
     self.highlights.clear();
+    self.structure.clear();
 
     self.parse_lines()?;
     self.folds_level2.recreate_level2(&self.folds)?;
@@ -117,17 +192,20 @@ impl<'a> BufData<'a> {
   }
 
   /// Update the `BufData` structure from the lines of a `Vec<String>`. Tries to
-  /// be as efficient as possible. Returns the range of indices with new
-  /// highlights. This is usefull to call
-  /// [`highlight_region_calls`](crate::bufdata::BufData::
-  /// highlight_region_calls) afterwards.
+  /// be as efficient as possible. Returns the ranges of lines whose highlights
+  /// need to be sent to neovim, see
+  /// [`changed_lines`](crate::bufdata::highlights::Highlights::changed_lines).
+  /// This is usefull to call
+  /// [`highlight_lines_calls`](crate::bufdata::BufData::highlight_lines_calls)
+  /// afterwards.
   pub fn update(
     &mut self,
     firstline: LineNr,
     lastline: LineNr,
     linedata: Vec<String>,
-  ) -> Result<(Range<usize>, isize), Error> {
-    let added: isize = linedata.len() as isize - (lastline - firstline);
+  ) -> Result<Vec<Range<LineNr>>, Error> {
+    let numnew = linedata.len();
+    let added: isize = numnew as isize - (lastline - firstline);
     let mut first_pre = self.lines.first_before(firstline);
     let last_pre = self.lines.first_after(lastline);
 
@@ -152,13 +230,47 @@ impl<'a> BufData<'a> {
 
     let mut newhls = Highlights::new();
     let mut newfolds = Folds::new();
-
-    let li = LinesIter::new(self.lines[first_post..last_post].iter());
-
-    BufData::parse_from_iter(&mut newhls, &mut newfolds, li)?;
-    self.folds.splice(newfolds, first_pre.1, last_pre.1, added);
-    self.folds_level2.recreate_level2(&self.folds)?;
-    Ok((self.highlights.splice(newhls, firstline, lastline, added), added))
+    let mut newstructure = Structure::new();
+
+    let li = self.lines.iter_range(first_post..last_post);
+
+    BufData::parse_from_iter(
+      &mut newhls,
+      &mut newfolds,
+      &mut newstructure,
+      li,
+    )?;
+
+    // The reparsed region in the new line numbers
+    let first_new = first_pre.1;
+    let last_new = last_pre.1 + added;
+
+    let foldrange =
+      self
+        .folds
+        .splice(newfolds, first_new, last_new, &self.lines);
+    self.folds_level2.splice_level2(
+      &self.folds,
+      foldrange,
+      first_new,
+      last_new,
+      &self.lines,
+    );
+    self
+      .structure
+      .splice(newstructure, first_new, last_new, &self.lines);
+    let (hlrange, oldhls) =
+      self
+        .highlights
+        .splice(newhls, first_new, last_new, &self.lines);
+
+    Ok(self.highlights.changed_lines(
+      hlrange,
+      &oldhls,
+      self.lines.iter_range(first_post..last_post),
+      firstline..firstline + LineNr::from_usize(numnew),
+      &mut self.sent,
+    ))
   }
 
   /// After initializing the lines and keywords of a `BufData` structure, this
@@ -169,18 +281,24 @@ impl<'a> BufData<'a> {
   pub fn parse_lines(&mut self) -> Result<(), Error> {
     let li = self.lines.iter();
 
-    BufData::parse_from_iter(&mut self.highlights, &mut self.folds, li)
+    BufData::parse_from_iter(
+      &mut self.highlights,
+      &mut self.folds,
+      &mut self.structure,
+      li,
+    )
   }
 
   /// Iterate over a [`LinesIter`](::linesiter::LinesIter) and add
-  /// the highlights and folds to the given structures.
+  /// the highlights, folds and card issues to the given structures.
   fn parse_from_iter<'b, I>(
     highlights: &mut Highlights,
     folds: &mut Folds,
+    structure: &mut Structure,
     mut li: LinesIter<'b, I>,
   ) -> Result<(), Error>
   where
-    I: Iterator<Item = &'b ParsedLine<'b>>,
+    I: Iterator<Item = ParsedLine<'b>>,
   {
     let mut foldstart;
     let mut foldend;
@@ -191,13 +309,13 @@ impl<'a> BufData<'a> {
 
     loop {
       foldkw = nextline.keyword;
-      foldstart = nextline.number;
-      skipped = li.skip_fold(&nextline, highlights);
+      foldstart = ParsedLine::from(&nextline);
+      skipped = li.skip_fold(&nextline, highlights, structure);
 
       // The latter only happens when a file ends after the only line of a card
       foldend = skipped.skip_end;
 
-      folds.checked_insert(foldstart, foldend, foldkw)?;
+      folds.checked_insert(&foldstart, &foldend, foldkw)?;
 
       if let Some(Some(kl)) =
         skipped.nextline.map(ParsedLine::try_into_keywordline)
@@ -209,8 +327,14 @@ impl<'a> BufData<'a> {
     }
   }
 
+  /// Return the number of the line with the given
+  /// [`LineId`](crate::lineid::LineId), if it is still part of the buffer.
+  pub fn linenr(&self, id: LineId) -> Option<LineNr> {
+    self.lines.linenr(id)
+  }
+
   pub fn hl_linerange(&self, first: LineNr, last: LineNr) -> Range<usize> {
-    self.highlights.linerange(first, last)
+    self.highlights.linerange(first, last, &self.lines)
   }
 
   pub fn first_before(&self, line: LineNr) -> (usize, LineNr) {
@@ -224,34 +348,284 @@ impl<'a> BufData<'a> {
   /// Construct the necessary calls to neovim to highlight the region given by
   /// `firstline..lastline`. Here, `indexrange` gives the index of the
   /// highlights to send. All existing highlights in this linerange are cleare
-  /// beforehand.
+  /// beforehand. The highlights are placed in the namespace `ns`, and the
+  /// lines are marked as sent.
   pub fn highlight_region_calls(
     &mut self,
+    ns: i64,
     indexrange: Range<usize>,
     firstline: LineNr,
     lastline: LineNr,
+  ) -> Option<Vec<Value>> {
+    for line in self.lines.iter_lines(firstline..lastline) {
+      self.sent.insert(line.id);
+    }
+
+    self.highlights.highlight_region_calls(
+      &self.buf,
+      ns,
+      indexrange,
+      firstline,
+      lastline,
+      &self.lines,
+    )
+  }
+
+  /// Construct the necessary calls to neovim to highlight the lines in the
+  /// given ranges, e.g. the ones returned by
+  /// [`update`](crate::bufdata::BufData::update). The existing highlights of
+  /// those lines are cleared beforehand, the new ones are placed in the
+  /// namespace `ns`.
+  pub fn highlight_lines_calls(
+    &self,
+    ns: i64,
+    ranges: &[Range<LineNr>],
   ) -> Option<Vec<Value>> {
     self
       .highlights
-      .highlight_region_calls(&self.buf, indexrange, firstline, lastline)
+      .highlight_lines_calls(&self.buf, ns, ranges, &self.lines)
   }
 
   /// Pack up all existing level 1 and level 2 folds (in that order) into a
   /// `Value` suitable to send to neovim.
   pub fn fold_calls(&self) -> Value {
     Value::from(vec![
-      self.folds.fold_calls(),
-      self.folds_level2.fold_calls(),
+      self.folds.fold_calls(&self.lines),
+      self.folds_level2.fold_calls(&self.lines),
     ])
   }
 
+  /// Iterate over all existing level 1 and level 2 folds (in that order) as
+  /// end-inclusive ranges of line numbers together with their
+  /// [`Keyword`](crate::card::keyword::Keyword).
+  pub fn fold_ranges(
+    &self,
+  ) -> impl Iterator<Item = (LineNr, LineNr, Keyword)> + '_ {
+    self
+      .folds
+      .iter()
+      .chain(self.folds_level2.iter())
+      .filter_map(move |([start, end], (kw, _))| {
+        Some((self.lines.linenr(*start)?, self.lines.linenr(*end)?, *kw))
+      })
+  }
+
   #[cfg(test)]
   pub fn folds_to_vec(&self) -> Vec<(usize, usize, Keyword)> {
-    self.folds.to_vec()
+    self.folds.to_vec(&self.lines)
   }
 
   #[cfg(test)]
   pub fn folds_level2_to_vec(&self) -> Vec<(usize, usize, Keyword)> {
-    self.folds_level2.to_vec()
+    self.folds_level2.to_vec(&self.lines)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use neovim_lib::{neovim_api::Buffer, Value};
+
+  use std::cmp;
+
+  use crate::{
+    bufdata::{BufData, Tick},
+    linenr::LineNr,
+  };
+
+  #[test]
+  fn changedticks() {
+    let buf = Buffer::new(Value::from(0_usize));
+    let mut bufdata = BufData::new(&buf);
+
+    assert_eq!(Tick::Next, bufdata.check_tick(17));
+
+    bufdata.synced(5);
+    assert_eq!(Tick::Stale, bufdata.check_tick(4));
+    assert_eq!(Tick::Stale, bufdata.check_tick(5));
+    assert_eq!(Tick::Next, bufdata.check_tick(6));
+
+    // A jump, e.g. from writing the buffer, is no reason to resync
+    assert_eq!(Tick::Next, bufdata.check_tick(8));
+    bufdata.changedtick = 8;
+    assert_eq!(Tick::Next, bufdata.check_tick(9));
+    assert_eq!(Tick::Behind, bufdata.check_tick(7));
+    assert_eq!(Tick::Stale, bufdata.check_tick(5));
+  }
+
+  #[test]
+  fn highlights_all_card_lines() {
+    use crate::bufdata::highlights::HighlightGroup;
+
+    parse_bufdata!(
+      bufdata,
+      [
+        "MASS  /        0       0",
+        "$#                                                                         TITLE",
+        "NAME MASS  / ->1",
+        "                                                        ",
+        "                                                                                &",
+        "                     1.",
+        "        PART 1234",
+        "        END",
+      ]
+    );
+
+    let mut lines: Vec<usize> = bufdata
+      .highlights
+      .iter()
+      .map(|((id, _, _), _)| bufdata.linenr(*id).unwrap().into())
+      .collect();
+    lines.dedup();
+    assert_eq!(lines, vec![0, 2, 3, 4, 5, 6, 7]);
+
+    let ges: Vec<_> = bufdata
+      .highlights
+      .iter()
+      .filter(|(_, hl)| *hl == HighlightGroup::GesKeyword)
+      .map(|((_, s, e), _)| (*s, *e))
+      .collect();
+    assert_eq!(ges, vec![(8, 12), (8, 11)]);
+  }
+
+  /// Apply the calls to neovim to a model of the highlights neovim shows, one
+  /// `Vec` of `(start, end, group)` per line.
+  fn apply_calls(shown: &mut [Vec<(u64, u64, String)>], calls: Vec<Value>) {
+    for call in calls {
+      let call = call.as_array().unwrap();
+      let args = call[1].as_array().unwrap();
+      let arg = |i: usize| args[i].as_u64().unwrap();
+
+      match call[0].as_str().unwrap() {
+        "nvim_buf_clear_namespace" => {
+          let last = cmp::min(arg(3) as usize, shown.len());
+          for line in &mut shown[arg(2) as usize..last] {
+            line.clear();
+          }
+        }
+        "nvim_buf_set_extmark" => {
+          let opts = args[4].as_map().unwrap();
+          let opt = |name: &str| {
+            &opts.iter().find(|o| o.0.as_str() == Some(name)).unwrap().1
+          };
+          let end = opt("end_col").as_u64().unwrap();
+          let group = opt("hl_group").as_str().unwrap().to_string();
+          shown[arg(2) as usize].push((arg(3), end, group));
+        }
+        c => panic!("Unexpected call {}", c),
+      }
+    }
+  }
+
+  #[test]
+  fn update_sends_changed_highlights() {
+    let file = std::fs::read_to_string("files/example.pc").unwrap();
+    let mut model: Vec<String> =
+      file.lines().take(2000).map(String::from).collect();
+
+    let buf = Buffer::new(Value::from(0_usize));
+    let mut bufdata = BufData::new(&buf);
+    bufdata.parse_vec(model.clone()).unwrap();
+
+    let mut shown = vec![vec![]; model.len()];
+    let numhls = bufdata.highlights.iter().count();
+    let calls = bufdata
+      .highlight_region_calls(1, 0..numhls, 0.into(), model.len().into())
+      .unwrap();
+    apply_calls(&mut shown, calls);
+
+    for &(first, last, from, len) in [
+      (765, 766, 765, 1),
+      (100, 101, 101, 1),
+      (760, 762, 30, 3),
+      (800, 850, 0, 0),
+      (28, 28, 758, 20),
+      (1900, 1905, 770, 10),
+    ]
+    .iter()
+    {
+      let newlines = model[from..from + len].to_vec();
+      let _ = model.splice(first..last, newlines.iter().cloned());
+
+      // Neovim keeps the extmarks of the lines changed in place, the ones of
+      // removed lines shrink to nothing
+      let inplace = cmp::min(last - first, len);
+      let _ = shown.splice(first + inplace..last, vec![vec![]; len - inplace]);
+
+      let changed =
+        bufdata.update(first.into(), last.into(), newlines).unwrap();
+      if first == 765 {
+        // Only the changed line is sent, not the whole card
+        assert_eq!(changed, vec![LineNr::from_usize(765)..766.into()]);
+      }
+      if let Some(calls) = bufdata.highlight_lines_calls(1, &changed) {
+        apply_calls(&mut shown, calls);
+      }
+
+      let mut expected = vec![vec![]; model.len()];
+      for ((id, s, e), hl) in bufdata.highlights.iter() {
+        let group: &'static str = (*hl).into();
+        expected[usize::from(bufdata.linenr(*id).unwrap())].push((
+          u64::from(*s),
+          u64::from(*e),
+          group.to_string(),
+        ));
+      }
+      for line in shown.iter_mut() {
+        line.sort();
+      }
+      assert_eq!(shown, expected, "Update {}..{}", first, last);
+    }
+  }
+
+  #[test]
+  fn update_equals_reparse() {
+    let file = std::fs::read_to_string("files/example.pc").unwrap();
+    let mut model: Vec<String> = file.lines().map(String::from).collect();
+
+    let buf = Buffer::new(Value::from(0_usize));
+    let mut bufdata = BufData::new(&buf);
+    bufdata.parse_vec(model.clone()).unwrap();
+
+    // Move some blocks of lines around, so cards get split, merged, or
+    // replaced by others
+    for (n, &(first, last, from, len)) in [
+      (28, 29, 40, 1),
+      (100, 100, 3000, 25),
+      (2000, 2100, 0, 0),
+      (500, 520, 10000, 7),
+      (20400, 20403, 17, 60),
+      (0, 5, 15000, 2),
+    ]
+    .iter()
+    .enumerate()
+    {
+      let newlines = model[from..from + len].to_vec();
+      let _ = model.splice(first..last, newlines.iter().cloned());
+      bufdata.update(first.into(), last.into(), newlines).unwrap();
+
+      let mut expected = BufData::new(&buf);
+      expected.parse_vec(model.clone()).unwrap();
+
+      let hls = |b: &BufData| -> Vec<_> {
+        b.highlights
+          .iter()
+          .map(|((id, s, e), h)| (b.linenr(*id), *s, *e, *h))
+          .collect()
+      };
+
+      assert_eq!(
+        bufdata.folds_to_vec(),
+        expected.folds_to_vec(),
+        "Update {}",
+        n
+      );
+      assert_eq!(
+        bufdata.folds_level2_to_vec(),
+        expected.folds_level2_to_vec(),
+        "Update {}",
+        n
+      );
+      assert!(hls(&bufdata) == hls(&expected), "Update {}", n);
+    }
   }
 }
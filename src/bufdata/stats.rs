@@ -0,0 +1,233 @@
+//! Aggregated statistics of a buffer, e.g. to put into the review notes of a
+//! model release.
+use std::{collections::BTreeMap, fmt};
+
+use neovim_lib::Value;
+
+use crate::{
+  bufdata::BufData,
+  card::{
+    cell::{read_integer, Cell},
+    keyword::Keyword,
+    Card,
+  },
+};
+
+/// The entity types we collect ID ranges for
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub enum Entity {
+  Node,
+  Element,
+  Part,
+  Constraint,
+}
+
+impl Entity {
+  /// Return the entity type a card of the given
+  /// [`Keyword`](crate::card::keyword::Keyword) defines, if any. Cards that
+  /// have no ID of their own (e.g. `MASS`, which references a node) return
+  /// `None`.
+  pub fn from_keyword(kw: Keyword) -> Option<Self> {
+    use self::Keyword::*;
+
+    match kw {
+      Node | Cnode => Some(Entity::Node),
+      Solid | Hexa20 | Pent15 | Penta6 | Tetr10 | Tetr4 | Bshel | Tshel
+      | Shell | Shel6 | Shel8 | Membr | Beam | Sprgbm | Bar | Spring
+      | Joint | Kjoin | Mtojnt | Sphel | Sphelo | Gap | Impma | Elink
+      | Llink | Slink | Plink | Tied => Some(Entity::Element),
+      PartSolid | PartBshel | PartTetra | PartSphel | PartCos3d | PartTshel
+      | PartShell | PartMembr | PartBar | PartBeam | PartSpring
+      | PartSprgbm | PartMbspr | PartJoint | PartKjoin | PartMbkjn
+      | PartMtojnt | PartTied | PartSlink | PartElink | PartLlink
+      | PartPlink | PartGap => Some(Entity::Part),
+      Mtoco | Otmco | Rbody0 | Rbody1 | Rbody2 | Rbody3 => {
+        Some(Entity::Constraint)
+      }
+      Mass | Nsmas | Nsmas2 | Signal | Group | Unknown => None,
+    }
+  }
+}
+
+/// The statistics of a buffer, see [`BufData::stats`](BufData::stats).
+#[derive(Debug, Default, PartialEq)]
+pub struct Stats {
+  /// The total number of lines
+  pub lines: usize,
+  /// The number of comment lines
+  pub comments: usize,
+  /// The number of cells that did not verify
+  pub invalid_cells: usize,
+  /// The number of cards per keyword, in order of first occurence
+  pub cards: Vec<(Keyword, usize)>,
+  /// The smallest and largest ID per entity type
+  pub id_ranges: BTreeMap<Entity, (usize, usize)>,
+  /// The number of elements per part ID
+  pub elements_per_part: BTreeMap<usize, usize>,
+}
+
+impl Stats {
+  /// The number of nodes, i.e. `NODE` and `CNODE` cards
+  pub fn nodes(&self) -> usize {
+    self
+      .cards
+      .iter()
+      .filter(|(kw, _)| *kw == Keyword::Node || *kw == Keyword::Cnode)
+      .map(|(_, n)| n)
+      .sum()
+  }
+
+  fn add_card(&mut self, kw: Keyword) {
+    match self.cards.iter_mut().find(|(k, _)| *k == kw) {
+      Some((_, n)) => *n += 1,
+      None => self.cards.push((kw, 1)),
+    }
+  }
+
+  fn add_id(&mut self, entity: Entity, id: usize) {
+    let range = self.id_ranges.entry(entity).or_insert((id, id));
+    if id < range.0 {
+      range.0 = id;
+    }
+    if id > range.1 {
+      range.1 = id;
+    }
+  }
+}
+
+impl<'a> BufData<'a> {
+  /// Collect the [`Stats`](crate::bufdata::stats::Stats) of the buffer. IDs
+  /// are read from the first integer cell after the keyword, part IDs of
+  /// elements from the second one.
+  pub fn stats(&self) -> Stats {
+    let mut stats = Stats {
+      lines: self.lines.numlines(),
+      comments: self.lines.numcomments(),
+      ..Default::default()
+    };
+
+    stats.invalid_cells =
+      self.highlights.iter().filter(|(_, h)| h.is_error()).count();
+
+    for line in self.lines.iter() {
+      let kw = match line.keyword {
+        Some(kw) => kw,
+        None => continue,
+      };
+      stats.add_card(kw);
+
+      let entity = match Entity::from_keyword(kw) {
+        Some(e) => e,
+        None => continue,
+      };
+
+      let card: &Card = (&kw).into();
+      let mut cells = card.lines[0]
+        .cells_with_text(line.text.as_ref())
+        .skip(1)
+        .map(|(c, s)| match c {
+          Cell::Integer(_) => read_integer(s),
+          _ => None,
+        });
+
+      if let Some(Some(id)) = cells.next() {
+        stats.add_id(entity, id);
+      }
+
+      if entity == Entity::Element {
+        if let Some(Some(part)) = cells.next() {
+          *stats.elements_per_part.entry(part).or_insert(0) += 1;
+        }
+      }
+    }
+
+    stats
+  }
+}
+
+impl fmt::Display for Stats {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    writeln!(f, "Lines: {}", self.lines)?;
+    writeln!(f, "Comment lines: {}", self.comments)?;
+    writeln!(f, "Nodes: {}", self.nodes())?;
+    writeln!(f, "Invalid cells: {}", self.invalid_cells)?;
+
+    writeln!(f, "Cards per keyword:")?;
+    for (kw, n) in &self.cards {
+      writeln!(f, "  {:<12}{:>10}", format!("{:?}", kw), n)?;
+    }
+
+    writeln!(f, "ID ranges:")?;
+    for (entity, (min, max)) in &self.id_ranges {
+      writeln!(f, "  {:<12}{:>10} - {}", format!("{:?}", entity), min, max)?;
+    }
+
+    writeln!(f, "Elements per part:")?;
+    for (part, n) in &self.elements_per_part {
+      writeln!(f, "  {:<12}{:>10}", part, n)?;
+    }
+
+    Ok(())
+  }
+}
+
+/// The report is sent as a list of lines, so it can be put into a buffer
+/// directly.
+impl<'a> From<&'a Stats> for Value {
+  fn from(s: &'a Stats) -> Self {
+    Value::from(
+      s.to_string()
+        .lines()
+        .map(Value::from)
+        .collect::<Vec<Value>>(),
+    )
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{bufdata::stats::Entity, card::keyword::Keyword::*};
+
+  const LINES: [&'static str; 15] = [
+    "$ A small deck",
+    "NODE  /        1              0.             0.5              0.",
+    "NODE  /        7              0.             0.5              0.",
+    "NODE  /        3              0.             0.5              0.",
+    "#Comment",
+    "SHELL /       10       1       1       2       3       7",
+    "SHELL /       11       1       1       2       3       7",
+    "SHELL /       12       2       1       2       3       7",
+    "PART  /        2   SHELL       1       0       0       0",
+    "NAME PART_2",
+    "                 x",
+    "                              ",
+    "                              ",
+    "                                        ",
+    "END_PART",
+  ];
+
+  #[test]
+  fn stats_small_deck() {
+    parse_bufdata!(bufdata, LINES);
+
+    let stats = bufdata.stats();
+
+    assert_eq!(stats.lines, 15);
+    assert_eq!(stats.comments, 2);
+    assert_eq!(stats.nodes(), 3);
+    assert_eq!(stats.invalid_cells, 1);
+    assert_eq!(stats.cards, vec![(Node, 3), (Shell, 3), (PartShell, 1)]);
+    assert_eq!(
+      stats.id_ranges.into_iter().collect::<Vec<_>>(),
+      vec![
+        (Entity::Node, (1, 7)),
+        (Entity::Element, (10, 12)),
+        (Entity::Part, (2, 2))
+      ]
+    );
+    assert_eq!(
+      stats.elements_per_part.into_iter().collect::<Vec<_>>(),
+      vec![(1, 2), (2, 1)]
+    );
+  }
+}
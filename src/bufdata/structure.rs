@@ -0,0 +1,176 @@
+//! Holds the `Structure` datastructure, recording the cards of a buffer that
+//! did not match their [`Card`](crate::card::Card) definition, and the lines
+//! of those whose values are not aligned to their cells or that have text
+//! after their last cell.
+use std::{cmp::Ordering, ops::Range};
+
+use crate::{
+  bufdata::lint::Rule,
+  card::{
+    cell::Cell,
+    keyword::Keyword,
+    line::{shift_value, Line as CardLine},
+    Card,
+  },
+  lineid::LineId,
+  linenr::LineNr,
+  lines::Lines,
+};
+
+/// The ways a card can fall short of its definition, see
+/// [`skip_card`](crate::linesiter::LinesIter::skip_card).
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Issue {
+  /// The card ended before the mandatory line with the given (0-based) index
+  /// into [`Card.lines`](crate::card::Card::lines)
+  MissingLine(usize),
+  /// A GES was not ended by a line consisting of `        END`
+  UnterminatedGes,
+  /// A block was not ended by a line starting with the given string
+  UnterminatedBlock(&'static [u8]),
+  /// The value in the columns `start..end` of a line straddles the boundary
+  /// of two cells, and probably needs to be moved by `shift` columns, see
+  /// [`misaligned`](crate::card::line::Line::misaligned).
+  Misaligned { start: u8, end: u8, shift: i8 },
+  /// Non-blank text in the columns `start..end` of a line, after its last
+  /// cell, see [`trailing`](crate::card::line::Line::trailing). Reported by
+  /// the [`TrailingText`](crate::bufdata::lint::Rule::TrailingText) lint.
+  TrailingText { start: u8, end: u8 },
+}
+
+impl Issue {
+  /// A message describing the issue for a card with the given keyword. Missing
+  /// lines are named if they're a GES, a block, or start with a fixed string.
+  /// For a misaligned value, `text` is the line it's in, and the message
+  /// contains the corrected line.
+  pub fn message(self, kw: Keyword, text: &[u8]) -> String {
+    match self {
+      Issue::MissingLine(i) => {
+        let card: &Card = (&kw).into();
+
+        match card.lines.get(i) {
+          Some(CardLine::Ges(_)) | Some(CardLine::OptionalGes(_, _)) => {
+            format!("{:?} card is missing its GES", kw)
+          }
+          Some(CardLine::Block(_, end)) => format!(
+            "{:?} card is missing its {} line",
+            kw,
+            String::from_utf8_lossy(end)
+          ),
+          Some(l) => match l.cells().and_then(|c| c.first()) {
+            Some(Cell::Fixed(f)) => format!(
+              "{:?} card is missing its {} line",
+              kw,
+              <&'static str>::from(*f)
+            ),
+            _ => format!(
+              "Incomplete {:?} card, it ends before line {} of its definition",
+              kw,
+              i + 1
+            ),
+          },
+          None => format!("Incomplete {:?} card", kw),
+        }
+      }
+      Issue::UnterminatedGes => format!("GES in {:?} card is missing END", kw),
+      Issue::UnterminatedBlock(end) => format!(
+        "{:?} card is missing its {} line",
+        kw,
+        String::from_utf8_lossy(end)
+      ),
+      Issue::Misaligned { start, end, shift } => {
+        let (start, end) = (start as usize, end as usize);
+        let value = match text.get(start..end) {
+          Some(v) => v,
+          None => {
+            return format!("A value spans two cells of the {:?} card", kw)
+          }
+        };
+        let moved = shift_value(text, start, end, isize::from(shift));
+
+        format!(
+          "'{}' spans two cells of the {:?} card, probably it needs to be \
+           moved {} column{} to the {}: '{}'",
+          String::from_utf8_lossy(value),
+          kw,
+          shift.abs(),
+          if shift.abs() == 1 { "" } else { "s" },
+          if shift < 0 { "left" } else { "right" },
+          String::from_utf8_lossy(&moved),
+        )
+      }
+      Issue::TrailingText { .. } => {
+        let rule = Rule::TrailingText;
+        format!("{} [{}]", rule.message(), rule)
+      }
+    }
+  }
+}
+
+/// The [`Issue`](crate::bufdata::structure::Issue)s of the cards in a buffer,
+/// saved with the [`LineId`](crate::lineid::LineId) of the line holding the
+/// keyword of the card. The `Vec` is ordered by the position of the lines,
+/// so like for the [`Folds`](crate::bufdata::folds::Folds), updates only need
+/// to touch the cards around the changed lines.
+#[derive(Default, Debug)]
+pub struct Structure(Vec<(LineId, (Keyword, Issue))>);
+
+impl Structure {
+  pub fn new() -> Self {
+    Self(Vec::new())
+  }
+
+  pub(super) fn clear(&mut self) {
+    self.0.clear()
+  }
+
+  pub fn iter(&self) -> impl Iterator<Item = &(LineId, (Keyword, Issue))> {
+    self.0.iter()
+  }
+
+  /// Record the issue of the card starting on the line with ID `id`.
+  pub(crate) fn push(&mut self, id: LineId, kw: Keyword, issue: Issue) {
+    self.0.push((id, (kw, issue)))
+  }
+
+  /// Return the range of indices of the issues of the cards starting in the
+  /// lines `firstline..lastline`. Issues of cards whose line is not part of
+  /// `lines` anymore are counted as being in that range.
+  fn linerange(
+    &self,
+    firstline: LineNr,
+    lastline: LineNr,
+    lines: &Lines,
+  ) -> Range<usize> {
+    let start = self
+      .0
+      .binary_search_by(|(id, _)| match lines.linenr(*id) {
+        Some(l) if l < firstline => Ordering::Less,
+        _ => Ordering::Greater,
+      })
+      .unwrap_or_else(|e| e);
+    let end = start
+      + self.0[start..]
+        .binary_search_by(|(id, _)| match lines.linenr(*id) {
+          Some(l) if l >= lastline => Ordering::Greater,
+          _ => Ordering::Less,
+        })
+        .unwrap_or_else(|e| e);
+
+    start..end
+  }
+
+  /// Replace the issues of the cards starting in `firstline..lastline` by
+  /// `new`. Like for [`Folds`](crate::bufdata::folds::Folds::splice), this
+  /// needs to be called after `lines` have been updated.
+  pub(super) fn splice(
+    &mut self,
+    new: Self,
+    firstline: LineNr,
+    lastline: LineNr,
+    lines: &Lines,
+  ) {
+    let range = self.linerange(firstline, lastline, lines);
+    let _ = self.0.splice(range, new.0);
+  }
+}
@@ -0,0 +1,320 @@
+//! Export the mesh of a buffer to the legacy VTK format, so it can be looked at
+//! in e.g. ParaView.
+use std::{
+  collections::HashMap,
+  fmt,
+  fs::File,
+  io::{BufWriter, Write},
+  path::Path,
+};
+
+use failure::{Error, ResultExt};
+use log::warn;
+
+use crate::{
+  bufdata::BufData,
+  card::{
+    cell::{read_float, read_integer, Cell},
+    keyword::Keyword,
+    line::Line,
+    Card,
+  },
+};
+
+/// The VTK cell types we export to. The values are the ones from the VTK file
+/// format specification.
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum VtkCell {
+  Line = 3,
+  Triangle = 5,
+  Quad = 9,
+  Tetra = 10,
+  Hexahedron = 12,
+  Wedge = 13,
+}
+
+impl VtkCell {
+  /// Return the VTK cell type of an element card, or `None` if the card is not
+  /// exported.
+  fn from_keyword(kw: Keyword) -> Option<Self> {
+    use self::Keyword::*;
+
+    match kw {
+      Shell | Tshel => Some(VtkCell::Quad),
+      Solid => Some(VtkCell::Hexahedron),
+      Tetr4 => Some(VtkCell::Tetra),
+      Penta6 => Some(VtkCell::Wedge),
+      Beam | Bar => Some(VtkCell::Line),
+      _ => None,
+    }
+  }
+
+  /// The number of nodes of the cell type
+  fn numnodes(self) -> usize {
+    match self {
+      VtkCell::Line => 2,
+      VtkCell::Triangle => 3,
+      VtkCell::Quad | VtkCell::Tetra => 4,
+      VtkCell::Wedge => 6,
+      VtkCell::Hexahedron => 8,
+    }
+  }
+}
+
+/// An element read from the buffer. The nodes are given by their ID.
+struct Element {
+  part: usize,
+  celltype: VtkCell,
+  nodes: Vec<usize>,
+}
+
+/// A summary of what was exported by
+/// [`write_vtk`](crate::bufdata::BufData::write_vtk).
+#[derive(Debug, PartialEq)]
+pub struct VtkSummary {
+  /// The number of nodes exported
+  pub points: usize,
+  /// The number of elements exported
+  pub cells: usize,
+  /// The number of elements that could not be exported because of invalid or
+  /// unknown nodes
+  pub skipped: usize,
+}
+
+impl fmt::Display for VtkSummary {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(
+      f,
+      "Exported {} nodes and {} elements, skipped {} elements",
+      self.points, self.cells, self.skipped
+    )
+  }
+}
+
+/// Read the values of all [`Integer`](crate::card::cell::Cell::Integer) cells
+/// of a line.
+fn integers<'a>(
+  cardline: &Line,
+  text: &'a [u8],
+) -> impl Iterator<Item = Option<usize>> + 'a {
+  cardline.cells_with_text(text).filter_map(|(c, s)| match c {
+    Cell::Integer(_) => Some(read_integer(s)),
+    _ => None,
+  })
+}
+
+impl<'a> BufData<'a> {
+  /// Export the mesh of the buffer to the file at `path`, see
+  /// [`write_vtk`](crate::bufdata::BufData::write_vtk).
+  pub fn export_vtk<P: AsRef<Path>>(
+    &self,
+    path: P,
+  ) -> Result<VtkSummary, Error> {
+    let path = path.as_ref();
+    let file = File::create(path)
+      .with_context(|_| format!("Could not create '{}'", path.display()))?;
+
+    let mut writer = BufWriter::new(file);
+    let summary = self.write_vtk(&mut writer)?;
+    writer.flush()?;
+
+    Ok(summary)
+  }
+
+  /// Write the nodes and elements of the buffer as a legacy VTK unstructured
+  /// grid. The part ID of the elements is written as cell data.
+  ///
+  /// The nodes are read from `NODE` and `CNODE` cards. Exported elements are
+  /// `SHELL`, `TSHEL` (as quads, or triangles if the last node is missing or
+  /// degenerated), `SOLID`, `TETR4`, `PENTA6`, `BEAM` and `BAR`. Elements
+  /// referencing nodes not in the buffer are skipped.
+  pub fn write_vtk<W: Write>(&self, mut w: W) -> Result<VtkSummary, Error> {
+    let mut points: Vec<[f64; 3]> = vec![];
+    let mut point_idx: HashMap<usize, usize> = HashMap::new();
+    let mut elements: Vec<Element> = vec![];
+    let mut skipped = 0;
+
+    let mut it = self.lines.iter().peekable();
+
+    while let Some(line) = it.next() {
+      let kw = match line.keyword {
+        Some(kw) => kw,
+        None => continue,
+      };
+      let card: &Card = (&kw).into();
+
+      if kw == Keyword::Node || kw == Keyword::Cnode {
+        let mut cells = card.lines[0].cells_with_text(line.text.as_ref());
+        let id = cells.nth(1).and_then(|(_, s)| read_integer(s));
+        let coords: Vec<_> = cells.map(|(_, s)| read_float(s)).collect();
+
+        match (id, coords.as_slice()) {
+          (Some(id), [Some(x), Some(y), Some(z)]) => {
+            let idx = points.len();
+            if *point_idx.entry(id).or_insert(idx) == idx {
+              points.push([*x, *y, *z]);
+            } else {
+              warn!("Node {} defined twice, using first definition", id);
+            }
+          }
+          _ => warn!("Could not read node in line {}", line.number),
+        }
+        continue;
+      }
+
+      let celltype = match VtkCell::from_keyword(kw) {
+        Some(t) => t,
+        None => continue,
+      };
+
+      let mut values: Vec<Option<usize>> =
+        integers(&card.lines[0], line.text.as_ref()).collect();
+
+      for cardline in &card.lines[1..] {
+        match it.peek() {
+          Some(l) if l.keyword.is_none() => {
+            values.extend(integers(cardline, l.text.as_ref()));
+            let _ = it.next();
+          }
+          _ => break,
+        }
+      }
+
+      let part = match values.get(1) {
+        Some(Some(p)) => *p,
+        _ => {
+          warn!("Could not read part of element in line {}", line.number);
+          continue;
+        }
+      };
+
+      let mut nodes: Vec<_> = values
+        .into_iter()
+        .skip(2)
+        .take(celltype.numnodes())
+        .collect();
+      let mut celltype = celltype;
+
+      if celltype == VtkCell::Quad
+        && (nodes.get(3) == Some(&None) || nodes.get(3) == nodes.get(2))
+      {
+        celltype = VtkCell::Triangle;
+        nodes.truncate(3);
+      }
+
+      match nodes.into_iter().collect::<Option<Vec<_>>>() {
+        Some(nodes) if nodes.len() == celltype.numnodes() => {
+          elements.push(Element {
+            part,
+            celltype,
+            nodes,
+          })
+        }
+        _ => skipped += 1,
+      }
+    }
+
+    let num_elements = elements.len();
+    elements.retain(|e| e.nodes.iter().all(|n| point_idx.contains_key(n)));
+    skipped += num_elements - elements.len();
+
+    writeln!(w, "# vtk DataFile Version 3.0")?;
+    writeln!(w, "Exported by nvimpam")?;
+    writeln!(w, "ASCII")?;
+    writeln!(w, "DATASET UNSTRUCTURED_GRID")?;
+
+    writeln!(w, "POINTS {} double", points.len())?;
+    for [x, y, z] in &points {
+      writeln!(w, "{} {} {}", x, y, z)?;
+    }
+
+    let size: usize = elements.iter().map(|e| e.nodes.len() + 1).sum();
+    writeln!(w, "CELLS {} {}", elements.len(), size)?;
+    for e in &elements {
+      write!(w, "{}", e.nodes.len())?;
+      for n in &e.nodes {
+        write!(w, " {}", point_idx[n])?;
+      }
+      writeln!(w)?;
+    }
+
+    writeln!(w, "CELL_TYPES {}", elements.len())?;
+    for e in &elements {
+      writeln!(w, "{}", e.celltype as u8)?;
+    }
+
+    writeln!(w, "CELL_DATA {}", elements.len())?;
+    writeln!(w, "SCALARS PartID int 1")?;
+    writeln!(w, "LOOKUP_TABLE default")?;
+    for e in &elements {
+      writeln!(w, "{}", e.part)?;
+    }
+
+    Ok(VtkSummary {
+      points: points.len(),
+      cells: elements.len(),
+      skipped,
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::bufdata::vtk::VtkSummary;
+
+  const LINES: [&'static str; 10] = [
+    "NODE  /        1              0.              0.              0.",
+    "NODE  /        2              1.              0.              0.",
+    "NODE  /        3              1.              1.              0.",
+    "#Comment",
+    "NODE  /        4              0.             1.5              0.",
+    "SHELL /       10       1       1       2       3       4",
+    "SHELL /       11       1       1       2       3       3",
+    "SHELL /       12       2       1       2       3      99",
+    "BAR   /       20       3       1       3",
+    "SOLID /       30       4",
+  ];
+
+  const VTK: &'static str = "# vtk DataFile Version 3.0
+Exported by nvimpam
+ASCII
+DATASET UNSTRUCTURED_GRID
+POINTS 4 double
+0 0 0
+1 0 0
+1 1 0
+0 1.5 0
+CELLS 3 12
+4 0 1 2 3
+3 0 1 2
+2 0 2
+CELL_TYPES 3
+9
+5
+3
+CELL_DATA 3
+SCALARS PartID int 1
+LOOKUP_TABLE default
+1
+1
+3
+";
+
+  #[test]
+  fn vtk_export() {
+    parse_bufdata!(bufdata, LINES);
+
+    let mut out: Vec<u8> = vec![];
+    let summary = bufdata.write_vtk(&mut out).unwrap();
+
+    assert_eq!(
+      summary,
+      VtkSummary {
+        points: 4,
+        cells: 3,
+        skipped: 2
+      }
+    );
+    assert_eq!(VTK, String::from_utf8(out).unwrap());
+  }
+}
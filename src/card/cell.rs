@@ -4,6 +4,7 @@
 /// input file, aside from comments and header data.
 use std::str;
 
+use atoi::atoi;
 use lexical::FromBytesLossy;
 
 use crate::card::keyword::Keyword;
@@ -63,6 +64,14 @@ pub enum Cell {
   Binary(u8),
   /// An alternative of 2 cells
   IntegerorBlank(u8),
+  /// A string of a given length that needs to be one of the given values,
+  /// e.g. `YES`, `NO` or `DFLT`
+  Choice(u8, &'static [&'static str]),
+  /// An integer of a given length in the given (inclusive) range
+  IntegerRange(u8, i64, i64),
+  /// A float of a given length in the given (inclusive) range. Use
+  /// `std::f64::INFINITY` for an open range, e.g. for a non-negative float
+  FloatRange(u8, f64, f64),
 }
 
 impl Cell {
@@ -84,8 +93,15 @@ impl Cell {
         s.len() as u8
       }
       Cont => 1,
-      Integer(u) | Float(u) | Blank(u) | Str(u) | Binary(u)
-      | IntegerorBlank(u) => u,
+      Integer(u)
+      | Float(u)
+      | Blank(u)
+      | Str(u)
+      | Binary(u)
+      | IntegerorBlank(u)
+      | Choice(u, _)
+      | IntegerRange(u, _, _)
+      | FloatRange(u, _, _) => u,
     }
   }
 
@@ -93,15 +109,27 @@ impl Cell {
   pub fn is_empty(&self) -> bool {
     use crate::card::cell::Cell::*;
     match *self {
-      Integer(u) | Float(u) | Blank(u) | Str(u) | Binary(u)
-      | IntegerorBlank(u) => u == 0,
+      Integer(u)
+      | Float(u)
+      | Blank(u)
+      | Str(u)
+      | Binary(u)
+      | IntegerorBlank(u)
+      | Choice(u, _)
+      | IntegerRange(u, _, _)
+      | FloatRange(u, _, _) => u == 0,
       _ => false,
     }
   }
 
   /// Checks if the contents of the cell in the file are valid for the type of
   /// the cell. Right now, only checks [`Float`](crate::card::cell::Cell::Float)
-  /// cells. Returns `false` if the slice is empty.
+  /// cells and the constrained cells
+  /// [`Choice`](crate::card::cell::Cell::Choice),
+  /// [`IntegerRange`](crate::card::cell::Cell::IntegerRange) and
+  /// [`FloatRange`](crate::card::cell::Cell::FloatRange). Those may be blank,
+  /// so the default value is used, or hold a variable like `<var>`. Returns
+  /// `false` if the slice is empty.
   ///
   /// TODO(KillTheMule): Extend. Implement Pyvars.
   #[inline]
@@ -133,14 +161,148 @@ impl Cell {
         trimmed == &[b' '] || f64::try_from_bytes_lossy(&trimmed).is_ok() ||
           (trimmed.first() == Some(&b'<') && trimmed.last() == Some(&b'>'))
       }
+      Choice(_, choices) => {
+        let trimmed = trim(s);
+        !s.is_empty()
+          && (trimmed.is_empty()
+            || is_variable(trimmed)
+            || choices.iter().any(|c| c.as_bytes() == trimmed))
+      }
+      IntegerRange(_, min, max) => {
+        let trimmed = trim(s);
+        !s.is_empty()
+          && (trimmed.is_empty()
+            || is_variable(trimmed)
+            || read_signed(trimmed).map_or(false, |i| min <= i && i <= max))
+      }
+      FloatRange(_, min, max) => {
+        let trimmed = trim(s);
+        !s.is_empty()
+          && (trimmed.is_empty()
+            || is_variable(trimmed)
+            || read_float(trimmed).map_or(false, |f| min <= f && f <= max))
+      }
       _ => true,
     }
   }
 }
 
+/// Strip leading and trailing blanks from the contents of a cell.
+#[inline]
+pub fn trim(s: &[u8]) -> &[u8] {
+  let start = s.iter().position(|b| *b != b' ').unwrap_or_else(|| s.len());
+  let end = s.iter().rposition(|b| *b != b' ').map_or(start, |e| e + 1);
+
+  &s[start..end]
+}
+
+/// If the trimmed contents of a cell are a variable like `<var>`
+#[inline]
+fn is_variable(trimmed: &[u8]) -> bool {
+  trimmed.first() == Some(&b'<') && trimmed.last() == Some(&b'>')
+}
+
+/// Read a possibly negative integer from the trimmed contents of a cell.
+#[inline]
+fn read_signed(trimmed: &[u8]) -> Option<i64> {
+  let (sign, digits) = match trimmed.split_first() {
+    Some((b'-', rest)) => (-1, rest),
+    Some((b'+', rest)) => (1, rest),
+    _ => (1, trimmed),
+  };
+
+  if !digits.is_empty() && digits.iter().all(u8::is_ascii_digit) {
+    atoi::<i64>(digits).map(|i| sign * i)
+  } else {
+    None
+  }
+}
+
+/// Read the value of an [`Integer`](crate::card::cell::Cell::Integer) cell.
+/// Returns `None` if the cell does not contain a (non-negative) number.
+#[inline]
+pub fn read_integer(s: &[u8]) -> Option<usize> {
+  let trimmed = trim(s);
+
+  if trimmed.iter().all(u8::is_ascii_digit) {
+    atoi::<usize>(trimmed)
+  } else {
+    None
+  }
+}
+
+/// Read the value of a [`Float`](crate::card::cell::Cell::Float) cell.
+/// Returns `None` if the cell does not contain a number.
+#[inline]
+pub fn read_float(s: &[u8]) -> Option<f64> {
+  let trimmed = trim(s);
+
+  if trimmed.is_empty() {
+    None
+  } else {
+    f64::try_from_bytes_lossy(trimmed).ok()
+  }
+}
+
 #[cfg(test)]
 mod tests {
-  use super::Cell;
+  use super::{read_float, read_integer, Cell};
+
+  #[test]
+  fn reading_floats() {
+    assert_eq!(read_float(b"              0."), Some(0.0));
+    assert_eq!(read_float(b"            50.5"), Some(50.5));
+    assert_eq!(read_float(b"  -1.5e-3 "), Some(-0.0015));
+    assert_eq!(read_float(b"                "), None);
+    assert_eq!(read_float(b"   <var>  "), None);
+  }
+
+  #[test]
+  fn reading_integers() {
+    assert_eq!(read_integer(b"       1"), Some(1));
+    assert_eq!(read_integer(b"  1234  "), Some(1234));
+    assert_eq!(read_integer(b"12345678"), Some(12_345_678));
+    assert_eq!(read_integer(b"        "), None);
+    assert_eq!(read_integer(b""), None);
+    assert_eq!(read_integer(b"   1 2  "), None);
+    assert_eq!(read_integer(b"  12x   "), None);
+  }
+
+  #[test]
+  fn verifying_constrained_cells() {
+    let choice = Cell::Choice(8, &["YES", "NO", "DFLT"]);
+    assert!(choice.verify(b"     YES"));
+    assert!(choice.verify(b"DFLT    "));
+    assert!(choice.verify(b"        "));
+    assert!(choice.verify(b"   <var>"));
+    assert!(!choice.verify(b"     yes"));
+    assert!(!choice.verify(b"   MAYBE"));
+    assert!(!choice.verify(b""));
+
+    let int = Cell::IntegerRange(8, 0, 3);
+    assert!(int.verify(b"       0"));
+    assert!(int.verify(b"    3   "));
+    assert!(int.verify(b"        "));
+    assert!(!int.verify(b"       4"));
+    assert!(!int.verify(b"      -1"));
+    assert!(!int.verify(b"      1."));
+
+    let int = Cell::IntegerRange(8, -1, 1);
+    assert!(int.verify(b"      -1"));
+    assert!(int.verify(b"      +1"));
+    assert!(!int.verify(b"       -"));
+
+    let float = Cell::FloatRange(16, 0.0, std::f64::INFINITY);
+    assert!(float.verify(b"              0."));
+    assert!(float.verify(b"          1.5e10"));
+    assert!(float.verify(b"                "));
+    assert!(!float.verify(b"            -0.1"));
+    assert!(!float.verify(b"             abc"));
+
+    let float = Cell::FloatRange(10, 0.0, 1.0);
+    assert!(float.verify(b"       0.5"));
+    assert!(!float.verify(b"       1.5"));
+  }
 
   #[test]
   fn verifying_floats() {
@@ -76,8 +76,12 @@ pub enum Keyword {
   Rbody1,
   Rbody2,
   Rbody3,
+  // Control
+  Signal,
   // Auxiliaries
   Group,
+  // A line that looks like a keyword, but isn't one of the above
+  Unknown,
 }
 
 impl Keyword {
@@ -181,7 +185,7 @@ impl Keyword {
         // b"PART  / "
         5782993917790138144 => {
           if len < 24 {
-            None
+            Some(Unknown)
           } else {
             let mut p = &s[16..24];
 
@@ -265,7 +269,7 @@ impl Keyword {
               5134473149087227936 | 2325899544952315936
               | 2314928554311163936 | 2314885698878971936
               | 2314885531474939936 | 2314885530821017936 => Some(PartGap),
-              _ => None,
+              _ => Some(Unknown),
             }
           }
         }
@@ -277,7 +281,7 @@ impl Keyword {
         // b"RBODY / "
         5927387214544645920 => {
           if len < 32 {
-            None
+            Some(Unknown)
           } else {
             let mut p = &s[24..32];
             let num3 = match p.read_u64::<BigEndian>() {
@@ -310,15 +314,46 @@ impl Keyword {
               | 2314906421539381280 | 2314885612422832160
               | 2314885531137220640 | 2314885530819698720
               | 2314885530818458400 | 2314885530818453555 => Some(Rbody3),
-              _ => None,
+              _ => Some(Unknown),
             }
           }
         }
+        // Control
+        // b"SIGNAL  "
+        6001406379876425760 => Some(Signal),
         // Auxiliaries
         // b"GROUP / "
         5139257352618258208 => Some(Group),
+        _ if Keyword::is_keyword(s) => Some(Unknown),
         _ => None,
       }
     }
   }
+
+  /// Check if a string starts like the keyword of a card, i.e. with an
+  /// uppercase name of up to 6 characters padded by blanks, followed by a `/`
+  /// in column 7. Used to detect cards nvimpam doesn't know.
+  #[inline]
+  pub fn is_keyword(s: &[u8]) -> bool {
+    if s.len() < 7 || s[6] != b'/' || !s[0].is_ascii_uppercase() {
+      return false;
+    }
+
+    let name = Keyword::name(s);
+    name
+      .iter()
+      .all(|&b| b.is_ascii_uppercase() || b.is_ascii_digit() || b == b'_')
+      && s[name.len()..6].iter().all(|&b| b == b' ')
+  }
+
+  /// The name of the keyword a string starts with, i.e. the first word of its
+  /// first 6 characters. Meant to be used on a line that
+  /// [`is_keyword`](crate::card::keyword::Keyword::is_keyword).
+  #[inline]
+  pub fn name(s: &[u8]) -> &[u8] {
+    let s = &s[..std::cmp::min(s.len(), 6)];
+    let len = s.iter().position(|&b| b == b' ').unwrap_or(s.len());
+
+    &s[..len]
+  }
 }
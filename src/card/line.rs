@@ -4,6 +4,7 @@
 use std::{cmp, ops::Range};
 
 use atoi::atoi;
+use itertools::Itertools;
 
 use crate::card::{cell::Cell, ges::GesType, keyword::Keyword};
 
@@ -16,6 +17,11 @@ pub enum Line {
   /// A [General Entity Selection](crate::card::ges::GesType), can consist of
   /// several lines
   Ges(GesType),
+  /// A [GES](crate::card::ges::GesType) that's only required if the
+  /// conditional with the given index holds, see the doc for
+  /// [`Optional`](crate::card::line::Line::Optional). It's skipped if present
+  /// nonetheless.
+  OptionalGes(GesType, u8),
   /// A line that provides a Conditional
   Provides(&'static [Cell], Conditional),
   /// An optional line, depending on a conditional
@@ -59,10 +65,149 @@ impl Line {
 
     match *self {
       Cells(s) | Provides(s, _) | Optional(s, _) | Repeat(s, _) => Some(s),
-      Ges(_) | Block(_, _) | OptionalBlock(_, _) => None,
+      Ges(_) | OptionalGes(_, _) | Block(_, _) | OptionalBlock(_, _) => None,
     }
   }
 
+  /// Iterate over the [`Cells`](crate::card::cell::Cell) of the line together
+  /// with the part of `text` they span. The iteration stops when `text` ends,
+  /// so the last cell returned might be cut short.
+  #[inline]
+  pub fn cells_with_text<'a>(
+    &self,
+    text: &'a [u8],
+  ) -> impl Iterator<Item = (&'static Cell, &'a [u8])> {
+    self
+      .cells()
+      .unwrap_or(&[])
+      .iter()
+      .scan(0_usize, move |until, cell| {
+        let start = *until;
+        if start >= text.len() {
+          return None;
+        }
+        *until += cell.len() as usize;
+
+        Some((cell, &text[start..cmp::min(text.len(), *until)]))
+      })
+  }
+
+  /// Whether a card needs to contain this line, given the results `conds` of
+  /// the conditionals of the lines before it. A
+  /// [`Ges`](crate::card::line::Line::Ges) needs at least its `END` line, and
+  /// a [`Block`](crate::card::line::Line::Block) its ending line.
+  pub fn is_mandatory(&self, conds: &[CondResult]) -> bool {
+    use self::Line::*;
+
+    match *self {
+      Cells(_) | Provides(_, _) | Ges(_) | Block(_, _) => true,
+      Optional(_, i) | OptionalGes(_, i) => {
+        conds.get(i as usize) == Some(&CondResult::Bool(true))
+      }
+      Repeat(_, i) => match conds.get(i as usize) {
+        Some(CondResult::Number(Some(u))) => *u > 0,
+        _ => false,
+      },
+      OptionalBlock(_, _) => false,
+    }
+  }
+
+  /// Find a number that straddles the boundary between two number cells of
+  /// the line, like an `Integer(8)` value shifted a few columns into the next
+  /// cell. Returns the columns `start..end` of the first such value, and the
+  /// shift that moves it into one of the cells. That's only done if it can be
+  /// shifted over blank columns only, and the whole line is valid afterwards.
+  /// If both cells would do, the smaller shift is taken.
+  pub fn misaligned(&self, text: &[u8]) -> Option<(usize, usize, isize)> {
+    use crate::card::cell::Cell::*;
+
+    let is_number = |c: &Cell| match c {
+      Integer(_) | IntegerorBlank(_) | IntegerRange(..) | Float(_)
+      | FloatRange(..) | Binary(_) => true,
+      _ => false,
+    };
+    let is_blank = |i: usize| text.get(i).map_or(true, |b| *b == b' ');
+
+    let cells = self.cells()?.iter().scan(0_usize, |until, cell| {
+      let start = *until;
+      *until += cell.len() as usize;
+      Some((cell, start..*until))
+    });
+
+    for ((c1, ref r1), (c2, ref r2)) in cells.tuple_windows() {
+      let b = r1.end;
+
+      if b >= text.len() {
+        break;
+      }
+
+      if !is_number(c1) || !is_number(c2) || is_blank(b - 1) || is_blank(b) {
+        continue;
+      }
+
+      let start = (r1.start..b)
+        .rev()
+        .find(|i| is_blank(*i))
+        .map_or(r1.start, |i| i + 1);
+      let end = (b..r2.end).find(|i| is_blank(*i)).unwrap_or(r2.end);
+      let value = &text[start..end];
+      let len = end - start;
+
+      if !value.iter().all(|b| b"0123456789+-.eE".contains(b)) {
+        continue;
+      }
+
+      // All cells of the shifted line need to be valid, and an `Integer` cell
+      // can't be left blank
+      let fits = |newstart: usize| {
+        let shift = newstart as isize - start as isize;
+        let moved = shift_value(text, start, end, shift);
+        (newstart..newstart + len)
+          .filter(|i| *i < start || *i >= end)
+          .all(is_blank)
+          && self.cells_with_text(&moved).all(|(c, s)| match c {
+            Integer(_) => s.iter().any(|b| *b != b' '),
+            _ => c.verify(s),
+          })
+      };
+
+      let mut shifts = vec![];
+      if len <= r1.len() && fits(b - len) {
+        shifts.push(b as isize - len as isize - start as isize);
+      }
+      if len <= r2.len() && fits(b) {
+        shifts.push(b as isize - start as isize);
+      }
+
+      if let Some(shift) = shifts.into_iter().min_by_key(|s| s.abs()) {
+        return Some((start, end, shift));
+      }
+    }
+
+    None
+  }
+
+  /// Find non-blank text after the last cell of the line, up to column 80.
+  /// Pamcrash doesn't read anything after the last cell. Returns the columns
+  /// `start..end` spanning that text. Lines consisting of a `Blank(0)` cell
+  /// don't have a known layout, so they're never flagged.
+  pub fn trailing(&self, text: &[u8]) -> Option<(usize, usize)> {
+    // Text after column 80 is not read at all, see the `long-line` lint
+    let columns = cmp::min(text.len(), 80);
+
+    let cells = self.cells()?;
+    if cells.contains(&Cell::Blank(0)) {
+      return None;
+    }
+
+    let len: usize = cells.iter().map(|c| c.len() as usize).sum();
+    let rest = text.get(len..columns)?;
+    let first = rest.iter().position(|b| !b.is_ascii_whitespace())?;
+    let last = rest.iter().rposition(|b| !b.is_ascii_whitespace())?;
+
+    Some((len + first, len + last + 1))
+  }
+
   /*
   /// Return an iterator over the highlight of a line. The `text` is
   /// parsed in the process to potentially highlight errors.
@@ -76,6 +221,30 @@ impl Line {
   */
 }
 
+/// Move the value in the columns `start..end` of `text` by `shift` columns,
+/// and fill the columns it left with blanks. The line is extended if
+/// necessary.
+pub fn shift_value(
+  text: &[u8],
+  start: usize,
+  end: usize,
+  shift: isize,
+) -> Vec<u8> {
+  let newstart = (start as isize + shift) as usize;
+  let newend = newstart + end - start;
+  let mut moved = text.to_vec();
+
+  if moved.len() < newend {
+    moved.resize(newend, b' ');
+  }
+  for b in &mut moved[start..end] {
+    *b = b' ';
+  }
+  moved[newstart..newend].copy_from_slice(&text[start..end]);
+
+  moved
+}
+
 /// An enum to represent different conditionals on lines
 #[derive(Debug, PartialEq)]
 pub enum Conditional {
@@ -154,6 +323,70 @@ mod tests {
     assert_eq!(Bool(false), cond2.evaluate(line.as_ref()));
   }
 
+  #[test]
+  fn cells_with_text_stops_at_line_end() {
+    use crate::card::{cell::Cell::*, keyword::Keyword::Node, line::Line};
+
+    let line = Line::Cells(&[Kw(Node), Integer(8), Float(16), Float(16)]);
+    let v: Vec<_> = line
+      .cells_with_text(b"NODE  /        1              0.   0.5")
+      .collect();
+
+    assert_eq!(
+      v,
+      vec![
+        (&Kw(Node), b"NODE  / ".as_ref()),
+        (&Integer(8), b"       1".as_ref()),
+        (&Float(16), b"              0.".as_ref()),
+        (&Float(16), b"   0.5".as_ref()),
+      ]
+    );
+  }
+
+  #[test]
+  fn finds_misaligned_values() {
+    use crate::card::{
+      cell::Cell::*,
+      keyword::Keyword::{Node, Shell},
+      line::{shift_value, Line},
+    };
+
+    let shell = Line::Cells(&[Kw(Shell), Integer(8), Integer(8), Integer(8)]);
+    let text = b"SHELL /       1    2 12345";
+    assert_eq!(shell.misaligned(text), Some((21, 26, 3)));
+    assert_eq!(
+      shift_value(text, 21, 26, 3),
+      b"SHELL /       1    2    12345".to_vec()
+    );
+    assert_eq!(shell.misaligned(b"SHELL /       1       2       3"), None);
+    // Both shifts would leave one of the cells blank
+    assert_eq!(shell.misaligned(b"SHELL /      12345678       1"), None);
+    assert_eq!(shell.misaligned(b"SHELL /1234567812345678"), None);
+
+    let node = Line::Cells(&[Kw(Node), Integer(8), Float(16), Float(16)]);
+    let text = b"NODE  /        1            -1.25e+3";
+    assert_eq!(node.misaligned(text), Some((28, 36, -4)));
+    assert_eq!(node.misaligned(b"NODE  /        1 abcdefghijklmnopq"), None);
+  }
+
+  #[test]
+  fn finds_trailing_text() {
+    use crate::card::{cell::Cell::*, keyword::Keyword::Shell, line::Line};
+
+    let shell = Line::Cells(&[Kw(Shell), Integer(8), Integer(8)]);
+    assert_eq!(shell.trailing(b"SHELL /       1       2"), None);
+    assert_eq!(shell.trailing(b"SHELL /       1       2     "), None);
+    assert_eq!(
+      shell.trailing(b"SHELL /       1       2  x y "),
+      Some((25, 28))
+    );
+    let long = format!("{:80}x", "SHELL /       1       2");
+    assert_eq!(shell.trailing(long.as_ref()), None);
+
+    let unknown = Line::Cells(&[Blank(0)]);
+    assert_eq!(unknown.trailing(b"      1.      2."), None);
+  }
+
   #[test]
   fn relchar_out_of_bounds() {
     let cond1 = Conditional::RelChar(95, b'b');
@@ -112,8 +112,11 @@ impl<'a> From<&'a Keyword> for &'static Card {
       Keyword::Rbody1 => &RBODY1,
       Keyword::Rbody2 => &RBODY2,
       Keyword::Rbody3 => &RBODY3,
+      // Control
+      Keyword::Signal => &SIGNAL,
       // Auxiliaries
       Keyword::Group => &GROUP,
+      Keyword::Unknown => &UNKNOWN,
     }
   }
 }
@@ -50,4 +50,33 @@ mod tests {
     vec![(0, 10, Group)]
   );
 
+  const CARD_GROUP_META: [&'static str; 7] = [
+    "GROUP / TitleOfTheGroup",
+    "META",
+    "  some metadata",
+    "END_META",
+    "        ELE ",
+    "        PART 14 ",
+    "        END",
+  ];
+
+  cardtest!(fold_group_meta, CARD_GROUP_META, vec![(0, 6, Group)]);
+
+  // The GES after the META block still belongs to the card
+  const CARD_GROUP_META2: [&'static str; 7] = [
+    "GROUP / TitleOfTheGroup",
+    "META",
+    "  some metadata",
+    "END_META",
+    "        PART 14 ",
+    "        END",
+    "NODE  /        1              0.             0.5              0.",
+  ];
+
+  cardtest!(
+    fold_group_meta_next_line,
+    CARD_GROUP_META2,
+    vec![(0, 5, Group), (6, 6, Node)]
+  );
+
 }
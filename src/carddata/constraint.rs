@@ -241,6 +241,26 @@ mod tests {
 
   cardtest!(fold_otmco2, CARD_OTMCO2, vec![(1, 8, Otmco)]);
 
+  const CARD_OTMCO3: [&'static str; 11] = [
+    "OTMCO /        1       0  111111       0      0.                        ",
+    "NAME Otmco->1                                                                   ",
+    "        WEIGHT                                                      1.",
+    "        NOD 1",
+    "        END",
+    "        WEIGHT                                                      2.",
+    "        PART 14",
+    "        END",
+    "END_OTMCO",
+    "RBODY /        1               0       0                       0       0        ",
+    "NAME RBODY / ->1                                                                ",
+  ];
+
+  cardtest!(
+    fold_otmco3,
+    CARD_OTMCO3,
+    vec![(0, 8, Otmco), (9, 10, Rbody0)]
+  );
+
   const CARD_RBODY0: [&'static str; 6] = [
     "$RBODY Type 0",
     "$#          IDRB   BLANK    ITRBIDNODcog    ICOG           ISENS    IFRA     HRB",
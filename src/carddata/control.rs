@@ -0,0 +1,21 @@
+//! This modules holds the the global static control
+//! [`Card`](crate::card::Card) instances.
+use crate::card::{cell::Cell::*, keyword::Keyword::*, line::Line::*, Card};
+
+pub static SIGNAL: Card = Card {
+  lines: &[Cells(&[Kw(Signal), Choice(8, &["YES", "NO"])])],
+  ownfold: false,
+};
+
+#[cfg(test)]
+mod tests {
+  use crate::card::keyword::Keyword::*;
+
+  const CARD_SIGNAL: [&'static str; 3] = [
+    "UNIT       MM       KG       MS   KELVIN",
+    "SIGNAL      YES",
+    "$",
+  ];
+
+  cardtest!(fold_signal, CARD_SIGNAL, vec![(1, 1, Signal)]);
+}
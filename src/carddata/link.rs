@@ -4,7 +4,7 @@ use crate::card::{
   cell::{Cell::*, FixedStr},
   ges::GesType::*,
   keyword::Keyword::*,
-  line::Line::*,
+  line::{Conditional::*, Line::*},
   Card,
 };
 
@@ -42,15 +42,18 @@ pub static SLINK: Card = Card {
 
 pub static PLINK: Card = Card {
   lines: &[
-    Cells(&[
-      Kw(Plink),
-      Integer(8),
-      Integer(8),
-      Integer(8),
-      Integer(8),
-      Integer(8),
-    ]),
-    Ges(GesFace),
+    Provides(
+      &[
+        Kw(Plink),
+        Integer(8),
+        Integer(8),
+        Integer(8),
+        IntegerRange(8, 0, 1),
+        Integer(8),
+      ],
+      Int(32..40, 1),
+    ),
+    OptionalGes(GesFace, 0),
   ],
   ownfold: false,
 };
@@ -140,6 +143,17 @@ mod tests {
 
   cardtest!(fold_plink, CARD_PLINK, vec![(1, 7, Plink)]);
 
+  // The GES is only given if MORE, in columns 33 to 40, is 1
+  const CARD_PLINK_MORE: [&'static str; 5] = [
+    "PLINK /        1       1       1       0       0",
+    "PLINK /        2       1       1       1       0",
+    "        PART 45",
+    "        END",
+    "PLINK /        3       1       1",
+  ];
+
+  cardtest!(fold_plink_more, CARD_PLINK_MORE, vec![(0, 4, Plink)]);
+
   const CARD_TIED: [&'static str; 22] = [
     "$TIED Element",
     "TIED  /                         ",
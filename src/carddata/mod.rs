@@ -2,17 +2,26 @@
 //! instances
 //
 // Export the macros inside this crate
+//
+// Bind a new `BufData` holding the parsed `$lines` to `$bufdata`. It borrows a
+// `Buffer` created alongside, so this can't be a function.
+#[cfg(test)]
+macro_rules! parse_bufdata {
+  ($bufdata: ident, $lines: expr) => {
+    let buf = neovim_lib::neovim_api::Buffer::new(neovim_lib::Value::from(
+      0_usize,
+    ));
+    let mut $bufdata = crate::bufdata::BufData::new(&buf);
+    $bufdata.parse_strs(&$lines).unwrap();
+  };
+}
+
 #[cfg(test)]
 macro_rules! cardtest {
   ($name: ident, $c: expr, $v: expr) => {
     #[test]
     fn $name() {
-      use crate::bufdata::BufData;
-      use neovim_lib::{neovim_api::Buffer, Value};
-
-      let buf = Buffer::new(Value::from(0_usize));
-      let mut bufdata = BufData::new(&buf);
-      bufdata.parse_strs(&$c).unwrap();
+      parse_bufdata!(bufdata, $c);
 
       assert_eq!($v, bufdata.folds_to_vec());
     }
@@ -20,12 +29,7 @@ macro_rules! cardtest {
   ($name: ident, $c: ident, $v: expr, $w: expr) => {
     #[test]
     fn $name() {
-      use crate::bufdata::BufData;
-      use neovim_lib::{neovim_api::Buffer, Value};
-
-      let buf = Buffer::new(Value::from(0_usize));
-      let mut bufdata = BufData::new(&buf);
-      bufdata.parse_strs(&$c).unwrap();
+      parse_bufdata!(bufdata, $c);
 
       assert_eq!($v, bufdata.folds_to_vec());
       assert_eq!($w, bufdata.folds_level2_to_vec());
@@ -35,14 +39,19 @@ macro_rules! cardtest {
 
 pub mod auxiliaries;
 pub mod constraint;
+pub mod control;
 pub mod element;
 pub mod link;
 pub mod node;
 pub mod part;
+pub mod unknown;
 
 /// All static declarations can be imported via
 /// ```rust, compile_fail
 /// use carddata::*;
 /// ```
 pub use self::element::*;
-pub use self::{auxiliaries::*, constraint::*, link::*, node::*, part::*};
+pub use self::{
+  auxiliaries::*, constraint::*, control::*, link::*, node::*, part::*,
+  unknown::*,
+};
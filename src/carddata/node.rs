@@ -41,7 +41,11 @@ pub static MASS: Card = Card {
       Float(16),
     ]),
     Cells(&[Fixed(FixedStr::Name), Str(76)]),
-    Cells(&[Float(16), Float(16), Float(16)]),
+    Cells(&[
+      FloatRange(16, 0.0, std::f64::INFINITY),
+      FloatRange(16, 0.0, std::f64::INFINITY),
+      FloatRange(16, 0.0, std::f64::INFINITY),
+    ]),
     Provides(
       &[Blank(8), Float(16), Float(16), Float(16), Blank(24), Cont],
       RelChar(80, b'&'),
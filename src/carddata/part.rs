@@ -124,19 +124,22 @@ pub static PARTMBKJN: Card = part!(
   ;PartMbkjn);
 
 pub static PARTTIED: Card = part!(
-  Provides(&[Float(10), Blank(60), Integer(10)], Int(71..81,1)),
+  Provides(&[Float(10), Blank(60), IntegerRange(10, 0, 1)],
+           Int(71..81,1)),
   Optional(&[Integer(5), Blank(5), Float(10), Float(10), Float(10), Float(10)],
            1)
   ;PartTied);
 
 pub static PARTSLINK: Card = part!(
-  Provides(&[Float(10), Blank(60), Integer(10)], Int(71..81,1)),
+  Provides(&[Float(10), Blank(60), IntegerRange(10, 0, 1)],
+           Int(71..81,1)),
   Optional(&[Integer(5), Blank(5), Float(10), Float(10), Float(10), Float(10)],
            1)
   ;PartSlink);
 
 pub static PARTELINK: Card = part!(
-  Provides(&[Float(10), Float(10), Blank(50), Integer(10)], Int(71..81,1)),
+  Provides(&[Float(10), Float(10), Blank(50), IntegerRange(10, 0, 1)],
+           Int(71..81,1)),
   Optional(&[Integer(5), Blank(5), Float(10), Float(10), Float(10), Float(10)],
            1)
   ;PartElink);
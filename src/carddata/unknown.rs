@@ -0,0 +1,59 @@
+//! This modules holds the the global static [`Card`](crate::card::Card)
+//! instance for cards with an
+//! [`Unknown`](crate::card::keyword::Keyword::Unknown) keyword.
+use crate::card::{cell::Cell::*, keyword::Keyword::*, line::Line::*, Card};
+
+/// Only the keyword is known. The lines after it are gathered until the next
+/// keyword or the matching `END_` line, see
+/// [`skip_unknown`](crate::linesiter::LinesIter::skip_unknown).
+pub static UNKNOWN: Card = Card {
+  lines: &[Cells(&[Kw(Unknown)])],
+  ownfold: true,
+};
+
+#[cfg(test)]
+mod tests {
+  use crate::card::keyword::Keyword::*;
+
+  const CARD_UNKNOWN: [&'static str; 12] = [
+    "TITLE /  BoxBeam fine meshed model",
+    "RUNEND/  ",
+    " TIME      15.01",
+    "END_RUNEND",
+    "$",
+    "OCTRL /  ",
+    " THPOUTPUT    POINT     1000",
+    "NODPLOT    DFLT",
+    "END_OCTRL",
+    "garbage",
+    "NODE  /        1              0.            50.5              0.",
+    "OCTRL /  ",
+  ];
+
+  cardtest!(
+    fold_unknown,
+    CARD_UNKNOWN,
+    vec![
+      (0, 0, Unknown),
+      (1, 3, Unknown),
+      (5, 8, Unknown),
+      (10, 10, Node),
+      (11, 11, Unknown)
+    ],
+    Vec::<(usize, usize, _)>::new()
+  );
+
+  const CARD_UNKNOWN_PART: [&'static str; 5] = [
+    "PART  /        1     PLY",
+    "NAME unknown part type",
+    "END_PARTX",
+    "END_PART",
+    "NODE  /        1              0.            50.5              0.",
+  ];
+
+  cardtest!(
+    fold_unknown_part,
+    CARD_UNKNOWN_PART,
+    vec![(0, 3, Unknown), (4, 4, Node)]
+  );
+}
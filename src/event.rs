@@ -1,12 +1,16 @@
 //! The events that nvimpam needs to accept and deal with. They're sent by the
 //! [`NeovimHandler`](crate::handler::NeovimHandler) to the main loop.
-use std::{ffi::OsString, fmt, fs, sync::mpsc};
+use std::{collections::HashMap, ffi::OsString, fmt, fs, mem, sync::mpsc};
 
 use failure::{self, Error, ResultExt};
 use log::{info, warn};
 use neovim_lib::{neovim::Neovim, neovim_api::Buffer, NeovimApi, Value};
 
-use crate::{bufdata::BufData, linenr::LineNr};
+use crate::{
+  bufdata::{BufData, Tick},
+  handler::parse_buf,
+  linenr::LineNr,
+};
 
 /// The event list the main loop reacts to
 pub enum Event {
@@ -14,8 +18,9 @@ pub enum Event {
   /// is zero-indexed (i.e. a change on the first line will have `firstline =
   /// 0`). The range from firstline to lastline is end-exclusive. `more`
   /// indicates if we need to expect another event of this type with more
-  /// lines, in case Neovim decided to split up the buffer (not yet
-  /// implemented).
+  /// lines, in case Neovim decided to split up the buffer. Those events are
+  /// collected in [`PendingLines`](crate::event::PendingLines) and applied
+  /// as one update.
   LinesEvent {
     buf: Buffer,
     changedtick: u64,
@@ -25,55 +30,266 @@ pub enum Event {
     more: bool,
   },
   /// Update notification for a new `changedtick` without a buffer change.
-  /// Used by undo/redo.
+  /// Used by undo/redo, and when writing the buffer. It only advances the
+  /// `changedtick` that updates are checked against to detect outdated ones,
+  /// see [`check_tick`](crate::bufdata::BufData::check_tick).
   ChangedTickEvent { buf: Buffer, changedtick: u64 },
   /// Notification the liveupdates are ending. Possible causes:
   ///  - Closing all a buffer's windows (unless 'hidden' is enabled).
   ///  - Using |:edit| to reload the buffer
   ///  - reloading the buffer after it is changed from outside neovim.
+  ///
+  /// In the latter 2 cases the buffer is still loaded, and nvimpam attaches to
+  /// it again, see [`reattach`](crate::event::reattach).
   DetachEvent { buf: Buffer },
+  /// Attach to another buffer, so it's served by the same process
+  AttachBuffer { buf: Buffer },
+  /// Detach from a buffer. If no buffers are left, nvimpam quits.
+  DetachBuffer { buf: Buffer },
   /// Recreate and resend the folds
-  RefreshFolds,
+  RefreshFolds { buf: Buffer },
+  /// Send the [`Stats`](crate::bufdata::stats::Stats) of the buffer
+  Stats { buf: Buffer },
+  /// Export the mesh of the buffer to a VTK file, see
+  /// [`write_vtk`](crate::bufdata::BufData::write_vtk)
+  ExportVtk { buf: Buffer, path: String },
   /// Highlight lines in the buffer containing at least the given line range
-  // TODO: maybe accept buffer as an argument?
-  HighlightRegion { firstline: i64, lastline: i64 },
+  HighlightRegion {
+    buf: Buffer,
+    firstline: i64,
+    lastline: i64,
+  },
   /// This plugin should quit. Currently only sent by the user directly.
   Quit,
 }
 
+/// The lines of a [`LinesEvent`](crate::event::Event::LinesEvent) with `more
+/// = true`, waiting for the events continuing it.
+#[derive(Debug, PartialEq)]
+struct PendingLines {
+  buf: Buffer,
+  firstline: i64,
+  lastline: i64,
+  linedata: Vec<String>,
+}
+
+/// Add the lines of a [`LinesEvent`](crate::event::Event::LinesEvent) to the
+/// pending lines of its buffer. If `more` is false, the update is complete
+/// and `firstline`, `lastline` and the lines of the whole update are
+/// returned. The range is taken from the first event of the update, the lines
+/// of the continuing events are appended.
+fn collect_lines(
+  pending: &mut Vec<PendingLines>,
+  buf: Buffer,
+  firstline: i64,
+  lastline: i64,
+  linedata: Vec<String>,
+  more: bool,
+) -> Option<(i64, i64, Vec<String>)> {
+  let idx = pending.iter().position(|p| p.buf == buf);
+
+  match (idx, more) {
+    (None, false) => Some((firstline, lastline, linedata)),
+    (None, true) => {
+      pending.push(PendingLines {
+        buf,
+        firstline,
+        lastline,
+        linedata,
+      });
+      None
+    }
+    (Some(i), true) => {
+      pending[i].linedata.extend(linedata);
+      None
+    }
+    (Some(i), false) => {
+      let mut p = pending.remove(i);
+      p.linedata.extend(linedata);
+      Some((p.firstline, p.lastline, p.linedata))
+    }
+  }
+}
+
+/// Request the lines and the `changedtick` of the buffer from neovim and
+/// rebuild its [`BufData`](crate::bufdata::BufData). Used to get the initial
+/// contents of a buffer after attaching, and when updates went missing. Both
+/// are requested in one atomic call, so they always match.
+fn resync(nvim: &mut Neovim, bufdata: &mut BufData) -> Result<(), Error> {
+  let buf = bufdata.buf.get_value().clone();
+  let calls = vec![
+    Value::from(vec![
+      Value::from("nvim_buf_get_changedtick"),
+      Value::from(vec![buf.clone()]),
+    ]),
+    Value::from(vec![
+      Value::from("nvim_buf_get_lines"),
+      Value::from(vec![
+        buf,
+        Value::from(0),
+        Value::from(-1),
+        Value::from(false),
+      ]),
+    ]),
+  ];
+
+  let mut res = nvim.call_atomic(calls).context("call_atomic failed")?;
+  if res.get(1).map(Value::is_nil) == Some(false) {
+    return Err(failure::err_msg(format!(
+      "Resync of buffer {:?} failed: {:?}",
+      bufdata.buf, res[1]
+    )));
+  }
+
+  let results = match res.get_mut(0) {
+    Some(Value::Array(v)) if v.len() == 2 => v,
+    _ => return Err(failure::err_msg("Invalid response to resync!")),
+  };
+  let changedtick = results[0]
+    .as_u64()
+    .ok_or_else(|| failure::err_msg("Invalid changedtick in resync!"))?;
+  let lines = match mem::replace(&mut results[1], Value::Nil) {
+    Value::Array(v) => v
+      .into_iter()
+      .map(|l| match l {
+        Value::String(s) => s.into_str(),
+        _ => None,
+      })
+      .collect::<Option<Vec<_>>>(),
+    _ => None,
+  }
+  .ok_or_else(|| failure::err_msg("Invalid lines in resync!"))?;
+
+  bufdata.clear();
+  bufdata.parse_vec(lines)?;
+  bufdata.synced(changedtick);
+
+  Ok(())
+}
+
+/// Attach to a buffer again after it was reloaded. The buffer is parsed
+/// anew, and the folds and highlights are sent to neovim again.
+fn reattach(
+  nvim: &mut Neovim,
+  bufdata: &mut BufData,
+  ns: i64,
+) -> Result<(), Error> {
+  let buf = bufdata.buf.clone();
+
+  if !buf.attach(nvim, false, vec![])? {
+    return Err(failure::err_msg("Could not reenable buffer updates!"));
+  }
+  resync(nvim, bufdata)?;
+
+  buf
+    .clear_namespace(nvim, ns, 0, -1)
+    .context("could not clear namespace")?;
+  nvim
+    .execute_lua(
+      "require('nvimpam').on_reload(...)",
+      vec![
+        buf.get_value().clone(),
+        bufdata.with_tick(bufdata.fold_calls()),
+      ],
+    )
+    .context("could not send folds")?;
+
+  Ok(())
+}
+
+/// The [`BufData`](crate::bufdata::BufData)s of the attached buffers, keyed
+/// by the [`handle`](crate::event::handle) of their buffer
+type BufDatas<'a> = HashMap<i64, BufData<'a>>;
+
+/// The handle of a buffer. [`parse_buf`](crate::handler::parse_buf) turns
+/// the buffers sent by neovim into their handle, so this is only `None` for
+/// values neovim would not send.
+fn handle(buf: &Buffer) -> Option<i64> {
+  buf.get_value().as_i64()
+}
+
+/// Find the [`BufData`](crate::bufdata::BufData) of a buffer
+fn find<'a, 'b>(
+  bufdatas: &'b mut BufDatas<'a>,
+  buf: &Buffer,
+) -> Option<&'b mut BufData<'a>> {
+  handle(buf).and_then(move |h| bufdatas.get_mut(&h))
+}
+
+/// Find the [`BufData`](crate::bufdata::BufData) of a buffer, or return an
+/// error message suitable to send to neovim
+fn find_or_err<'a, 'b>(
+  bufdatas: &'b mut BufDatas<'a>,
+  buf: &Buffer,
+) -> Result<&'b mut BufData<'a>, Value> {
+  find(bufdatas, buf).ok_or_else(|| {
+    Value::from(format!("Nvimpam is not attached to buffer {:?}!", buf))
+  })
+}
+
 impl Event {
   /// Run the event loop. The receiver receives the events from the
   /// [handler](crate::handler::NeovimHandler).
   ///
+  /// The buffer to attach to should be given by the plugin on startup. If it
+  /// isn't, the current buffer is used, which might have changed since the
+  /// plugin was started.
+  ///
   /// If a file was given as an argument, nvimpam reads it and creates its
   /// [`BufData`](crate::bufdata::BufData) from it. Then it enables
   /// [buffer events](https://neovim.io/doc/user/api.html#nvim_buf_attach()) and
   /// updates the [`BufData`](crate::bufdata::BufData) accordingly.
   ///
-  /// If no file was given as an argument, nvimpam directly enables
+  /// If no file was given as an argument, nvimpam enables
   /// [buffer events](https://neovim.io/doc/user/api.html#nvim_buf_attach())
-  /// and requests the buffer's contents from it instead.
+  /// and requests the buffer's contents from it afterwards.
+  ///
+  /// Either way, the initial parse is done before the first event is handled.
+  /// Requests and notifications sent in the meantime stay queued in the
+  /// receiver, so they are never answered from an empty
+  /// [`BufData`](crate::bufdata::BufData).
   ///
-  /// Sending the [`Quit`](crate::event::Event::Quit) event will
-  /// exit the loop and return from the function.
+  /// More buffers can be attached by sending
+  /// [`AttachBuffer`](crate::event::Event::AttachBuffer), their contents are
+  /// always requested from neovim before replying. Events are routed to the
+  /// [`BufData`](crate::bufdata::BufData) of their buffer.
+  ///
+  /// Sending the [`Quit`](crate::event::Event::Quit) event, or detaching from
+  /// the last buffer, will exit the loop and return from the function.
   pub fn event_loop(
     from_handler: &mpsc::Receiver<Self>,
-    to_handler: &mpsc::Sender<Value>,
+    to_handler: &mpsc::Sender<Result<Value, Value>>,
     nvim: &mut Neovim,
+    buf: Option<Buffer>,
     file: Option<OsString>,
   ) -> Result<(), Error> {
     use self::Event::*;
 
-    let curbuf = nvim.get_current_buf()?;
+    let curbuf = match buf {
+      Some(buf) => buf,
+      None => parse_buf(nvim.get_current_buf()?.get_value().clone()),
+    };
+    // The highlights of all buffers are placed in this namespace. Neovim
+    // returns the same one for every nvimpam process.
+    let ns = nvim
+      .create_namespace("nvimpam")
+      .context("could not create namespace")?;
+    let curhandle = handle(&curbuf).ok_or_else(|| {
+      failure::err_msg(format!("Invalid buffer {:?}!", curbuf))
+    })?;
     let origlines;
     let mut bufdata = BufData::new(&curbuf);
+    let mut pending: Vec<PendingLines> = vec![];
 
-    let connected = match file {
-      None => curbuf.attach(nvim, true, vec![])?,
+    // Get the changedtick before attaching, so the updates of a change in
+    // between are applied
+    let (connected, from_file) = match file {
+      None => (curbuf.attach(nvim, false, vec![])?, false),
       Some(f) => {
         origlines = fs::read(f)?;
         bufdata.parse_slice(&origlines)?;
-        curbuf.attach(nvim, false, vec![])?
+        bufdata.synced(curbuf.get_changedtick(nvim)? as u64);
+        (curbuf.attach(nvim, false, vec![])?, true)
       }
     };
 
@@ -81,20 +297,71 @@ impl Event {
       return Err(failure::err_msg("Could not enable buffer updates!"));
     }
 
+    // Request the contents after attaching, so no change can get lost
+    if !from_file {
+      resync(nvim, &mut bufdata)?;
+    }
+
+    let mut bufdatas: BufDatas = HashMap::new();
+    let _ = bufdatas.insert(curhandle, bufdata);
+
     loop {
       match from_handler.recv() {
         Ok(LinesEvent {
+          buf,
           firstline,
           lastline,
           linedata,
           changedtick,
-          ..
+          more,
         }) => {
           if changedtick == 0 {
             continue;
           }
-          if lastline == -1 {
+          let (firstline, lastline, linedata) = match collect_lines(
+            &mut pending,
+            buf.clone(),
+            firstline,
+            lastline,
+            linedata,
+            more,
+          ) {
+            Some(update) => update,
+            None => continue,
+          };
+          let bufdata = match find(&mut bufdatas, &buf) {
+            Some(b) => b,
+            None => {
+              warn!("Received LinesEvent for unknown buffer {:?}", buf);
+              continue;
+            }
+          };
+          let tick = bufdata.check_tick(changedtick);
+
+          if tick == Tick::Stale {
+            warn!(
+              "Ignoring outdated update {} of buffer {:?}, already at {}",
+              changedtick, buf, bufdata.changedtick
+            );
+          } else if lastline == -1 {
+            bufdata.clear();
             bufdata.parse_vec(linedata)?;
+            bufdata.synced(changedtick);
+          } else if tick == Tick::Behind {
+            warn!(
+              "Update {} of buffer {:?} arrived after {}, resyncing",
+              changedtick, buf, bufdata.changedtick
+            );
+            resync(nvim, bufdata)?;
+          } else if lastline as usize > bufdata.numlines() {
+            warn!(
+              "Update of lines {}..{} of buffer {:?} with {} lines, resyncing",
+              firstline,
+              lastline,
+              buf,
+              bufdata.numlines()
+            );
+            resync(nvim, bufdata)?;
           } else {
             debug_assert!(
               lastline >= 0 && firstline >= 0 && lastline >= firstline
@@ -102,17 +369,110 @@ impl Event {
             let lastline = LineNr::from_i64(lastline);
             let firstline = LineNr::from_i64(firstline);
 
-            let (newrange, added) = bufdata.update(firstline, lastline, linedata)?;
-            if let Some(calls) =
-              bufdata.highlight_region_calls(newrange, firstline, lastline +
-                                             added)
-            {
+            let changed = bufdata.update(firstline, lastline, linedata)?;
+            if let Some(calls) = bufdata.highlight_lines_calls(ns, &changed) {
               nvim.call_atomic(calls).context("call_atomic failed")?;
             }
+            bufdata.changedtick = changedtick;
+          }
+        }
+        Ok(ChangedTickEvent { buf, changedtick }) => {
+          let bufdata = match find(&mut bufdatas, &buf) {
+            Some(b) => b,
+            None => {
+              warn!("Received ChangedTickEvent for unknown buffer {:?}", buf);
+              continue;
+            }
+          };
+
+          if bufdata.check_tick(changedtick) == Tick::Next {
+            bufdata.changedtick = changedtick;
+          }
+        }
+        Ok(AttachBuffer { buf }) => {
+          let h = match handle(&buf) {
+            Some(h) => h,
+            None => {
+              to_handler
+                .send(Err(Value::from(format!("Invalid buffer {:?}!", buf))))?;
+              continue;
+            }
+          };
+          if bufdatas.contains_key(&h) {
+            to_handler.send(Err(Value::from(format!(
+              "Nvimpam is already attached to buffer {:?}!",
+              buf
+            ))))?;
+            continue;
+          }
+
+          let res = match buf.attach(nvim, false, vec![]) {
+            Ok(true) => {
+              let mut bufdata = BufData::new(&buf);
+              match resync(nvim, &mut bufdata) {
+                Ok(()) => {
+                  let _ = bufdatas.insert(h, bufdata);
+                  Ok(Value::from(true))
+                }
+                Err(e) => {
+                  let _ = buf.detach(nvim);
+                  Err(Value::from(format!(
+                    "Could not read buffer {:?}: {}",
+                    buf, e
+                  )))
+                }
+              }
+            }
+            Ok(false) => Err(Value::from(format!(
+              "Could not enable buffer updates for buffer {:?}!",
+              buf
+            ))),
+            Err(e) => Err(Value::from(format!(
+              "Could not attach to buffer {:?}: {}",
+              buf, e
+            ))),
+          };
+          to_handler.send(res)?;
+        }
+        Ok(DetachBuffer { buf }) => {
+          let res = match handle(&buf).and_then(|h| bufdatas.remove(&h)) {
+            Some(_) => {
+              pending.retain(|p| p.buf != buf);
+              buf
+                .clear_namespace(nvim, ns, 0, -1)
+                .context("could not clear namespace")?;
+              buf.detach(nvim).context("could not detach from buffer")?;
+              Ok(Value::from(true))
+            }
+            None => Err(Value::from(format!(
+              "Nvimpam is not attached to buffer {:?}!",
+              buf
+            ))),
+          };
+          to_handler.send(res)?;
+
+          if bufdatas.is_empty() {
+            break;
           }
         }
-        Ok(RefreshFolds) => to_handler.send(bufdata.fold_calls())?,
+        Ok(RefreshFolds { buf }) => to_handler.send(
+          find_or_err(&mut bufdatas, &buf).map(|b| b.with_tick(b.fold_calls())),
+        )?,
+        Ok(Stats { buf }) => to_handler.send(
+          find_or_err(&mut bufdatas, &buf).map(|b| b.with_tick(&b.stats())),
+        )?,
+        Ok(ExportVtk { buf, path }) => {
+          let res = find_or_err(&mut bufdatas, &buf).map(|b| {
+            let msg = match b.export_vtk(&path) {
+              Ok(summary) => format!("{} to '{}'", summary, path),
+              Err(e) => format!("Export to '{}' failed: {}", path, e),
+            };
+            b.with_tick(msg)
+          });
+          to_handler.send(res)?
+        }
         Ok(HighlightRegion {
+          buf,
           firstline,
           lastline,
         }) => {
@@ -122,6 +482,14 @@ impl Event {
           let lastline = LineNr::from_i64(lastline);
           let firstline = LineNr::from_i64(firstline);
 
+          let bufdata = match find(&mut bufdatas, &buf) {
+            Some(b) => b,
+            None => {
+              warn!("Received HighlightRegion for unknown buffer {:?}", buf);
+              continue;
+            }
+          };
+
           let fl = bufdata.first_before(firstline);
           let mut ll = bufdata.first_after(lastline);
 
@@ -134,7 +502,7 @@ impl Event {
           let newrange = bufdata.hl_linerange(fl.1, ll.1);
 
           if let Some(calls) =
-            bufdata.highlight_region_calls(newrange, fl.1, ll.1)
+            bufdata.highlight_region_calls(ns, newrange, fl.1, ll.1)
           {
             nvim.call_atomic(calls).context("call_atomic failed")?;
           }
@@ -143,22 +511,39 @@ impl Event {
           break;
         }
         Ok(DetachEvent { buf }) => {
-          if *bufdata.buf == buf {
+          let h = match handle(&buf).filter(|h| bufdatas.contains_key(h)) {
+            Some(h) => h,
+            None => {
+              warn!(
+                "Received Detach Event for buffer {:?}, but was not attached \
+                 to it. Continuing!",
+                buf
+              );
+              continue;
+            }
+          };
+          pending.retain(|p| p.buf != buf);
+
+          // A reloaded buffer is still loaded, a wiped or unloaded one isn't
+          if buf.is_loaded(nvim).unwrap_or(false) {
+            info!("Buffer {:?} was reloaded, reattaching", buf);
+            if let Some(bufdata) = bufdatas.get_mut(&h) {
+              reattach(nvim, bufdata, ns)?;
+            }
+            continue;
+          }
+
+          let _ = bufdatas.remove(&h);
+          if buf.is_valid(nvim)? {
             buf
-              .clear_namespace(nvim, 5, 0, -1)
-              .context("could not clear namespace 5")?;
+              .clear_namespace(nvim, ns, 0, -1)
+              .context("could not clear namespace")?;
+          }
+
+          if bufdatas.is_empty() {
             break;
-          } else {
-            warn!(
-              "Received Detach Event for buffer {:?}, but was attached to
-               buffer {:?}. Continuing!",
-              buf, bufdata.buf
-            );
           }
         }
-        Ok(o) => {
-          warn!("receiver recieved {:?}", o);
-        }
         Err(e) => {
           warn!("receiver received error: {:?}", e);
         }
@@ -195,14 +580,70 @@ impl fmt::Debug for Event {
       HighlightRegion {
         firstline,
         lastline,
+        ..
       } => write!(
         f,
         "HighlightRegion{{ firstline: {}, lastline: {} }}",
         firstline, lastline
       ),
       DetachEvent { .. } => write!(f, "DetachEvent"),
-      RefreshFolds => write!(f, "RefreshFolds"),
+      AttachBuffer { ref buf } => write!(f, "AttachBuffer{{ buf: {:?} }}", buf),
+      DetachBuffer { ref buf } => write!(f, "DetachBuffer{{ buf: {:?} }}", buf),
+      RefreshFolds { .. } => write!(f, "RefreshFolds"),
+      Stats { .. } => write!(f, "Stats"),
+      ExportVtk { ref path, .. } => write!(f, "ExportVtk{{ path: {} }}", path),
       Quit => write!(f, "Quit"),
     }
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use neovim_lib::{neovim_api::Buffer, Value};
+
+  use crate::{
+    bufdata::BufData,
+    event::{collect_lines, find, BufDatas},
+    handler::parse_buf,
+  };
+
+  fn strings(v: &[&str]) -> Vec<String> {
+    v.iter().map(|s| s.to_string()).collect()
+  }
+
+  #[test]
+  fn bufdatas_are_found_by_handle() {
+    let buf = parse_buf(Value::Ext(0, vec![0x03]));
+    let mut bufdatas: BufDatas = BufDatas::new();
+    let _ = bufdatas.insert(3, BufData::new(&buf));
+
+    assert!(find(&mut bufdatas, &Buffer::new(Value::from(3))).is_some());
+    assert!(find(&mut bufdatas, &Buffer::new(Value::from(4))).is_none());
+    assert!(find(&mut bufdatas, &Buffer::new(Value::from("x"))).is_none());
+  }
+
+  #[test]
+  fn split_updates_are_collected() {
+    let buf0 = Buffer::new(Value::from(0_usize));
+    let buf1 = Buffer::new(Value::from(1_usize));
+    let mut pending = vec![];
+
+    assert_eq!(
+      collect_lines(&mut pending, buf0.clone(), 0, -1, strings(&["a"]), true),
+      None
+    );
+    assert_eq!(
+      collect_lines(&mut pending, buf1.clone(), 2, 3, strings(&["x"]), false),
+      Some((2, 3, strings(&["x"])))
+    );
+    assert_eq!(
+      collect_lines(&mut pending, buf0.clone(), 1, -1, strings(&["b"]), true),
+      None
+    );
+    assert_eq!(
+      collect_lines(&mut pending, buf0, 2, -1, strings(&["c"]), false),
+      Some((0, -1, strings(&["a", "b", "c"])))
+    );
+    assert!(pending.is_empty());
+  }
+}
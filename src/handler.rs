@@ -2,19 +2,20 @@
 //! excuted in another thread, so we use a
 //! [`Sender<Event>`](std::sync::mpsc::Sender) to send the parsed event data to
 //! the main thread.
-use std::sync::mpsc;
+use std::{fs, sync::mpsc};
 
+use byteorder::{BigEndian, ReadBytesExt};
 use failure::{self, Error};
 use log::{error, info};
 use neovim_lib::{neovim_api::Buffer, Handler, RequestHandler, Value};
 
-use crate::event::Event;
+use crate::{event::Event, nastran};
 
 /// The handler containing the sending end of a channel. The receiving end is
 /// the main [`event loop`](crate::event::Event::event_loop).
 pub struct NeovimHandler {
   pub to_main: mpsc::Sender<Event>,
-  pub from_main: mpsc::Receiver<Value>,
+  pub from_main: mpsc::Receiver<Result<Value, Value>>,
 }
 
 impl NeovimHandler {
@@ -67,7 +68,9 @@ impl NeovimHandler {
 
     let lastline = parse_i64(&last_arg(&mut args, nea)?)?;
     let firstline = parse_i64(&last_arg(&mut args, nea)?)?;
+    let buf = parse_buf(last_arg(&mut args, nea)?);
     Ok(Event::HighlightRegion {
+      buf,
       firstline,
       lastline,
     })
@@ -85,6 +88,24 @@ impl NeovimHandler {
   }
 }
 
+impl NeovimHandler {
+  /// Send an event for a request to the main thread and wait for the answer
+  fn request_main(&mut self, name: &str, event: Event) -> Result<Value, Value> {
+    self.to_main.send(event).map_err(|e| {
+      Value::from(format!(
+        "Could not send '{}' to main thread: {:?}!",
+        name, e
+      ))
+    })?;
+    self.from_main.recv().map_err(|e| {
+      Value::from(format!(
+        "Error receiving value for request '{}' from main thread: {:?}!",
+        name, e
+      ))
+    })?
+  }
+}
+
 impl Handler for NeovimHandler {
   fn handle_notify(&mut self, name: &str, args: Vec<Value>) {
     match name {
@@ -156,21 +177,34 @@ impl RequestHandler for NeovimHandler {
   fn handle_request(
     &mut self,
     name: String,
-    _args: Vec<Value>,
+    mut args: Vec<Value>,
   ) -> Result<Value, Value> {
     match name.as_str() {
-      "RefreshFolds" => {
-        self.to_main.send(Event::RefreshFolds).map_err(|e| {
-          Value::from(format!(
-            "Could not send 'RefreshFolds' to main thread: {:?}!",
-            e
-          ))
+      "RefreshFolds" | "Stats" | "AttachBuffer" | "DetachBuffer" => {
+        let nea = "Not enough arguments in request!";
+        let buf = last_arg(&mut args, nea).map(parse_buf).map_err(|e| {
+          Value::from(format!("Could not parse args of {}: {:?}!", name, e))
+        })?;
+        let event = match name.as_str() {
+          "RefreshFolds" => Event::RefreshFolds { buf },
+          "Stats" => Event::Stats { buf },
+          "AttachBuffer" => Event::AttachBuffer { buf },
+          _ => Event::DetachBuffer { buf },
+        };
+        self.request_main(&name, event)
+      }
+      "ExportVtk" => {
+        let event = parse_export_vtk(args).map_err(|e| {
+          Value::from(format!("Could not parse args of {}: {:?}!", name, e))
         })?;
-        self.from_main.recv().map_err(|e| {
-          Value::from(format!(
-            "Error receiving value for request '{}' from main thread: {:?}!",
-            name, e
-          ))
+        self.request_main(&name, event)
+      }
+      "ImportNastran" => {
+        let path = parse_import_nastran(args).map_err(|e| {
+          Value::from(format!("Could not parse args of {}: {:?}!", name, e))
+        })?;
+        import_nastran(&path).map_err(|e| {
+          Value::from(format!("Could not import '{}': {}", path, e))
         })
       }
       _ => Err(Value::from(format!("Unknown Request: '{}'!", name))),
@@ -178,6 +212,41 @@ impl RequestHandler for NeovimHandler {
   }
 }
 
+/// Parse the arguments of an ExportVtk request into an
+/// [`ExportVtk`](::event::Event::ExportVtk) event
+fn parse_export_vtk(mut args: Vec<Value>) -> Result<Event, Error> {
+  let nea = "Not enough arguments in ExportVtk request!";
+  let path = parse_string(last_arg(&mut args, nea)?)?;
+  let buf = parse_buf(last_arg(&mut args, nea)?);
+  Ok(Event::ExportVtk { buf, path })
+}
+
+/// Parse the arguments of an ImportNastran request into the path of the file
+fn parse_import_nastran(mut args: Vec<Value>) -> Result<String, Error> {
+  let nea = "Not enough arguments in ImportNastran request!";
+  parse_string(last_arg(&mut args, nea)?)
+}
+
+/// Convert a Nastran file, see [`nastran`](crate::nastran). Since this does not
+/// need the buffer, it's done directly in the handler thread. Returns a list
+/// of the converted lines and a list of messages about cards that could not be
+/// converted.
+fn import_nastran(path: &str) -> Result<Value, Error> {
+  let input = fs::read(path)?;
+  let conversion = nastran::convert(&String::from_utf8_lossy(&input));
+
+  let lines: Vec<Value> = conversion.lines().map(Value::from).collect();
+  let messages: Vec<Value> = conversion
+    .errors
+    .iter()
+    .map(|(linenr, msg)| format!("{}:{}: {}", path, linenr, msg))
+    .chain(conversion.to_string().lines().map(String::from))
+    .map(Value::from)
+    .collect();
+
+  Ok(Value::from(vec![Value::from(lines), Value::from(messages)]))
+}
+
 /// Helper function to get the last argument of a `Vec<Value>` or return an
 /// error message
 fn last_arg(v: &mut Vec<Value>, errmsg: &'static str) -> Result<Value, Error> {
@@ -205,6 +274,19 @@ fn parse_bool(value: &Value) -> Result<bool, Error> {
   })
 }
 
+/// Parse a [`neovim_lib::Value`](neovim_lib::Value) into a String
+fn parse_string(value: Value) -> Result<String, Error> {
+  match value {
+    Value::String(s) => s
+      .into_str()
+      .ok_or_else(|| failure::err_msg("Non-utf8 value in string")),
+    v => Err(failure::err_msg(format!(
+      "Cannot parse '{:?}' as string",
+      v
+    ))),
+  }
+}
+
 /// Parse a [`neovim_lib::Value`](neovim_lib::Value) into a `Vec<String>`. Note
 /// that this method takes ownership of the value so it does not need to copy
 /// out the contained strings
@@ -237,6 +319,54 @@ fn parse_vecstr(value: Value) -> Result<Vec<String>, Error> {
 /// [`neovim_lib::Buffer`](neovim_lib::neovim_api::Buffer). This cannot fail,
 /// but if the Value was not obtained from the rpc api, this will probably not
 /// be a valid buffer to send commands to.
-fn parse_buf(value: Value) -> Buffer {
-  Buffer::new(value)
+///
+/// Neovim sends buffers as msgpack EXT values, but buffer numbers sent from
+/// lua are integers. Neovim accepts both, so we convert EXT values to integers
+/// to be able to compare them.
+pub fn parse_buf(value: Value) -> Buffer {
+  let handle = match value {
+    Value::Ext(_, ref data) => ext_handle(data),
+    _ => None,
+  };
+
+  Buffer::new(handle.map_or(value, Value::from))
+}
+
+/// Decode the msgpack encoded integer that is the data of an EXT value
+fn ext_handle(data: &[u8]) -> Option<i64> {
+  let (&first, mut rest) = data.split_first()?;
+
+  match first {
+    0x00..=0x7f => Some(i64::from(first)),
+    0xe0..=0xff => Some(i64::from(first as i8)),
+    0xcc => rest.read_u8().ok().map(i64::from),
+    0xcd => rest.read_u16::<BigEndian>().ok().map(i64::from),
+    0xce => rest.read_u32::<BigEndian>().ok().map(i64::from),
+    0xcf => rest.read_u64::<BigEndian>().ok().map(|u| u as i64),
+    0xd0 => rest.read_i8().ok().map(i64::from),
+    0xd1 => rest.read_i16::<BigEndian>().ok().map(i64::from),
+    0xd2 => rest.read_i32::<BigEndian>().ok().map(i64::from),
+    0xd3 => rest.read_i64::<BigEndian>().ok(),
+    _ => None,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use neovim_lib::{neovim_api::Buffer, Value};
+
+  use crate::handler::parse_buf;
+
+  #[test]
+  fn buffers_compare_to_numbers() {
+    assert_eq!(
+      parse_buf(Value::Ext(0, vec![0x03])),
+      Buffer::new(Value::from(3))
+    );
+    assert_eq!(
+      parse_buf(Value::Ext(0, vec![0xcd, 0x01, 0x00])),
+      Buffer::new(Value::from(256))
+    );
+    assert_eq!(parse_buf(Value::from(7)), Buffer::new(Value::from(7)));
+  }
 }
@@ -8,7 +8,10 @@ pub mod bufdata;
 pub mod card;
 pub mod event;
 pub mod handler;
+pub mod lineid;
 pub mod linenr;
 pub mod lines;
 pub mod linesiter;
+pub mod lsp;
+pub mod nastran;
 pub mod skipresult;
@@ -0,0 +1,38 @@
+//! The struct for line IDs.
+use std::fmt;
+
+/// An immutable identifier for a line of the buffer that is not a comment.
+/// While the [`LineNr`](crate::linenr::LineNr) of a line changes whenever
+/// lines are added or removed before it, the `LineId` stays the same until the
+/// line is removed. [`Lines`](crate::lines::Lines) provides the map from IDs to
+/// line numbers.
+///
+/// IDs of removed lines get reused for new ones, so they should not be kept
+/// around longer than the lines they belong to.
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy)]
+pub struct LineId(u32);
+
+impl LineId {
+  pub fn from_usize(num: usize) -> Self {
+    debug_assert!(num < u32::max_value() as usize, "Got a usize >= u32::MAX!");
+    LineId(num as u32)
+  }
+}
+
+impl From<LineId> for usize {
+  fn from(l: LineId) -> Self {
+    l.0 as usize
+  }
+}
+
+impl From<usize> for LineId {
+  fn from(num: usize) -> Self {
+    LineId::from_usize(num)
+  }
+}
+
+impl fmt::Display for LineId {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "#{}", self.0)
+  }
+}
@@ -1,13 +1,31 @@
 //! This module holds the datastructure for the Lines of the buffer.
 //!
-//! Future ideas, if performance isn't enough: Skip list, gap buffer (adapted to
-//! lines instead of strings), rope (adapted to lines instead of strings)
-use std::{convert::AsRef, fmt, ops::Deref, slice};
+//! The lines are saved in chunks spanning about
+//! [`CHUNK_SIZE`](crate::lines::CHUNK_SIZE) lines of the buffer. The line
+//! numbers inside a chunk are relative to its start, and the starts of the
+//! chunks are kept in a [Fenwick tree](https://en.wikipedia.org/wiki/Fenwick_tree).
+//! That way, an update only needs to touch the chunks it changes, and shifting
+//! the chunks after it is logarithmic in their number, so the cost of an update
+//! does not depend on where in the buffer it happens. Chunks emptied by an
+//! update are kept as placeholders, to be filled by later updates, so only
+//! updates adding more chunks than there are around need to rebuild the index.
+//!
+//! Every line that is not a comment gets a [`LineId`](crate::lineid::LineId)
+//! that does not change when other lines are added or removed, together with a
+//! map from those IDs to the position of the line.
+use std::{collections::HashMap, convert::AsRef, fmt, iter, mem, ops::Range};
 
-use crate::{card::keyword::Keyword, linenr::LineNr, linesiter::LinesIter};
+use crate::{
+  card::keyword::Keyword, lineid::LineId, linenr::LineNr, linesiter::LinesIter,
+};
 
 use memchr;
 
+/// The number of lines of the buffer a chunk spans before a new one is
+/// started. Updates may let chunks grow to twice that size before they're
+/// split up.
+pub const CHUNK_SIZE: usize = 1024;
+
 /// An enum representing a line of a file, either as a byte slice (which we
 /// obtain from reading a file into a `Vec<u8>` and splitting on newlines) or an
 /// owned `String` (which we get from neovim's buffer update API via a
@@ -18,12 +36,14 @@ pub enum RawLine<'a> {
   ChangedLine(String),
 }
 
-/// A struct to hold the data of a [`RawLine`](crate::lines::RawLine) that has
-/// been [`parse`](crate::card::keyword::Keyword::parse)d before.
-#[derive(PartialEq, Debug)]
+/// A line of the buffer that has been
+/// [`parse`](crate::card::keyword::Keyword::parse)d before, as returned by
+/// the methods of [`Lines`](crate::lines::Lines).
+#[derive(PartialEq, Debug, Clone, Copy)]
 pub struct ParsedLine<'a> {
+  pub id: LineId,
   pub number: LineNr,
-  pub text: RawLine<'a>,
+  pub text: &'a [u8],
   pub keyword: Option<Keyword>,
 }
 
@@ -32,14 +52,68 @@ pub struct ParsedLine<'a> {
 /// starts with a [`Keyword`](crate::card::keyword::Keyword).
 #[derive(PartialEq, Debug)]
 pub struct KeywordLine<'a> {
+  pub id: LineId,
   pub number: LineNr,
   pub text: &'a [u8],
   pub keyword: Keyword,
 }
 
-/// The struct to hold the lines.
-#[derive(Debug, Default, PartialEq)]
-pub struct Lines<'a>(Vec<ParsedLine<'a>>);
+/// A line as it is saved in a [`Chunk`](crate::lines::Chunk). The line number
+/// is relative to the start of the chunk.
+#[derive(Debug)]
+struct StoredLine<'a> {
+  id: LineId,
+  offset: LineNr,
+  text: RawLine<'a>,
+  keyword: Option<Keyword>,
+}
+
+/// A consecutive part of the buffer. `numlines` counts all the lines of the
+/// buffer the chunk spans, including comments. The `key` identifies the chunk
+/// while its index in [`Lines`](crate::lines::Lines) changes. A chunk spanning
+/// no lines is a placeholder that can be reused by an update.
+#[derive(Debug, Default)]
+struct Chunk<'a> {
+  key: usize,
+  lines: Vec<StoredLine<'a>>,
+  numlines: usize,
+}
+
+/// Where to find a line: The key of its chunk, and its index in there.
+#[derive(Debug, Clone, Copy)]
+struct Location {
+  chunk: u32,
+  pos: u32,
+}
+
+/// A [Fenwick tree](https://en.wikipedia.org/wiki/Fenwick_tree) over the
+/// chunks, holding the number of lines of the buffer and the number of saved
+/// lines for each of them. Allows computing the start of a chunk, finding the
+/// chunk for a given line, and changing the size of a chunk in logarithmic
+/// time.
+#[derive(Debug, Default)]
+struct ChunkIndex {
+  // 1-based, tree[0] is unused
+  tree: Vec<(usize, usize)>,
+}
+
+/// The struct to hold the lines. Comments are not saved, but we keep track of
+/// the total number of lines of the buffer.
+///
+/// The chunks span the whole buffer without gaps, the first one starts at line
+/// 0. `locations` is indexed by the [`LineId`](crate::lineid::LineId)s, and
+/// `chunk_pos` maps the keys of the chunks to their index.
+#[derive(Debug, Default)]
+pub struct Lines<'a> {
+  chunks: Vec<Chunk<'a>>,
+  index: ChunkIndex,
+  numlines: usize,
+  len: usize,
+  locations: Vec<Option<Location>>,
+  free_ids: Vec<LineId>,
+  chunk_pos: HashMap<usize, usize>,
+  next_key: usize,
+}
 
 impl<'a> AsRef<[u8]> for RawLine<'a> {
   fn as_ref(&self) -> &[u8] {
@@ -51,106 +125,320 @@ impl<'a> AsRef<[u8]> for RawLine<'a> {
   }
 }
 
-impl<'a> ParsedLine<'a> {
-  fn shift(&mut self, added: isize) {
-    self.number += added;
+/// Check if a line is a comment, i.e. starts with `$` or `#`
+fn is_comment(text: &[u8]) -> bool {
+  let first = text.first();
+  first == Some(&b'$') || first == Some(&b'#')
+}
+
+impl<'a> StoredLine<'a> {
+  fn new(id: LineId, offset: LineNr, text: RawLine<'a>) -> Self {
+    StoredLine {
+      id,
+      offset,
+      keyword: Keyword::parse(text.as_ref()),
+      text,
+    }
+  }
+
+  fn parsed(&self, start: LineNr) -> ParsedLine<'_> {
+    ParsedLine {
+      id: self.id,
+      number: start + self.offset,
+      text: self.text.as_ref(),
+      keyword: self.keyword,
+    }
   }
+}
 
+impl<'a> ParsedLine<'a> {
   /// Try to convert the [`ParsedLine`](crate::lines::ParsedLine) into a
   /// [`KeywordLine`](crate::lines::KeywordLine). This is of course possible if
   /// and only if the [`keyword`](crate::lines::ParsedLine::keyword) is
   /// `Some(kw)`.
-  pub fn try_into_keywordline(&'a self) -> Option<KeywordLine<'a>> {
-    if let Some(kw) = self.keyword {
-      return Some(KeywordLine {
-        number: self.number,
-        text: self.text.as_ref(),
-        keyword: kw,
-      });
-    } else {
-      return None;
+  pub fn try_into_keywordline(self) -> Option<KeywordLine<'a>> {
+    self.keyword.map(|kw| KeywordLine {
+      id: self.id,
+      number: self.number,
+      text: self.text,
+      keyword: kw,
+    })
+  }
+}
+
+impl<'a, 'b> From<&'b KeywordLine<'a>> for ParsedLine<'a> {
+  fn from(k: &'b KeywordLine<'a>) -> Self {
+    ParsedLine {
+      id: k.id,
+      number: k.number,
+      text: k.text,
+      keyword: Some(k.keyword),
     }
   }
 }
 
+impl ChunkIndex {
+  fn new(chunks: &[Chunk]) -> Self {
+    let mut tree = Vec::with_capacity(chunks.len() + 1);
+    tree.push((0, 0));
+    tree.extend(chunks.iter().map(|c| (c.numlines, c.lines.len())));
+
+    for i in 1..tree.len() {
+      let parent = i + lowbit(i);
+      if parent < tree.len() {
+        tree[parent].0 += tree[i].0;
+        tree[parent].1 += tree[i].1;
+      }
+    }
+
+    ChunkIndex { tree }
+  }
+
+  /// Change the number of lines of the chunk with index `c` by `lines`, and its
+  /// number of saved lines by `saved`.
+  fn add(&mut self, c: usize, lines: isize, saved: isize) {
+    let mut i = c + 1;
+    while i < self.tree.len() {
+      let t = &mut self.tree[i];
+      // The sums are numbers of lines of the buffer before and after the
+      // update, so the casts are lossless
+      t.0 = (t.0 as isize + lines) as usize;
+      t.1 = (t.1 as isize + saved) as usize;
+      i += lowbit(i);
+    }
+  }
+
+  /// The number of the first line of the chunk with index `c`, and the index
+  /// of its first saved line.
+  fn start(&self, c: usize) -> (LineNr, usize) {
+    let (mut line, mut idx) = (0, 0);
+    let mut i = c;
+    while i > 0 {
+      line += self.tree[i].0;
+      idx += self.tree[i].1;
+      i -= lowbit(i);
+    }
+
+    (line.into(), idx)
+  }
+
+  /// Find the last chunk starting at or before `target`, where the start is
+  /// measured by `key` (lines of the buffer or saved lines). Returns 0 if
+  /// there are no chunks.
+  fn find<F>(&self, target: usize, key: F) -> usize
+  where
+    F: Fn(&(usize, usize)) -> usize,
+  {
+    let numchunks = self.tree.len().saturating_sub(1);
+    let mut pos = 0;
+    let mut rem = target;
+    let mut step = numchunks.next_power_of_two();
+
+    while step > 0 {
+      if pos + step <= numchunks && key(&self.tree[pos + step]) <= rem {
+        pos += step;
+        rem -= key(&self.tree[pos]);
+      }
+      step /= 2;
+    }
+
+    pos.min(numchunks.saturating_sub(1))
+  }
+}
+
+/// The value of the lowest set bit of `i`
+fn lowbit(i: usize) -> usize {
+  i & i.wrapping_neg()
+}
+
+/// The difference `a - b` of two numbers of lines. Those are bounded by the
+/// length of a buffer, which is far below `isize::MAX`, so the casts are
+/// lossless.
+fn diff(a: usize, b: usize) -> isize {
+  a as isize - b as isize
+}
+
+/// The [`Location`](crate::lines::Location) of the line at index `pos` of
+/// the chunk with the given key. The index is below `2 * CHUNK_SIZE`. Keys
+/// are only handed out for chunks created while parsing, and for chunks added
+/// by updates inserting more than `CHUNK_SIZE` lines, so they stay far below
+/// `u32::MAX`.
+fn location(key: usize, pos: usize) -> Location {
+  debug_assert!(key <= u32::max_value() as usize);
+  Location {
+    chunk: key as u32,
+    pos: pos as u32,
+  }
+}
+
 impl<'a> Lines<'a> {
   pub fn new() -> Self {
-    Lines(vec![])
+    Lines::default()
   }
 
   pub fn is_empty(&self) -> bool {
-    self.0.is_empty()
+    self.len == 0
   }
 
   pub fn clear(&mut self) {
-    self.0.clear()
+    *self = Lines::default();
   }
+
   pub fn len(&self) -> usize {
-    self.0.len()
+    self.len
+  }
+
+  /// The number of lines of the buffer, including comments.
+  pub fn numlines(&self) -> usize {
+    self.numlines
+  }
+
+  /// The number of comment lines of the buffer.
+  pub fn numcomments(&self) -> usize {
+    self.numlines - self.len
+  }
+
+  /// Get an unused [`LineId`](crate::lineid::LineId). Its location needs to
+  /// be set afterwards.
+  fn new_id(&mut self) -> LineId {
+    self.free_ids.pop().unwrap_or_else(|| {
+      self.locations.push(None);
+      LineId::from_usize(self.locations.len() - 1)
+    })
+  }
+
+  /// Give IDs to new lines. The IDs of the removed lines they replace are
+  /// reused in order, so changing a line keeps its ID.
+  fn assign_ids(&mut self, lines: &mut [StoredLine], removed: Vec<LineId>) {
+    let mut removed = removed.into_iter();
+
+    for line in lines {
+      line.id = match removed.next() {
+        Some(id) => id,
+        None => self.new_id(),
+      };
+    }
+
+    for id in removed {
+      self.locations[usize::from(id)] = None;
+      self.free_ids.push(id);
+    }
+  }
+
+  fn new_chunk(&mut self) -> Chunk<'a> {
+    self.next_key += 1;
+    Chunk {
+      key: self.next_key - 1,
+      ..Default::default()
+    }
+  }
+
+  /// Set the locations of the lines of the chunk with index `c`, starting with
+  /// its line at index `from`.
+  fn locate(&mut self, c: usize, from: usize) {
+    let chunk = &self.chunks[c];
+
+    for (pos, line) in chunk.lines.iter().enumerate().skip(from) {
+      self.locations[usize::from(line.id)] = Some(location(chunk.key, pos));
+    }
+  }
+
+  /// Rebuild the [`ChunkIndex`](crate::lines::ChunkIndex) and the positions
+  /// of the chunks after chunks have been added. Updates only do this if they
+  /// need more chunks than they can reuse, see
+  /// [`update`](crate::lines::Lines::update).
+  fn reindex(&mut self) {
+    self.index = ChunkIndex::new(&self.chunks);
+    self.chunk_pos = self
+      .chunks
+      .iter()
+      .enumerate()
+      .map(|(i, c)| (c.key, i))
+      .collect();
+  }
+
+  /// Append lines to the buffer, continuing the last chunk until it's full.
+  /// Comments are counted, but not saved.
+  fn extend<I>(&mut self, lines: I)
+  where
+    I: Iterator<Item = RawLine<'a>>,
+  {
+    let mut start =
+      self.numlines - self.chunks.last().map_or(0, |c| c.numlines);
+    let mut number = self.numlines;
+
+    for text in lines {
+      if !is_comment(text.as_ref()) {
+        if self.chunks.is_empty() {
+          let chunk = self.new_chunk();
+          self.chunks.push(chunk);
+        } else if number - start >= CHUNK_SIZE {
+          if let Some(chunk) = self.chunks.last_mut() {
+            chunk.numlines = number - start;
+          }
+          start = number;
+          let chunk = self.new_chunk();
+          self.chunks.push(chunk);
+        }
+
+        let id = self.new_id();
+        let chunk = self.chunks.last_mut().expect("Chunk was pushed above");
+        self.locations[usize::from(id)] =
+          Some(location(chunk.key, chunk.lines.len()));
+        chunk
+          .lines
+          .push(StoredLine::new(id, (number - start).into(), text));
+        self.len += 1;
+      }
+      number += 1;
+    }
+
+    // The first chunk always starts at 0, even if all lines are comments
+    if self.chunks.is_empty() && number > 0 {
+      let chunk = self.new_chunk();
+      self.chunks.push(chunk);
+    }
+    if let Some(chunk) = self.chunks.last_mut() {
+      chunk.numlines = number - start;
+    }
+
+    self.numlines = number;
+    self.reindex();
   }
 
   /// Extend a [`Lines`](crate::lines::Lines) struct from a `Vec<String>`
   pub fn parse_vec(&mut self, v: Vec<String>) {
-    self.0.extend(
-      v.into_iter()
-        .enumerate()
-        .filter(|(_, s)| {
-          let first = s.as_bytes().get(0);
-          first != Some(&b'$') && first != Some(&b'#')
-        })
-        .map(|(i, s)| ParsedLine {
-          number: i.into(),
-          keyword: Keyword::parse(s.as_ref()),
-          text: RawLine::ChangedLine(s),
-        }),
-    );
+    self.extend(v.into_iter().map(RawLine::ChangedLine));
   }
 
   /// Extend a [`Lines`](crate::lines::Lines) struct from a slice of `&'str`s
   pub fn parse_strs<'c: 'a>(&mut self, v: &'c [&'a str]) {
-    self.0.extend(
-      v.iter()
-        .enumerate()
-        .filter(|(_, s)| {
-          let first = s.as_bytes().get(0_usize);
-          first != Some(&b'$') && first != Some(&b'#')
-        })
-        .map(|(i, l)| ParsedLine {
-          number: i.into(),
-          text: RawLine::OriginalLine(l.as_ref()),
-          keyword: Keyword::parse(l.as_ref()),
-        }),
-    );
+    self.extend(v.iter().map(|l| RawLine::OriginalLine(l.as_ref())));
   }
 
   /// Extend a [`Lines`](crate::lines::Lines) struct from a byte slice by
   /// splitting on newlines.
   pub fn parse_slice<'c: 'a>(&mut self, mut v: &'c [u8]) {
-    let mut lineidx = 0usize;
-
-    while let Some(nl) = memchr::memchr(b'\n', v) {
-      let first = v.get(0_usize).expect("Memchr found slice nonempty");
-      if first != &b'$' && first != &b'#' {
-        let l = &v[..nl];
-        self.0.push(ParsedLine {
-          number: lineidx.into(),
-          text: RawLine::OriginalLine(l),
-          keyword: Keyword::parse(l),
-        });
+    self.extend(iter::from_fn(move || {
+      if v.is_empty() {
+        return None;
       }
-      lineidx += 1;
-      v = &v[nl + 1..];
-    }
 
-    let first = v.get(0_usize);
+      let line = match memchr::memchr(b'\n', v) {
+        Some(nl) => {
+          let l = &v[..nl];
+          v = &v[nl + 1..];
+          l
+        }
+        None => {
+          let l = v;
+          v = &[];
+          l
+        }
+      };
 
-    if first.is_some() && first != Some(&b'$') && first != Some(&b'#') {
-      self.0.push(ParsedLine {
-        number: lineidx.into(),
-        text: RawLine::OriginalLine(v),
-        keyword: Keyword::parse(v),
-      });
-    }
+      Some(RawLine::OriginalLine(line))
+    }));
   }
 
   /// Update Lines:
@@ -159,6 +447,11 @@ impl<'a> Lines<'a> {
   /// This are the exact conditions to use the range `first..last` together with
   /// `splice` on a `Vec`.
   /// Returns the change in length after removing comments
+  ///
+  /// Only the chunks containing `first..last` are rebuilt, the following ones
+  /// just get a new start. The rebuilt chunks take the places of the old ones
+  /// and of placeholders next to them, only if that's not enough, new chunks
+  /// are inserted and the index is rebuilt.
   pub fn update(
     &mut self,
     linedata: Vec<String>,
@@ -166,50 +459,316 @@ impl<'a> Lines<'a> {
     last: LineNr,
     added: isize,
   ) -> isize {
-    let startidx = self.linenr_to_index(first);
-    let endidx = self.linenr_to_index(last);
+    let mut c0 = self.chunk_of_line(first);
+    let mut c1 = if last > first {
+      self.chunk_of_line(last.prev())
+    } else {
+      c0
+    };
+    c1 = (c1 + 1).min(self.chunks.len());
 
-    let indexrange = startidx..endidx;
+    let (start, _) = self.index.start(c0);
+    let first_rel = LineNr::from_isize(first - start);
+    let last_rel = LineNr::from_isize(last - start);
 
-    if added != 0 {
-      for line in self.0[indexrange.end..].iter_mut() {
-        line.shift(added);
+    let oldspan: usize = self.chunks[c0..c1].iter().map(|c| c.numlines).sum();
+    // Cast is lossless, see the comment for [`LineNr`](crate::linenr::LineNr)
+    let span = (oldspan as isize + added) as usize;
+    self.numlines = (self.numlines as isize + added) as usize;
+
+    // The IDs are set later, when we know the lines that got removed
+    let mut newlines: Vec<_> = linedata
+      .into_iter()
+      .enumerate()
+      .filter(|(_, s)| !is_comment(s.as_ref()))
+      .map(|(i, s)| {
+        StoredLine::new(
+          LineId::from_usize(0),
+          first_rel + LineNr::from_usize(i),
+          RawLine::ChangedLine(s),
+        )
+      })
+      .collect();
+    let inserted = newlines.len();
+
+    // If the update only touches one chunk, and that chunk keeps a sensible
+    // size, we can splice the lines in directly. Otherwise, rebuild all chunks
+    // involved, adding the next one if they would get too small.
+    let removed = if c1 == c0 + 1
+      && span > 0
+      && span <= 2 * CHUNK_SIZE
+      && (span >= CHUNK_SIZE / 2 || c1 == self.chunks.len())
+    {
+      let lines = &self.chunks[c0].lines;
+      let idx = |l: LineNr| {
+        lines
+          .binary_search_by_key(&l, |l| l.offset)
+          .unwrap_or_else(|e| e)
+      };
+      let (i0, i1) = (idx(first_rel), idx(last_rel));
+      let removed_ids = lines[i0..i1].iter().map(|l| l.id).collect();
+      self.assign_ids(&mut newlines, removed_ids);
+
+      let chunk = &mut self.chunks[c0];
+      for line in &mut chunk.lines[i1..] {
+        line.offset += added;
+      }
+      let _ = chunk.lines.splice(i0..i1, newlines);
+      chunk.numlines = span;
+      self.locate(c0, i0);
+      let removed = i1 - i0;
+
+      self.index.add(c0, added, diff(inserted, removed));
+      removed
+    } else {
+      let mut span = span;
+      while span < CHUNK_SIZE / 2 && c1 < self.chunks.len() {
+        span += self.chunks[c1].numlines;
+        c1 += 1;
+      }
+
+      // Take over the placeholders around the chunks if we need more places
+      let needed = numchunks(span);
+      while c1 - c0 < needed
+        && c1 < self.chunks.len()
+        && self.chunks[c1].numlines == 0
+      {
+        c1 += 1;
+      }
+      while c1 - c0 < needed && c0 > 0 && self.chunks[c0 - 1].numlines == 0 {
+        c0 -= 1;
       }
-    }
 
-    let mut newlines = Lines::new();
-    newlines.parse_vec(linedata);
+      let mut before = vec![];
+      let mut after = vec![];
+      let mut removed_ids = vec![];
+      let mut oldsizes = Vec::with_capacity(c1 - c0);
+      let mut chunkstart = 0;
 
-    // TODO(KillTheMule): What to do about these casts?
-    let new_nocomments = newlines.len() as isize - indexrange.len() as isize;
+      for chunk in &mut self.chunks[c0..c1] {
+        let offset = LineNr::from_usize(chunkstart);
+        chunkstart += chunk.numlines;
+        oldsizes.push((chunk.numlines, chunk.lines.len()));
+        chunk.numlines = 0;
 
-    let _ = self.0.splice(
-      indexrange,
-      newlines.0.into_iter().map(|mut p| {
-        p.number += first;
-        p
-      }),
-    );
+        for mut line in mem::replace(&mut chunk.lines, vec![]) {
+          line.offset += offset;
+          if line.offset < first_rel {
+            before.push(line);
+          } else if line.offset >= last_rel {
+            line.offset += added;
+            after.push(line);
+          } else {
+            removed_ids.push(line.id);
+          }
+        }
+      }
+      let removed = removed_ids.len();
+      self.assign_ids(&mut newlines, removed_ids);
 
-    new_nocomments
+      before.extend(newlines);
+      before.append(&mut after);
+
+      let mut newchunks = rechunk(before, span).into_iter();
+
+      // Fill the places we have, keeping their keys. The rest become
+      // placeholders.
+      for (i, (numlines, saved)) in oldsizes.into_iter().enumerate() {
+        let c = c0 + i;
+        if let Some(new) = newchunks.next() {
+          self.chunks[c].lines = new.lines;
+          self.chunks[c].numlines = new.numlines;
+        }
+        let chunk = &self.chunks[c];
+        let lines = diff(chunk.numlines, numlines);
+        self.index.add(c, lines, diff(chunk.lines.len(), saved));
+        self.locate(c, 0);
+      }
+
+      // If there are chunks left, insert them together with as many
+      // placeholders, so the next updates around here don't need to rebuild
+      // the index again.
+      let mut rest: Vec<_> = newchunks.collect();
+      if !rest.is_empty() {
+        let num = rest.len();
+        rest.extend((0..num).map(|_| Chunk::default()));
+        for chunk in &mut rest {
+          chunk.key = self.next_key;
+          self.next_key += 1;
+        }
+        let _ = self.chunks.splice(c1..c1, rest);
+        self.reindex();
+        for c in c1..c1 + num {
+          self.locate(c, 0);
+        }
+      }
+      removed
+    };
+
+    self.len = self.len + inserted - removed;
+
+    diff(inserted, removed)
+  }
+
+  /// The index of the chunk containing the given line. If the line is after
+  /// the end of the buffer, this is the last chunk.
+  fn chunk_of_line(&self, line: LineNr) -> usize {
+    self.index.find(line.into(), |t| t.0)
+  }
+
+  /// The index of the chunk containing the line with the given index.
+  fn chunk_of_index(&self, idx: usize) -> usize {
+    self.index.find(idx, |t| t.1)
+  }
+
+  /// Iterate over the lines of the chunk with the given index, together with
+  /// their indices.
+  fn chunk_iter(
+    &self,
+    c: usize,
+  ) -> impl DoubleEndedIterator<Item = (usize, ParsedLine<'_>)> + '_ {
+    let (start, idx) = self.index.start(c);
+    self.chunks[c]
+      .lines
+      .iter()
+      .enumerate()
+      .map(move |(i, l)| (idx + i, l.parsed(start)))
+  }
+
+  /// Iterate over the lines beginning with the one at index `idx`, together
+  /// with their indices.
+  fn indexed_from(
+    &self,
+    idx: usize,
+  ) -> impl Iterator<Item = (usize, ParsedLine<'_>)> + '_ {
+    (self.chunk_of_index(idx)..self.chunks.len())
+      .flat_map(move |c| self.chunk_iter(c))
+      .skip_while(move |(i, _)| *i < idx)
+  }
+
+  /// Iterate backwards over the lines before the one at index `idx`, together
+  /// with their indices.
+  fn indexed_before(
+    &self,
+    idx: usize,
+  ) -> impl Iterator<Item = (usize, ParsedLine<'_>)> + '_ {
+    let last = self.chunk_of_index(idx.saturating_sub(1)) + 1;
+
+    (0..last.min(self.chunks.len()))
+      .rev()
+      .flat_map(move |c| self.chunk_iter(c).rev())
+      .skip_while(move |(i, _)| *i >= idx)
   }
 
   /// Return an Iterator over the lines of a file.
-  pub fn iter<'b>(&'a self) -> LinesIter<'b, slice::Iter<'b, ParsedLine<'b>>>
-  where
-    'a: 'b,
-  {
-    LinesIter::new(self.0.iter())
+  pub fn iter(
+    &self,
+  ) -> LinesIter<'_, impl Iterator<Item = ParsedLine<'_>> + '_> {
+    self.iter_range(0..self.len)
   }
 
-  fn linenr_to_index(&self, line: LineNr) -> usize {
+  /// Return an Iterator over the lines with indices in the given range.
+  pub fn iter_range(
+    &self,
+    range: Range<usize>,
+  ) -> LinesIter<'_, impl Iterator<Item = ParsedLine<'_>> + '_> {
+    LinesIter::new(
+      self
+        .indexed_from(range.start)
+        .take(range.end.saturating_sub(range.start))
+        .map(|(_, l)| l),
+    )
+  }
+
+  /// Return an Iterator over the lines with line numbers in the given range.
+  pub fn iter_lines(
+    &self,
+    range: Range<LineNr>,
+  ) -> LinesIter<'_, impl Iterator<Item = ParsedLine<'_>> + '_> {
+    let start = self.linenr_to_index(range.start);
+    let end = self.linenr_to_index(range.end);
+
+    self.iter_range(start..end)
+  }
+
+  /// Return the line with the given index.
+  pub fn get(&self, idx: usize) -> Option<ParsedLine<'_>> {
+    if idx >= self.len {
+      return None;
+    }
+    let c = self.chunk_of_index(idx);
+    let (start, startidx) = self.index.start(c);
+
+    self.chunks[c]
+      .lines
+      .get(idx - startidx)
+      .map(|l| l.parsed(start))
+  }
+
+  /// Return the last line that is not a comment.
+  pub fn last(&self) -> Option<ParsedLine<'_>> {
+    self.len.checked_sub(1).and_then(|i| self.get(i))
+  }
+
+  /// Return the line with the given number, if it is not a comment.
+  pub fn get_line(&self, line: LineNr) -> Option<ParsedLine<'_>> {
+    let c = self.chunk_of_line(line);
+    let lines = &self.chunks.get(c)?.lines;
+    let (start, _) = self.index.start(c);
+    let offset = LineNr::from_isize(line - start);
+
+    lines
+      .binary_search_by_key(&offset, |l| l.offset)
+      .ok()
+      .map(|i| lines[i].parsed(start))
+  }
+
+  /// Return the line with the given [`LineId`](crate::lineid::LineId), if
+  /// it is still part of the buffer.
+  pub fn get_by_id(&self, id: LineId) -> Option<ParsedLine<'_>> {
+    let loc = (*self.locations.get(usize::from(id))?)?;
+    let c = self.chunk_pos[&(loc.chunk as usize)];
+    let (start, _) = self.index.start(c);
+
+    Some(self.chunks[c].lines[loc.pos as usize].parsed(start))
+  }
+
+  /// Return the number of the line with the given
+  /// [`LineId`](crate::lineid::LineId), if it is still part of the buffer.
+  pub fn linenr(&self, id: LineId) -> Option<LineNr> {
+    self.get_by_id(id).map(|l| l.number)
+  }
+
+  /// Return the last line before the line with the given number that is not a
+  /// comment.
+  pub fn last_line_before(&self, line: LineNr) -> Option<ParsedLine<'_>> {
     self
-      .0
-      .binary_search_by_key(&line, |l| l.number)
-      .unwrap_or_else(|e| e)
+      .linenr_to_index(line)
+      .checked_sub(1)
+      .and_then(|i| self.get(i))
+  }
+
+  /// Return the first line that is not a comment, starting with the line with
+  /// the given number.
+  pub fn first_line_from(&self, line: LineNr) -> Option<ParsedLine<'_>> {
+    self.get(self.linenr_to_index(line))
+  }
+
+  fn linenr_to_index(&self, line: LineNr) -> usize {
+    let c = self.chunk_of_line(line);
+    if self.chunks.is_empty() {
+      return 0;
+    }
+    let (start, startidx) = self.index.start(c);
+    let offset = LineNr::from_isize((line - start).max(0));
+
+    startidx
+      + self.chunks[c]
+        .lines
+        .binary_search_by_key(&offset, |l| l.offset)
+        .unwrap_or_else(|e| e)
   }
 
-  // TODO(KillTheMule): Efficient? This is called a lot ...
   // TODO(KillTheMule): This should return an option... none if empty
   /// Find the index of the first line that starts with a non-comment keyword
   /// before the line with the given number. If the line with the given number
@@ -222,18 +781,14 @@ impl<'a> Lines<'a> {
       line_index += 1;
     }
     self
-      .get(0..line_index)
-      .unwrap_or(&[])
-      .iter()
-      .enumerate()
-      .rfind(|(_, l)| l.keyword.is_some())
+      .indexed_before(line_index)
+      .find(|(_, l)| l.keyword.is_some())
       .map(|(i, l)| (i, l.number))
       .unwrap_or_else(|| {
         self.get(0).map_or((0, 0_usize.into()), |l| (0, l.number))
       })
   }
 
-  // TODO(KillTheMule): Efficient? This is called a lot ...
   // TODO(KillTheMule): This should return an option... none if empty
   /// Find the index of the next line that starts with a non-comment keyword
   /// after the line with the given number. If the line with the given number
@@ -244,24 +799,75 @@ impl<'a> Lines<'a> {
       (0_usize, 0_usize.into())
     } else {
       self
-        .iter()
-        .enumerate()
-        .skip(to_skip)
+        .indexed_from(to_skip)
         .find(|(_, l)| l.keyword.is_some())
         .map(|(i, l)| (i, l.number))
         .unwrap_or_else(|| {
-          let len = self.len();
-          (len, self[len - 1].number + 1)
+          let last = self.last().expect("Lines are not empty");
+          (self.len(), last.number + 1)
         })
     }
   }
 }
 
-impl<'a> Deref for Lines<'a> {
-  type Target = [ParsedLine<'a>];
+/// The number of chunks [`rechunk`](crate::lines::rechunk) splits `span`
+/// lines of the buffer into.
+fn numchunks(span: usize) -> usize {
+  if span == 0 {
+    0
+  } else if span <= 2 * CHUNK_SIZE {
+    1
+  } else {
+    span / CHUNK_SIZE
+  }
+}
+
+/// Split lines into chunks. The offsets of the lines are relative to the start
+/// of the first chunk, `span` is the number of lines of the buffer they cover.
+fn rechunk(lines: Vec<StoredLine>, span: usize) -> Vec<Chunk> {
+  let numchunks = numchunks(span);
+  if numchunks <= 1 {
+    return if span == 0 {
+      vec![]
+    } else {
+      vec![Chunk {
+        lines,
+        numlines: span,
+        ..Default::default()
+      }]
+    };
+  }
+
+  let mut chunks: Vec<Chunk> = (0..numchunks)
+    .map(|i| Chunk {
+      key: 0,
+      lines: vec![],
+      numlines: if i + 1 == numchunks {
+        span - i * CHUNK_SIZE
+      } else {
+        CHUNK_SIZE
+      },
+    })
+    .collect();
+
+  for mut line in lines {
+    let c = (usize::from(line.offset) / CHUNK_SIZE).min(numchunks - 1);
+    line.offset += -((c * CHUNK_SIZE) as isize);
+    chunks[c].lines.push(line);
+  }
+
+  chunks
+}
 
-  fn deref(&self) -> &[ParsedLine<'a>] {
-    &self.0
+impl<'a> PartialEq for Lines<'a> {
+  /// Two [`Lines`](crate::lines::Lines) are equal if they hold the same lines,
+  /// no matter how they are split into chunks or which IDs they got.
+  fn eq(&self, other: &Self) -> bool {
+    self.numlines == other.numlines
+      && self
+        .iter()
+        .map(|l| (l.number, l.text, l.keyword))
+        .eq(other.iter().map(|l| (l.number, l.text, l.keyword)))
   }
 }
 
@@ -279,7 +885,13 @@ impl<'a> fmt::Display for RawLine<'a> {
 
 impl<'a> fmt::Display for ParsedLine<'a> {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-    write!(f, "{{{}, {}, {:?}}}", self.number, self.text, self.keyword)
+    write!(
+      f,
+      "{{{}, {}, {:?}}}",
+      self.number,
+      String::from_utf8_lossy(self.text),
+      self.keyword
+    )
   }
 }
 
@@ -299,7 +911,7 @@ impl<'a> fmt::Display for Lines<'a> {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
     let mut s = String::new();
     s.push_str("Lines {{\n");
-    for line in &self.0 {
+    for line in self.iter() {
       s.push_str(&format!(" {}\n", line));
     }
     s.push_str("}}\n");
@@ -309,7 +921,10 @@ impl<'a> fmt::Display for Lines<'a> {
 
 #[cfg(test)]
 mod tests {
-  use crate::{linenr::LineNr, lines::Lines};
+  use crate::{
+    linenr::LineNr,
+    lines::{Lines, CHUNK_SIZE},
+  };
   use std::fs;
 
   const LINES: &str = "This\nis \nan \nexample \nof \nsome \nlines \n.";
@@ -349,8 +964,8 @@ mod tests {
 
     for i in 0..11 {
       assert_eq!(
-        (l[i].number, l[i].text.as_ref(), l[i].keyword),
-        (ln[i].number, ln[i].text.as_ref(), ln[i].keyword)
+        l.get(i).map(|p| (p.number, p.text, p.keyword)),
+        ln.get(i).map(|p| (p.number, p.text, p.keyword))
       );
     }
   }
@@ -374,8 +989,8 @@ mod tests {
 
     for i in 0..5 {
       assert_eq!(
-        (i, l[i].number, l[i].text.as_ref(), l[i].keyword),
-        (i, ln[i].number, ln[i].text.as_ref(), ln[i].keyword)
+        l.get(i).map(|p| (i, p.number, p.text, p.keyword)),
+        ln.get(i).map(|p| (i, p.number, p.text, p.keyword))
       );
     }
   }
@@ -385,11 +1000,151 @@ mod tests {
     let v = fs::read(file!()).unwrap();
     let mut l = Lines::new();
     l.parse_slice(&v);
-    let f = "OriginalLine { //! This module holds the datastructure for the \
-             Lines of the buffer. }"
-      .to_string();
+    let f =
+      "//! This module holds the datastructure for the Lines of the buffer.";
+
+    assert_eq!(f.as_bytes(), l.get(0).unwrap().text);
+  }
+
+  const LINES_COMMENTS: &str = "#This\nis \n$an\nexample \n#of\n#comments\n";
+
+  #[test]
+  fn lines_count_comments() {
+    let mut l = Lines::new();
+    l.parse_slice(LINES_COMMENTS.as_ref());
+
+    assert_eq!(l.numlines(), 6);
+    assert_eq!(l.numcomments(), 4);
+
+    l.update(vec!["#new".to_string()], 1.into(), 4.into(), -2);
+    assert_eq!(l.numlines(), 4);
+    assert_eq!(l.numcomments(), 4);
+  }
+
+  #[test]
+  fn lines_update_empty() {
+    let mut l = Lines::new();
+    l.update(
+      vec!["NODE  / ".to_string(), "#c".to_string()],
+      0.into(),
+      0.into(),
+      2,
+    );
+
+    let mut ln = Lines::new();
+    ln.parse_slice(b"NODE  / \n#c");
+    assert_eq!(l, ln);
 
-    assert_eq!(f, format!("{}", l.0[0].text));
+    l.update(vec![], 0.into(), 2.into(), -2);
+    assert!(l.is_empty());
+    assert_eq!(l.numlines(), 0);
+    assert_eq!(l.first_after(0.into()), (0, 0.into()));
+  }
+
+  #[test]
+  fn lines_update_across_chunks() {
+    let mut model: Vec<String> = (0..5 * CHUNK_SIZE)
+      .map(|i| match i % 7 {
+        0 => format!("NODE  / {}", i),
+        3 => format!("#comment {}", i),
+        _ => format!("line {}", i),
+      })
+      .collect();
+    let mut l = Lines::new();
+    l.parse_vec(model.clone());
+
+    // Deterministic pseudo-random updates, some of them spanning several
+    // chunks, some deleting or inserting more than a chunk
+    let mut seed = 17_usize;
+    for n in 0..200 {
+      seed = (seed * 1103 + 12345) % 65536;
+      let first = seed % (model.len() + 1);
+      let len = (seed / 7) % (3 * CHUNK_SIZE);
+      let last =
+        (first + len % 40 * (n % 3) + len * (n % 5 / 4)).min(model.len());
+      let newlines: Vec<String> = (0..(seed / 3) % 50 + (n % 11 / 10) * len)
+        .map(|i| match i % 5 {
+          0 => format!("SHELL / {}", n),
+          1 => format!("$comment {}", n),
+          _ => format!("new {} {}", n, i),
+        })
+        .collect();
+      let added = newlines.len() as isize - (last - first) as isize;
+
+      // The lines after the update keep their IDs
+      let after = l
+        .first_line_from(last.into())
+        .map(|line| (line.id, line.number));
+
+      let _ = model.splice(first..last, newlines.iter().cloned());
+      l.update(newlines, first.into(), last.into(), added);
+
+      if let Some((id, number)) = after {
+        assert_eq!(l.linenr(id), Some(number + added), "Update {}", n);
+      }
+
+      let mut expected = Lines::new();
+      expected.parse_vec(model.clone());
+      assert_eq!(l, expected, "Update {} differs", n);
+      assert_eq!(l.len(), expected.len());
+      assert_eq!(l.numcomments(), expected.numcomments());
+    }
+
+    let mut expected = Lines::new();
+    expected.parse_vec(model.clone());
+    for i in (0..model.len()).step_by(97) {
+      let i = LineNr::from_usize(i);
+      assert_eq!(
+        l.get_line(i).map(|l| (l.number, l.text, l.keyword)),
+        expected.get_line(i).map(|l| (l.number, l.text, l.keyword))
+      );
+      if let Some(line) = l.get_line(i) {
+        assert_eq!(l.get_by_id(line.id), Some(line));
+      }
+      assert_eq!(l.first_before(i), expected.first_before(i));
+      assert_eq!(l.first_after(i), expected.first_after(i));
+    }
+  }
+
+  #[test]
+  fn lines_update_reuses_chunks() {
+    let model: Vec<String> =
+      (0..6 * CHUNK_SIZE).map(|i| format!("line {}", i)).collect();
+    let mut l = Lines::new();
+    l.parse_vec(model.clone());
+    let keys: Vec<_> = l.chunks.iter().map(|c| c.key).collect();
+
+    // Deleting across a chunk boundary leaves a placeholder
+    let (first, last) = (CHUNK_SIZE / 2, 3 * CHUNK_SIZE);
+    l.update(
+      vec![],
+      first.into(),
+      last.into(),
+      -((last - first) as isize),
+    );
+    assert_eq!(l.chunks.iter().map(|c| c.key).collect::<Vec<_>>(), keys);
+    assert!(l.chunks.iter().any(|c| c.numlines == 0));
+
+    // Pasting the lines back fills it again
+    let pasted = model[first..last].to_vec();
+    l.update(pasted, first.into(), first.into(), (last - first) as isize);
+    assert_eq!(l.chunks.iter().map(|c| c.key).collect::<Vec<_>>(), keys);
+
+    let mut expected = Lines::new();
+    expected.parse_vec(model.clone());
+    assert_eq!(l, expected);
+
+    // Pasting more than there are places for adds chunks and placeholders
+    let pasted = model[..4 * CHUNK_SIZE].to_vec();
+    l.update(pasted, 0.into(), 0.into(), 4 * CHUNK_SIZE as isize);
+    assert!(l.chunks.len() > keys.len());
+    assert_eq!(l.numlines(), 10 * CHUNK_SIZE);
+    for c in 0..l.chunks.len() {
+      let (start, _) = l.index.start(c);
+      for line in &l.chunks[c].lines {
+        assert_eq!(l.linenr(line.id), Some(start + line.offset));
+      }
+    }
   }
 
   macro_rules! test_before {
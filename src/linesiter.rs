@@ -6,26 +6,31 @@
 //! [`parse_from_iter`](crate::bufdata::BufData::parse_from_iter), work on a
 //! [`LinesIter`](crate::linesiter::LinesIter).
 use crate::{
-  bufdata::highlights::Highlights,
+  bufdata::{
+    highlights::Highlights,
+    structure::{Issue, Structure},
+  },
   card::{
     ges::GesType,
+    keyword::Keyword,
     line::{CondResult, Line as CardLine},
     Card,
   },
-  linenr::LineNr,
+  carddata::UNKNOWN,
+  lineid::LineId,
   lines::{KeywordLine, ParsedLine},
   skipresult::SkipResult,
 };
 
 // Used in skip functions. Returns the next `ParsedLine` from the iterator. If
-// theres no next line, return a `SkipResult` containing the line number of
-// `prevline` and nothing else.
-macro_rules! next_or_return_previdx {
-  ($self:ident, $previdx:expr) => {
+// theres no next line, return a `SkipResult` containing `prevline` and nothing
+// else.
+macro_rules! next_or_return_prevline {
+  ($self:ident, $prevline:expr) => {
     match $self.next() {
       None => {
         return SkipResult {
-          skip_end: $previdx,
+          skip_end: $prevline,
           nextline: None,
         };
       }
@@ -36,12 +41,12 @@ macro_rules! next_or_return_previdx {
 
 // Used in skip_ges to get the next line. If it's None, we're at the end of
 // the file and only return what we found before. Also used in `advance_some!`
-macro_rules! next_or_return_some_previdx {
-  ($self:ident, $previdx:expr) => {
+macro_rules! next_or_return_some_prevline {
+  ($self:ident, $prevline:expr) => {
     match $self.next() {
       None => {
         return Some(SkipResult {
-          skip_end: $previdx,
+          skip_end: $prevline,
           nextline: None,
         });
       }
@@ -63,36 +68,115 @@ macro_rules! next_or_return_none {
 
 // A common pattern for nocommentiter: Save Some(nextline) in prevline,
 // and advance the iterator. Save in nextline, or return a SkipResult built
-// from prevline's line number
+// from prevline
 macro_rules! advance {
-  ($self:ident, $previdx:ident, $nextline:ident) => {
-    $previdx = $nextline.number;
-    $nextline = next_or_return_previdx!($self, $previdx);
+  ($self:ident, $prevline:ident, $nextline:ident) => {
+    $prevline = $nextline;
+    $nextline = next_or_return_prevline!($self, $prevline);
   };
 }
 
 // Same as advance above, just that the `SkipResult` is wrapped in `Some`. Used
 // in skip_ges.
 macro_rules! advance_some {
-  ($self:ident, $previdx:ident, $nextline:ident) => {
-    $previdx = $nextline.number;
-    $nextline = next_or_return_some_previdx!($self, $previdx);
+  ($self:ident, $prevline:ident, $nextline:ident) => {
+    $prevline = $nextline;
+    $nextline = next_or_return_some_prevline!($self, $prevline);
   };
 }
+/// How far a card got against its definition while skipping it, see
+/// [`skip_card`](LinesIter::skip_card).
+#[derive(Default)]
+struct Progress {
+  /// The results of the conditionals of the lines read so far
+  conds: Vec<CondResult>,
+  /// The number of lines of the definition that have been dealt with
+  done: usize,
+  /// The string ending the block we're in, if any
+  block: Option<&'static [u8]>,
+  /// The first issue found
+  issue: Option<Issue>,
+  /// The lines with values straddling two cells, or text after their last
+  /// cell
+  lineissues: Vec<(LineId, Issue)>,
+}
+
+impl Progress {
+  /// Add the highlights of `line`, read as `cardline`, and check it for
+  /// [`misaligned`](crate::card::line::Line::misaligned) values and
+  /// [`trailing`](crate::card::line::Line::trailing) text.
+  fn add_line(
+    &mut self,
+    highlights: &mut Highlights,
+    line: &ParsedLine,
+    cardline: &CardLine,
+  ) {
+    // Only values up to column 255 are reported, and a shift is never longer
+    // than a cell
+    #![allow(clippy::cast_possible_truncation)]
+    highlights.add_line_highlights(line.id, line.text, cardline);
+
+    if let Some((start, end, shift)) = cardline.misaligned(line.text) {
+      if end < 256 {
+        let issue = Issue::Misaligned {
+          start: start as u8,
+          end: end as u8,
+          shift: shift as i8,
+        };
+        self.lineissues.push((line.id, issue));
+      }
+    }
+
+    if let Some((start, end)) = cardline.trailing(line.text) {
+      // Only the first 80 columns are checked
+      let issue = Issue::TrailingText {
+        start: start as u8,
+        end: end as u8,
+      };
+      self.lineissues.push((line.id, issue));
+    }
+  }
+
+  /// Record `issue`, unless an issue has been found before.
+  fn found(&mut self, issue: Issue) {
+    if self.issue.is_none() {
+      self.issue = Some(issue);
+    }
+  }
+
+  /// The issue of the card after skipping it. If nothing went wrong while
+  /// reading its lines, check if the card ended inside a block, or before one
+  /// of its mandatory lines.
+  fn issue(&self, card: &Card) -> Option<Issue> {
+    self
+      .issue
+      .or_else(|| self.block.map(Issue::UnterminatedBlock))
+      .or_else(|| {
+        card
+          .lines
+          .iter()
+          .enumerate()
+          .skip(self.done)
+          .find(|(_, l)| l.is_mandatory(&self.conds))
+          .map(|(i, _)| Issue::MissingLine(i))
+      })
+  }
+}
+
 /// The struct simply holds a type instance. Skipping comments is done in the
 /// Iterator implementation.
 pub struct LinesIter<'a, I>
 where
-  I: Iterator<Item = &'a ParsedLine<'a>>,
+  I: Iterator<Item = ParsedLine<'a>>,
 {
   it: I,
 }
 
 impl<'a, I> Iterator for LinesIter<'a, I>
 where
-  I: Iterator<Item = &'a ParsedLine<'a>>,
+  I: Iterator<Item = ParsedLine<'a>>,
 {
-  type Item = &'a ParsedLine<'a>;
+  type Item = ParsedLine<'a>;
 
   fn next(&mut self) -> Option<Self::Item> {
     self.it.next()
@@ -101,7 +185,7 @@ where
 
 impl<'a, I> LinesIter<'a, I>
 where
-  I: Iterator<Item = &'a ParsedLine<'a>>,
+  I: Iterator<Item = ParsedLine<'a>>,
 {
   pub fn new(it: I) -> Self {
     Self { it }
@@ -120,7 +204,8 @@ where
   }
 
   /// Advance the iterator until the first line after a General Entity
-  /// Selection (GES).
+  /// Selection (GES). The keywords of the lines of the GES are added to
+  /// `highlights`.
   ///
   /// Returns `None` if skipline neither ends the GES, nor is
   /// contained in it. We do not try to advance the iterator in this case.
@@ -128,53 +213,101 @@ where
     &'b mut self,
     ges: GesType,
     skipline: &ParsedLine<'a>,
+    highlights: &mut Highlights,
   ) -> Option<SkipResult<'a>> {
-    let mut previdx: LineNr = skipline.number;
-    let mut nextline: &'a ParsedLine<'a>;
+    let mut prevline = *skipline;
+    let mut nextline: ParsedLine<'a>;
 
     let contained = ges.contains(skipline.text.as_ref());
     let ends = ges.ended_by(skipline.text.as_ref());
 
     if ends {
-      nextline = next_or_return_some_previdx!(self, previdx);
+      highlights.add_ges_highlights(skipline.id, skipline.text.as_ref());
+      nextline = next_or_return_some_prevline!(self, prevline);
       Some(SkipResult {
         nextline: Some(nextline),
-        skip_end: previdx,
+        skip_end: prevline,
       })
     } else if !ends && !contained {
       None
     } else {
-      nextline = next_or_return_some_previdx!(self, skipline.number);
+      highlights.add_ges_highlights(skipline.id, skipline.text.as_ref());
+      nextline = next_or_return_some_prevline!(self, *skipline);
 
       while ges.contains(nextline.text.as_ref()) {
-        advance_some!(self, previdx, nextline);
+        highlights.add_ges_highlights(nextline.id, nextline.text.as_ref());
+        advance_some!(self, prevline, nextline);
       }
 
       if ges.ended_by(nextline.text.as_ref()) {
-        advance_some!(self, previdx, nextline);
+        highlights.add_ges_highlights(nextline.id, nextline.text.as_ref());
+        advance_some!(self, prevline, nextline);
       }
 
       Some(SkipResult {
         nextline: Some(nextline),
-        skip_end: previdx,
+        skip_end: prevline,
       })
     }
   }
 
   /// A wrapper around [`skip_card`](LinesIter::skip_card) and
   /// [`skip_card_gather`](LinesIter::skip_card_gather), dispatching by value of
-  /// [`Card.ownfold`](crate::card::Card::ownfold)
+  /// [`Card.ownfold`](crate::card::Card::ownfold). The
+  /// [`Issue`](crate::bufdata::structure::Issue)s of the cards skipped are
+  /// added to `structure`.
   pub fn skip_fold<'b>(
     &'b mut self,
     skipline: &KeywordLine<'a>,
     highlights: &mut Highlights,
+    structure: &mut Structure,
   ) -> SkipResult<'a> {
     let card: &Card = (&skipline.keyword).into();
 
-    if card.ownfold {
-      self.skip_card(&skipline, card, highlights)
+    if skipline.keyword == Keyword::Unknown {
+      self.skip_unknown(skipline, highlights)
+    } else if card.ownfold {
+      self.skip_card(&skipline, card, highlights, structure)
     } else {
-      self.skip_card_gather(&skipline, card, highlights)
+      self.skip_card_gather(&skipline, card, highlights, structure)
+    }
+  }
+
+  /// Skip a card with an [`Unknown`](crate::card::keyword::Keyword::Unknown)
+  /// keyword, passed as `skipline`. Since we don't know its lines, the card
+  /// ends before the next line with a keyword, or with the line starting with
+  /// `END_` followed by the name of the keyword, e.g. `END_OCTRL` for a card
+  /// starting with `OCTRL / `. Only the keyword is highlighted.
+  fn skip_unknown<'b>(
+    &'b mut self,
+    skipline: &KeywordLine<'a>,
+    highlights: &mut Highlights,
+  ) -> SkipResult<'a> {
+    highlights.add_line_highlights(
+      skipline.id,
+      skipline.text,
+      &UNKNOWN.lines[0],
+    );
+
+    let name = Keyword::name(skipline.text);
+    let ends = |text: &[u8]| {
+      text.starts_with(b"END_") && Keyword::name(&text[4..]) == name
+    };
+
+    let mut prevline = ParsedLine::from(skipline);
+    let mut nextline = next_or_return_prevline!(self, prevline);
+
+    while nextline.keyword.is_none() {
+      if ends(nextline.text) {
+        advance!(self, prevline, nextline);
+        break;
+      }
+      advance!(self, prevline, nextline);
+    }
+
+    SkipResult {
+      nextline: Some(nextline),
+      skip_end: prevline,
     }
   }
 
@@ -183,6 +316,11 @@ where
   /// sense when the last line the iterator returned is the line with the
   /// keyword starting that card, which is passed as `skipline`.
   ///
+  /// If the card falls short of its definition, i.e. it ends before one of
+  /// its mandatory lines, or a GES or block in it is not ended properly, the
+  /// first such [`Issue`](crate::bufdata::structure::Issue) is added to
+  /// `structure`.
+  ///
   /// If you want to skip all cards of a given type, use
   /// [`skip_card_gather`](NoCommentIter::skip_card_gather)
   fn skip_card<'b>(
@@ -190,101 +328,186 @@ where
     skipline: &KeywordLine<'a>,
     card: &Card,
     highlights: &mut Highlights,
+    structure: &mut Structure,
+  ) -> SkipResult<'a> {
+    let mut progress = Progress::default();
+    let skipped =
+      self.skip_card_lines(skipline, card, highlights, &mut progress);
+
+    if let Some(issue) = progress.issue(card) {
+      structure.push(skipline.id, skipline.keyword, issue);
+    }
+    for (id, issue) in progress.lineissues {
+      structure.push(id, skipline.keyword, issue);
+    }
+
+    skipped
+  }
+
+  /// The workhorse of [`skip_card`](LinesIter::skip_card), recording how far
+  /// the card got in `progress`.
+  fn skip_card_lines<'b>(
+    &'b mut self,
+    skipline: &KeywordLine<'a>,
+    card: &Card,
+    highlights: &mut Highlights,
+    progress: &mut Progress,
   ) -> SkipResult<'a> {
-    let mut conds: Vec<CondResult> = vec![]; // the vec to hold the conditionals
-    let mut cardlines = card.lines.iter();
-    let cardline = cardlines.next().unwrap_or_else(|| unreachable!());
+    let mut cardlines = card.lines.iter().enumerate();
+    let (_, cardline) = cardlines.next().unwrap_or_else(|| unreachable!());
 
     if let CardLine::Provides(_s, ref c) = cardline {
-      conds.push(c.evaluate(skipline.text));
+      progress.conds.push(c.evaluate(skipline.text));
     }
 
-    highlights.add_line_highlights(skipline.number, skipline.text, cardline);
+    let mut prevline = ParsedLine::from(skipline);
+    progress.add_line(highlights, &prevline, cardline);
+    progress.done = 1;
 
-    let mut previdx: LineNr = skipline.number;
-    let mut nextline = next_or_return_previdx!(self, previdx);
+    let mut nextline = next_or_return_prevline!(self, prevline);
 
-    for cardline in cardlines {
+    for (i, cardline) in cardlines {
       if nextline.keyword.is_some() {
         break;
       }
 
       match *cardline {
         CardLine::Provides(_s, ref c) => {
-          conds.push(c.evaluate(nextline.text.as_ref()));
-          advance!(self, previdx, nextline);
+          progress.conds.push(c.evaluate(nextline.text.as_ref()));
+          progress.add_line(highlights, &nextline, cardline);
+          progress.done = i + 1;
+          advance!(self, prevline, nextline);
         }
-        CardLine::Ges(ref g) => {
-          if let Some(sr) = self.skip_ges(*g, nextline) {
-            match sr.nextline {
-              None => return sr,
-              Some(pl) => {
-                previdx = sr.skip_end;
-                nextline = pl;
+        CardLine::Ges(ref g) | CardLine::OptionalGes(ref g, _) => {
+          progress.done = i + 1;
+
+          match self.skip_ges(*g, &nextline, highlights) {
+            None if cardline.is_mandatory(&progress.conds) => {
+              progress.found(Issue::MissingLine(i))
+            }
+            None => {}
+            Some(sr) => {
+              if !g.ended_by(sr.skip_end.text) {
+                progress.found(Issue::UnterminatedGes);
               }
-            };
+              match sr.nextline {
+                None => return sr,
+                Some(pl) => {
+                  prevline = sr.skip_end;
+                  nextline = pl;
+                }
+              };
+            }
           }
         }
         CardLine::Cells(_s) => {
-          highlights.add_line_highlights(
-            nextline.number,
-            nextline.text.as_ref(),
-            cardline,
-          );
+          progress.add_line(highlights, &nextline, cardline);
+          progress.done = i + 1;
 
-          advance!(self, previdx, nextline);
+          advance!(self, prevline, nextline);
         }
-        CardLine::Optional(_s, i) => {
-          if conds.get(i as usize) == Some(&CondResult::Bool(true)) {
-            advance!(self, previdx, nextline);
+        CardLine::Optional(_s, c) => {
+          progress.done = i + 1;
+
+          if progress.conds.get(c as usize) == Some(&CondResult::Bool(true)) {
+            progress.add_line(highlights, &nextline, cardline);
+            advance!(self, prevline, nextline);
           } else {
             continue;
           }
         }
-        CardLine::Repeat(_s, i) => {
-          let num = match conds.get(i as usize) {
-            Some(CondResult::Number(Some(u))) if *u > 0 => u,
-            _ => continue,
+        CardLine::Repeat(_s, c) => {
+          let num = match progress.conds.get(c as usize) {
+            Some(CondResult::Number(Some(u))) if *u > 0 => *u,
+            _ => {
+              progress.done = i + 1;
+              continue;
+            }
           };
 
-          // TODO(KillTheMule): Is this comment still right? Guess not...
-          // We need one more loop than *num because we need to get the next
-          // line for the next outer iteration
-          for _ in 0..*num {
-            advance!(self, previdx, nextline);
+          for j in 0..num {
+            progress.add_line(highlights, &nextline, cardline);
+            if j + 1 == num {
+              progress.done = i + 1;
+            }
+            advance!(self, prevline, nextline);
 
             if nextline.keyword.is_some() {
               break;
             }
           }
         }
-        CardLine::Block(_l, s) => loop {
-          while !nextline.text.as_ref().starts_with(s) {
-            advance!(self, previdx, nextline);
+        CardLine::Block(blocklines, end) => {
+          progress.block = Some(end);
+
+          // The lines of the block repeat until the line starting with `end`
+          'block: loop {
+            let start = nextline.number;
+
+            for blockline in blocklines {
+              if nextline.keyword.is_some()
+                || nextline.text.as_ref().starts_with(end)
+              {
+                break 'block;
+              }
 
-            if nextline.keyword.is_some() {
-              break;
+              if let CardLine::Ges(g) = *blockline {
+                if let Some(sr) = self.skip_ges(g, &nextline, highlights) {
+                  if !g.ended_by(sr.skip_end.text) {
+                    progress.found(Issue::UnterminatedGes);
+                  }
+                  match sr.nextline {
+                    None => return sr,
+                    Some(pl) => {
+                      prevline = sr.skip_end;
+                      nextline = pl;
+                    }
+                  };
+                }
+              } else {
+                progress.add_line(highlights, &nextline, blockline);
+                advance!(self, prevline, nextline);
+              }
+            }
+
+            // Don't get stuck on a line that doesn't fit the block
+            if nextline.number == start {
+              advance!(self, prevline, nextline);
             }
           }
-          advance!(self, previdx, nextline);
-        },
+
+          if nextline.text.as_ref().starts_with(end) {
+            progress.block = None;
+            progress.done = i + 1;
+            advance!(self, prevline, nextline);
+          }
+        }
         CardLine::OptionalBlock(s1, s2) => {
           if !nextline.text.as_ref().starts_with(s1) {
+            progress.done = i + 1;
             continue;
           }
+          progress.block = Some(s2);
+
           while !nextline.text.as_ref().starts_with(s2) {
-            advance!(self, previdx, nextline);
+            advance!(self, prevline, nextline);
 
             if nextline.keyword.is_some() {
               break;
             }
           }
+
+          if nextline.text.as_ref().starts_with(s2) {
+            progress.block = None;
+            progress.done = i + 1;
+            advance!(self, prevline, nextline);
+          }
         }
       }
     }
     SkipResult {
       nextline: Some(nextline),
-      skip_end: previdx,
+      skip_end: prevline,
     }
   }
 
@@ -297,13 +520,14 @@ where
     skipline: &KeywordLine<'a>,
     card: &Card,
     highlights: &mut Highlights,
+    structure: &mut Structure,
   ) -> SkipResult<'a> {
-    let mut r = self.skip_card(&skipline, card, highlights);
+    let mut r = self.skip_card(&skipline, card, highlights, structure);
 
     while let Some(p) = r.nextline {
       if let Some(kl) = p.try_into_keywordline() {
         if kl.keyword == card.keyword() {
-          r = self.skip_card(&kl, card, highlights);
+          r = self.skip_card(&kl, card, highlights, structure);
         } else {
           break;
         }
@@ -320,25 +544,31 @@ where
 #[cfg(test)]
 mod tests {
   use crate::{
-    bufdata::highlights::Highlights,
+    bufdata::{
+      highlights::{HighlightGroup::GesKeyword, Highlights},
+      structure::{Issue, Structure},
+    },
     card::{ges::GesType::GesNode, keyword::Keyword::*},
     carddata::*,
-    lines::{KeywordLine, Lines, ParsedLine, RawLine::*},
+    lineid::LineId,
+    lines::{KeywordLine, Lines, ParsedLine},
   };
 
   macro_rules! pline {
-    ($number:expr, $text:expr, $keyword:expr) => {
+    ($id:expr, $number:expr, $text:expr, $keyword:expr) => {
       ParsedLine {
+        id: LineId::from_usize($id),
         number: $number,
-        text: OriginalLine($text.as_ref()),
+        text: $text.as_ref(),
         keyword: $keyword,
       }
     };
   }
 
   macro_rules! kwline {
-    ($number:expr, $text:expr, $keyword:expr) => {
+    ($id:expr, $number:expr, $text:expr, $keyword:expr) => {
       KeywordLine {
+        id: LineId::from_usize($id),
         number: $number,
         text: $text,
         keyword: $keyword,
@@ -354,8 +584,8 @@ mod tests {
     let mut lines = Lines::new();
     lines.parse_slice(COMMENTS.as_ref());
     let mut l = lines.iter();
-    assert_eq!(l.next(), Some(&pline!(4.into(), "of", None)));
-    assert_eq!(l.next(), Some(&pline!(5.into(), "some", None)));
+    assert_eq!(l.next(), Some(pline!(0, 4.into(), "of", None)));
+    assert_eq!(l.next(), Some(pline!(1, 5.into(), "some", None)));
   }
 
   const NOKEYWORD_LINES: &'static str = "\nsome\nlines\n.";
@@ -378,11 +608,11 @@ mod tests {
     let mut l = lines.iter();
     assert_eq!(
       l.skip_to_next_keyword(),
-      Some(kwline!(2.into(), b"NODE  / ", Node))
+      Some(kwline!(1, 2.into(), b"NODE  / ", Node))
     );
     assert_eq!(
       l.skip_to_next_keyword(),
-      Some(kwline!(4.into(), b"NSMAS / ", Nsmas))
+      Some(kwline!(2, 4.into(), b"NSMAS / ", Nsmas))
     );
     assert_eq!(l.skip_to_next_keyword(), None);
     assert_eq!(l.next(), None);
@@ -398,15 +628,30 @@ mod tests {
     let mut lines = Lines::new();
     lines.parse_slice(GES1.as_ref());
     let mut l = lines.iter();
+    let mut hls = Highlights::new();
 
     let nextline = l.next().unwrap();
-    let tmp = l.skip_ges(GesNode, &nextline).unwrap();
+    let tmp = l.skip_ges(GesNode, &nextline, &mut hls).unwrap();
     assert_eq!(
       tmp.nextline.unwrap(),
-      &pline!(4.into(), b"NODE  / ", Some(Node))
+      pline!(4, 4.into(), b"NODE  / ", Some(Node))
     );
-    assert_eq!(tmp.skip_end, 3.into());
+    assert_eq!(tmp.skip_end.number, 3.into());
     assert_eq!(l.next(), None);
+
+    let ges: Vec<_> = hls
+      .iter()
+      .map(|((id, s, e), hl)| (usize::from(*id), *s, *e, *hl))
+      .collect();
+    assert_eq!(
+      ges,
+      vec![
+        (0, 8, 12, GesKeyword),
+        (1, 8, 12, GesKeyword),
+        (2, 8, 18, GesKeyword),
+        (3, 8, 11, GesKeyword),
+      ]
+    );
   }
 
   const GES2: &'static str = "        PART 1234\
@@ -426,16 +671,17 @@ mod tests {
     let mut lines = Lines::new();
     lines.parse_slice(GES2.as_ref());
     let mut l = lines.iter();
+    let mut hls = Highlights::new();
 
     let mut nextline = l.next().unwrap();
-    let mut tmp = l.skip_ges(GesNode, &nextline).unwrap();
-    assert_eq!(tmp.nextline.unwrap(), &pline!(3.into(), GES2_NEXT, None));
-    assert_eq!(tmp.skip_end, 2.into());
+    let mut tmp = l.skip_ges(GesNode, &nextline, &mut hls).unwrap();
+    assert_eq!(tmp.nextline.unwrap(), pline!(3, 3.into(), GES2_NEXT, None));
+    assert_eq!(tmp.skip_end.number, 2.into());
 
     nextline = l.next().unwrap();
-    tmp = l.skip_ges(GesNode, &nextline).unwrap();
+    tmp = l.skip_ges(GesNode, &nextline, &mut hls).unwrap();
     assert_eq!(tmp.nextline, None);
-    assert_eq!(tmp.skip_end, 8.into());
+    assert_eq!(tmp.skip_end.number, 8.into());
     assert_eq!(l.next(), None);
   }
 
@@ -458,19 +704,23 @@ mod tests {
     let mut lines = Lines::new();
     lines.parse_slice(GES3.as_ref());
     let mut l = lines.iter();
+    let mut hls = Highlights::new();
     let mut nextline = l.next().unwrap();
-    let mut tmp = l.skip_ges(GesNode, &nextline).unwrap();
+    let mut tmp = l.skip_ges(GesNode, &nextline, &mut hls).unwrap();
     assert_eq!(
       tmp.nextline.unwrap(),
-      &pline!(2.into(), GES3_FIRST, Some(Node))
+      pline!(2, 2.into(), GES3_FIRST, Some(Node))
     );
-    assert_eq!(tmp.skip_end, 1.into());
+    assert_eq!(tmp.skip_end.number, 1.into());
 
     nextline = l.next().unwrap();
-    tmp = l.skip_ges(GesNode, &nextline).unwrap();
-    assert_eq!(tmp.nextline.unwrap(), &pline!(7.into(), GES3_SECOND, None));
-    assert_eq!(tmp.skip_end, 6.into());
-    assert_eq!(l.next(), Some(&pline!(8.into(), GES3_LAST, None)));
+    tmp = l.skip_ges(GesNode, &nextline, &mut hls).unwrap();
+    assert_eq!(
+      tmp.nextline.unwrap(),
+      pline!(7, 7.into(), GES3_SECOND, None)
+    );
+    assert_eq!(tmp.skip_end.number, 6.into());
+    assert_eq!(l.next(), Some(pline!(8, 8.into(), GES3_LAST, None)));
   }
 
   const GES4: &'static str = "wupdiwup\nNODE  / ";
@@ -481,10 +731,14 @@ mod tests {
     let mut lines = Lines::new();
     lines.parse_slice(GES4.as_ref());
     let mut l = lines.iter();
+    let mut hls = Highlights::new();
     let nextline = l.next().unwrap();
-    let tmp = l.skip_ges(GesNode, &nextline);
+    let tmp = l.skip_ges(GesNode, &nextline, &mut hls);
     assert!(tmp.is_none());
-    assert_eq!(l.next().unwrap(), &pline!(1.into(), GES4_LAST, Some(Node)));
+    assert_eq!(
+      l.next().unwrap(),
+      pline!(1, 1.into(), GES4_LAST, Some(Node))
+    );
   }
 
   const GES5: &'static str = "        PART 1234\
@@ -502,13 +756,14 @@ mod tests {
     let mut lines = Lines::new();
     lines.parse_slice(GES5.as_ref());
     let mut l = lines.iter();
+    let mut hls = Highlights::new();
     let nextline = l.next().unwrap();
-    let tmp = l.skip_ges(GesNode, &nextline).unwrap();
+    let tmp = l.skip_ges(GesNode, &nextline, &mut hls).unwrap();
     assert_eq!(
       tmp.nextline.unwrap(),
-      &pline!(6.into(), GES5_NEXTL, Some(Node))
+      pline!(4, 6.into(), GES5_NEXTL, Some(Node))
     );
-    assert_eq!(tmp.skip_end, 4.into());
+    assert_eq!(tmp.skip_end.number, 4.into());
     assert_eq!(l.next(), None);
   }
 
@@ -522,10 +777,11 @@ mod tests {
     let mut lines = Lines::new();
     lines.parse_slice(GES6.as_ref());
     let mut l = lines.iter();
+    let mut hls = Highlights::new();
     let nextline = l.next().unwrap();
-    let tmp = l.skip_ges(GesNode, &nextline).unwrap();
+    let tmp = l.skip_ges(GesNode, &nextline, &mut hls).unwrap();
     assert_eq!(tmp.nextline, None);
-    assert_eq!(tmp.skip_end, 0.into());
+    assert_eq!(tmp.skip_end.number, 0.into());
     assert_eq!(l.next(), None);
   }
 
@@ -546,14 +802,25 @@ mod tests {
     lines.parse_slice(CARD_MASS_INCOMPLETE.as_ref());
     let mut l = lines.iter();
     let mut hls = Highlights::new();
+    let mut structure = Structure::new();
     let firstline = l.next().unwrap();
-    let tmp =
-      l.skip_card(&firstline.try_into_keywordline().unwrap(), &MASS, &mut hls);
+    let tmp = l.skip_card(
+      &firstline.try_into_keywordline().unwrap(),
+      &MASS,
+      &mut hls,
+      &mut structure,
+    );
     assert_eq!(
       tmp.nextline.unwrap(),
-      &pline!(7.into(), &"NODE  /      ", Some(Node))
+      pline!(2, 7.into(), &"NODE  /      ", Some(Node))
+    );
+    assert_eq!(tmp.skip_end.number, 4.into());
+
+    let issues: Vec<_> = structure.iter().cloned().collect();
+    assert_eq!(
+      issues,
+      vec![(LineId::from_usize(0), (Mass, Issue::MissingLine(2)))]
     );
-    assert_eq!(tmp.skip_end, 4.into());
   }
 
   const LINES_GATHER: [&'static str; 20] = [
@@ -603,37 +870,50 @@ mod tests {
   fn skips_gather_cards() {
     let mut lines = Lines::new();
     let mut hls = Highlights::new();
+    let mut structure = Structure::new();
     lines.parse_strs(&LINES_GATHER);
     let mut li = lines.iter();
 
     let firstline = li.next().unwrap();
 
-    let mut tmp =
-      li.skip_fold(&(firstline.try_into_keywordline()).unwrap(), &mut hls);
+    let mut tmp = li.skip_fold(
+      &(firstline.try_into_keywordline()).unwrap(),
+      &mut hls,
+      &mut structure,
+    );
     let mut tmp_nextline = tmp.nextline.unwrap();
     assert_eq!(
       tmp_nextline,
-      &pline!(5.into(), &LINES_GATHER[5], Some(Shell))
+      pline!(4, 5.into(), &LINES_GATHER[5], Some(Shell))
     );
-    assert_eq!(tmp.skip_end, 3.into());
+    assert_eq!(tmp.skip_end.number, 3.into());
 
-    tmp = li.skip_fold(&tmp_nextline.try_into_keywordline().unwrap(), &mut hls);
+    tmp = li.skip_fold(
+      &tmp_nextline.try_into_keywordline().unwrap(),
+      &mut hls,
+      &mut structure,
+    );
     tmp_nextline = tmp.nextline.unwrap();
-    assert_eq!(tmp_nextline, &pline!(6.into(), &LINES_GATHER[6], None));
-    assert_eq!(tmp.skip_end, 5.into());
+    assert_eq!(tmp_nextline, pline!(5, 6.into(), &LINES_GATHER[6], None));
+    assert_eq!(tmp.skip_end.number, 5.into());
 
     let skipped = li.skip_to_next_keyword().unwrap();
-    tmp = li.skip_fold(&skipped.into(), &mut hls);
+    tmp = li.skip_fold(&skipped.into(), &mut hls, &mut structure);
     tmp_nextline = tmp.nextline.unwrap();
     assert_eq!(
       tmp_nextline,
-      &pline!(18.into(), &LINES_GATHER[18], Some(Node))
+      pline!(12, 18.into(), &LINES_GATHER[18], Some(Node))
     );
-    assert_eq!(tmp.skip_end, 15.into());
+    assert_eq!(tmp.skip_end.number, 15.into());
 
-    tmp = li.skip_fold(&tmp_nextline.try_into_keywordline().unwrap(), &mut hls);
+    tmp = li.skip_fold(
+      &tmp_nextline.try_into_keywordline().unwrap(),
+      &mut hls,
+      &mut structure,
+    );
     assert_eq!(tmp.nextline, None);
-    assert_eq!(tmp.skip_end, 19.into());
+    assert_eq!(tmp.skip_end.number, 19.into());
+    assert_eq!(structure.iter().count(), 0);
   }
 
 }
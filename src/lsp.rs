@@ -0,0 +1,592 @@
+//! A server for the [Language Server
+//! Protocol](https://microsoft.github.io/language-server-protocol/) over
+//! stdio, so nvimpam can be used from editors other than neovim. It provides
+//!
+//! * Incremental document sync, mapped onto
+//!   [`BufData::update`](crate::bufdata::BufData::update)
+//! * Folding ranges from the level 1 and level 2 folds
+//! * Semantic tokens from the cell highlights, the token types are the names
+//!   of the [`HighlightGroup`](crate::bufdata::highlights::HighlightGroup)s
+//! * Diagnostics, see
+//!   [`diagnostics`](crate::bufdata::BufData::diagnostics). The line-level
+//!   [`lint rules`](crate::bufdata::lint::Rule) named in the `allow` array of
+//!   the `initializationOptions` are turned off.
+//!
+//! Columns are sent as byte offsets. Those are the same as the UTF-16 offsets
+//! the protocol expects, as long as the file is ASCII.
+use std::{
+  collections::HashMap,
+  io::{BufRead, Write},
+};
+
+use failure::{self, Error, ResultExt};
+use log::{info, warn};
+use neovim_lib::{neovim_api::Buffer, Value as NvimValue};
+use serde_json::{json, Value};
+
+use crate::{
+  bufdata::{
+    diagnostics::Severity, highlights::HighlightGroup as Hl, lint::Lints,
+    BufData,
+  },
+  linenr::LineNr,
+};
+
+/// The highlight groups used as semantic token types, in order of their index
+/// in the legend
+const TOKEN_TYPES: [Hl; 20] = [
+  Hl::CellEven,
+  Hl::CellOdd,
+  Hl::ErrorCellEven,
+  Hl::ErrorCellOdd,
+  Hl::Keyword,
+  Hl::IdEven,
+  Hl::IdOdd,
+  Hl::IntegerEven,
+  Hl::IntegerOdd,
+  Hl::FloatEven,
+  Hl::FloatOdd,
+  Hl::StrEven,
+  Hl::StrOdd,
+  Hl::BinaryEven,
+  Hl::BinaryOdd,
+  Hl::FixedEven,
+  Hl::FixedOdd,
+  Hl::ContEven,
+  Hl::ContOdd,
+  Hl::GesKeyword,
+];
+
+/// The error code for unknown methods, as defined by JSON-RPC
+const METHOD_NOT_FOUND: i64 = -32601;
+/// The error code for errors while handling a request, as defined by JSON-RPC
+const INTERNAL_ERROR: i64 = -32603;
+
+/// Read a message from the client. Returns `None` if the input has ended.
+fn read_message<R: BufRead>(input: &mut R) -> Result<Option<Value>, Error> {
+  let mut len = None;
+
+  loop {
+    let mut header = String::new();
+    if input.read_line(&mut header)? == 0 {
+      return Ok(None);
+    }
+
+    let header = header.trim_end();
+    if header.is_empty() {
+      break;
+    }
+
+    let mut parts = header.splitn(2, ':');
+    if let (Some(name), Some(value)) = (parts.next(), parts.next()) {
+      if name.eq_ignore_ascii_case("Content-Length") {
+        len = Some(value.trim().parse::<usize>().with_context(|_| {
+          format!("Invalid Content-Length header '{}'", header)
+        })?);
+      }
+    }
+  }
+
+  let len = len.ok_or_else(|| failure::err_msg("Missing Content-Length"))?;
+  let mut content = vec![0; len];
+  input.read_exact(&mut content)?;
+
+  Ok(Some(serde_json::from_slice(&content)?))
+}
+
+/// Send a message to the client
+fn write_message<W: Write>(output: &mut W, msg: &Value) -> Result<(), Error> {
+  let content = msg.to_string();
+  write!(
+    output,
+    "Content-Length: {}\r\n\r\n{}",
+    content.len(),
+    content
+  )?;
+  output.flush()?;
+
+  Ok(())
+}
+
+/// Return the byte index of the given UTF-16 offset in a line. Offsets after
+/// the end of the line return the length of the line.
+fn byte_index(line: &str, character: u64) -> usize {
+  let mut units = 0;
+
+  for (i, c) in line.char_indices() {
+    if units >= character {
+      return i;
+    }
+    units += c.len_utf16() as u64;
+  }
+
+  line.len()
+}
+
+/// Split a text into lines. A text ending in a newline has an empty last line.
+fn split_lines(text: &str) -> Vec<String> {
+  text
+    .split('\n')
+    .map(|l| l.trim_end_matches('\r').to_owned())
+    .collect()
+}
+
+/// A document opened by the client. We need to keep the text of all lines to
+/// apply incremental changes, since comments are not saved in the
+/// [`BufData`](crate::bufdata::BufData).
+struct Document<'a> {
+  lines: Vec<String>,
+  bufdata: BufData<'a>,
+}
+
+impl<'a> Document<'a> {
+  fn new(buf: &'a Buffer, text: &str, lints: Lints) -> Result<Self, Error> {
+    let lines = split_lines(text);
+    let mut bufdata = BufData::new(buf);
+    bufdata.lints = lints;
+    bufdata.parse_vec(lines.clone())?;
+
+    Ok(Document { lines, bufdata })
+  }
+
+  /// Apply a `TextDocumentContentChangeEvent`. Changes without a range
+  /// replace the whole document.
+  fn change(&mut self, change: &Value) -> Result<(), Error> {
+    let text = change["text"]
+      .as_str()
+      .ok_or_else(|| failure::err_msg("Change without text"))?;
+
+    let range = &change["range"];
+    if range.is_null() {
+      self.lines = split_lines(text);
+      self.bufdata.clear();
+      return self.bufdata.parse_vec(self.lines.clone());
+    }
+
+    let position = |p: &Value| -> Result<(usize, u64), Error> {
+      match (p["line"].as_u64(), p["character"].as_u64()) {
+        (Some(l), Some(c)) => Ok((l as usize, c)),
+        _ => Err(failure::err_msg(format!("Invalid position {}", p))),
+      }
+    };
+    let (firstline, firstchar) = position(&range["start"])?;
+    let (lastline, lastchar) = position(&range["end"])?;
+
+    let numlines = self.lines.len();
+    let firstline = firstline.min(numlines);
+    let lastline = lastline.min(numlines).max(firstline);
+
+    let mut newtext = String::new();
+    if let Some(l) = self.lines.get(firstline) {
+      newtext.push_str(&l[..byte_index(l, firstchar)]);
+    }
+    newtext.push_str(text);
+    if let Some(l) = self.lines.get(lastline) {
+      newtext.push_str(&l[byte_index(l, lastchar)..]);
+    }
+
+    let newlines = split_lines(&newtext);
+    let end = (lastline + 1).min(numlines);
+    let _ = self.lines.splice(firstline..end, newlines.iter().cloned());
+
+    let _ = self.bufdata.update(
+      LineNr::from_usize(firstline),
+      LineNr::from_usize(end),
+      newlines,
+    )?;
+
+    Ok(())
+  }
+
+  fn folding_ranges(&self) -> Value {
+    Value::from(
+      self
+        .bufdata
+        .fold_ranges()
+        .map(|(start, end, _)| {
+          json!({
+            "startLine": usize::from(start),
+            "endLine": usize::from(end),
+          })
+        })
+        .collect::<Vec<_>>(),
+    )
+  }
+
+  /// Encode the highlights as semantic tokens, i.e. 5 numbers per token, the
+  /// first 2 relative to the previous token.
+  fn semantic_tokens(&self) -> Value {
+    let mut data: Vec<u64> = vec![];
+    let mut prevline = 0;
+    let mut prevstart = 0;
+
+    for ((id, start, end), hl) in self.bufdata.highlights.iter() {
+      let typ = match TOKEN_TYPES.iter().position(|t| t == hl) {
+        Some(t) => t as u64,
+        None => continue,
+      };
+      if end <= start {
+        continue;
+      }
+
+      let line = match self.bufdata.linenr(*id) {
+        Some(l) => usize::from(l) as u64,
+        None => continue,
+      };
+      let start = u64::from(*start);
+
+      if line != prevline {
+        prevstart = 0;
+      }
+      data.extend(&[
+        line - prevline,
+        start - prevstart,
+        u64::from(*end) - start,
+      ]);
+      data.extend(&[typ, 0]);
+
+      prevline = line;
+      prevstart = start;
+    }
+
+    json!({ "data": data })
+  }
+
+  fn diagnostics(&self) -> Value {
+    Value::from(
+      self
+        .bufdata
+        .diagnostics()
+        .into_iter()
+        .map(|d| {
+          let line = usize::from(d.line);
+          json!({
+            "range": {
+              "start": { "line": line, "character": d.start },
+              "end": { "line": line, "character": d.end },
+            },
+            "severity": match d.severity {
+              Severity::Error => 1,
+              Severity::Warning => 2,
+              Severity::Info => 3,
+            },
+            "source": "nvimpam",
+            "message": d.message,
+          })
+        })
+        .collect::<Vec<_>>(),
+    )
+  }
+}
+
+/// The state of the server
+struct Server<'a, W: Write> {
+  output: W,
+  /// Only needed to construct [`BufData`](crate::bufdata::BufData) instances,
+  /// it is never used to talk to neovim.
+  buf: &'a Buffer,
+  documents: HashMap<String, Document<'a>>,
+  /// The lint rules for all documents, set on initialization
+  lints: Lints,
+  shutdown: bool,
+}
+
+impl<'a, W: Write> Server<'a, W> {
+  fn notify(&mut self, method: &str, params: Value) -> Result<(), Error> {
+    write_message(
+      &mut self.output,
+      &json!({ "jsonrpc": "2.0", "method": method, "params": params }),
+    )
+  }
+
+  fn publish_diagnostics(&mut self, uri: &str) -> Result<(), Error> {
+    let diagnostics = self
+      .documents
+      .get(uri)
+      .map_or_else(|| json!([]), Document::diagnostics);
+
+    self.notify(
+      "textDocument/publishDiagnostics",
+      json!({ "uri": uri, "diagnostics": diagnostics }),
+    )
+  }
+
+  fn document(&self, params: &Value) -> Result<&Document<'a>, Error> {
+    let uri = params["textDocument"]["uri"].as_str().unwrap_or("");
+    self
+      .documents
+      .get(uri)
+      .ok_or_else(|| failure::err_msg(format!("Unknown document '{}'", uri)))
+  }
+
+  /// Handle the `initialize` request: Turn off the lint rules given in
+  /// `initializationOptions.allow`, and return the capabilities of the server.
+  fn initialize(&mut self, params: &Value) -> Result<Value, Error> {
+    if let Some(allow) = params["initializationOptions"]["allow"].as_array() {
+      for rule in allow {
+        self.lints.allow(rule.as_str().unwrap_or(""))?;
+      }
+    }
+
+    Ok(json!({
+      "capabilities": {
+        "textDocumentSync": { "openClose": true, "change": 2 },
+        "foldingRangeProvider": true,
+        "semanticTokensProvider": {
+          "legend": {
+            "tokenTypes": TOKEN_TYPES
+              .iter()
+              .map(|&t| <&'static str>::from(t))
+              .collect::<Vec<_>>(),
+            "tokenModifiers": [],
+          },
+          "full": true,
+        },
+      },
+      "serverInfo": {
+        "name": env!("CARGO_PKG_NAME"),
+        "version": env!("CARGO_PKG_VERSION"),
+      },
+    }))
+  }
+
+  /// Handle a request and return its result, or `None` if the method is
+  /// unknown
+  fn request(
+    &mut self,
+    method: &str,
+    params: &Value,
+  ) -> Option<Result<Value, Error>> {
+    let res = match method {
+      "initialize" => self.initialize(params),
+      "shutdown" => {
+        self.shutdown = true;
+        Ok(Value::Null)
+      }
+      "textDocument/foldingRange" => {
+        self.document(params).map(Document::folding_ranges)
+      }
+      "textDocument/semanticTokens/full" => {
+        self.document(params).map(Document::semantic_tokens)
+      }
+      _ => return None,
+    };
+
+    Some(res)
+  }
+
+  /// Handle a notification
+  fn notification(
+    &mut self,
+    method: &str,
+    params: &Value,
+  ) -> Result<(), Error> {
+    let uri = params["textDocument"]["uri"]
+      .as_str()
+      .unwrap_or("")
+      .to_owned();
+
+    match method {
+      "textDocument/didOpen" => {
+        let text = params["textDocument"]["text"].as_str().unwrap_or("");
+        let document = Document::new(self.buf, text, self.lints)?;
+        let _ = self.documents.insert(uri.clone(), document);
+      }
+      "textDocument/didChange" => {
+        let document = self.documents.get_mut(&uri).ok_or_else(|| {
+          failure::err_msg(format!("Unknown document '{}'", uri))
+        })?;
+
+        if let Some(changes) = params["contentChanges"].as_array() {
+          for change in changes {
+            document.change(change)?;
+          }
+        }
+      }
+      "textDocument/didClose" => {
+        let _ = self.documents.remove(&uri);
+      }
+      _ => {
+        info!("Ignoring notification '{}'", method);
+        return Ok(());
+      }
+    }
+
+    self.publish_diagnostics(&uri)
+  }
+
+  /// Handle a message from the client. Returns `false` if the server should
+  /// exit.
+  fn handle(&mut self, msg: &Value) -> Result<bool, Error> {
+    let method = match msg["method"].as_str() {
+      Some(m) => m,
+      // A response to a request we never sent
+      None => return Ok(true),
+    };
+    let params = &msg["params"];
+
+    match msg.get("id") {
+      Some(id) => {
+        let response = match self.request(method, params) {
+          Some(Ok(result)) => {
+            json!({ "jsonrpc": "2.0", "id": id, "result": result })
+          }
+          Some(Err(e)) => json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": { "code": INTERNAL_ERROR, "message": e.to_string() },
+          }),
+          None => json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": {
+              "code": METHOD_NOT_FOUND,
+              "message": format!("Unknown method '{}'", method),
+            },
+          }),
+        };
+        write_message(&mut self.output, &response)?;
+      }
+      None if method == "exit" => {
+        if self.shutdown {
+          return Ok(false);
+        } else {
+          return Err(failure::err_msg("Received exit before shutdown"));
+        }
+      }
+      None => {
+        if let Err(e) = self.notification(method, params) {
+          warn!("Error handling notification '{}': {}", method, e);
+        }
+      }
+    }
+
+    Ok(true)
+  }
+}
+
+/// Run the language server, reading messages from `input` and sending
+/// messages to `output`, until the client sends the `exit` notification.
+pub fn run<R: BufRead, W: Write>(mut input: R, output: W) -> Result<(), Error> {
+  let buf = Buffer::new(NvimValue::from(0_usize));
+  let mut server = Server {
+    output,
+    buf: &buf,
+    documents: HashMap::new(),
+    lints: Lints::default(),
+    shutdown: false,
+  };
+
+  while let Some(msg) = read_message(&mut input)? {
+    if !server.handle(&msg)? {
+      return Ok(());
+    }
+  }
+
+  Err(failure::err_msg("Input ended before exit notification"))
+}
+
+#[cfg(test)]
+mod tests {
+  use std::io::{BufRead, Cursor};
+
+  use serde_json::{json, Value};
+
+  use crate::lsp::{read_message, run};
+
+  fn message(msg: Value) -> String {
+    let content = msg.to_string();
+    format!("Content-Length: {}\r\n\r\n{}", content.len(), content)
+  }
+
+  fn responses(input: &[Value]) -> Vec<Value> {
+    let input: String = input.iter().cloned().map(message).collect();
+    let mut output = vec![];
+    run(Cursor::new(input), &mut output).unwrap();
+
+    let mut output = Cursor::new(output);
+    let mut responses = vec![];
+    while let Some(msg) = read_message(&mut output).unwrap() {
+      responses.push(msg);
+    }
+    assert!(output.fill_buf().unwrap().is_empty());
+
+    responses
+  }
+
+  const TEXT: &'static str = "\
+NODE  /        1              0.             0.5              0.
+NODE  /        2              0.             0.5              0.
+$Comment
+SHELL /       10       1       1       2       3       4
+";
+
+  #[test]
+  fn lsp_session() {
+    let uri = "file:///deck.pc";
+    let doc = json!({ "textDocument": { "uri": uri } });
+
+    let responses = responses(&[
+      json!({ "jsonrpc": "2.0", "id": 1, "method": "initialize",
+              "params": {} }),
+      json!({ "jsonrpc": "2.0", "method": "initialized", "params": {} }),
+      json!({ "jsonrpc": "2.0", "method": "textDocument/didOpen",
+              "params": { "textDocument": {
+                "uri": uri, "languageId": "pamcrash", "version": 1,
+                "text": TEXT } } }),
+      json!({ "jsonrpc": "2.0", "method": "textDocument/didChange",
+              "params": {
+                "textDocument": { "uri": uri, "version": 2 },
+                "contentChanges": [{
+                  "range": { "start": { "line": 1, "character": 44 },
+                             "end": { "line": 1, "character": 48 } },
+                  "text": "abc" }] } }),
+      json!({ "jsonrpc": "2.0", "id": 2,
+              "method": "textDocument/foldingRange", "params": doc }),
+      json!({ "jsonrpc": "2.0", "id": 3,
+              "method": "textDocument/semanticTokens/full", "params": doc }),
+      json!({ "jsonrpc": "2.0", "id": 4, "method": "unknown/method",
+              "params": {} }),
+      json!({ "jsonrpc": "2.0", "id": 5, "method": "shutdown" }),
+      json!({ "jsonrpc": "2.0", "method": "exit" }),
+    ]);
+
+    assert_eq!(responses.len(), 7);
+    assert_eq!(
+      responses[0]["result"]["capabilities"]["textDocumentSync"]["change"],
+      2
+    );
+
+    assert_eq!(responses[1]["method"], "textDocument/publishDiagnostics");
+    assert_eq!(responses[1]["params"]["diagnostics"], json!([]));
+
+    let diagnostics = &responses[2]["params"]["diagnostics"];
+    assert_eq!(diagnostics.as_array().unwrap().len(), 1);
+    assert_eq!(
+      diagnostics[0]["range"]["start"],
+      json!({"line": 1, "character": 32})
+    );
+    assert_eq!(
+      diagnostics[0]["message"],
+      "Invalid value 'abc' in Node card"
+    );
+
+    assert_eq!(
+      responses[3]["result"],
+      json!([{ "startLine": 0, "endLine": 1 }, { "startLine": 3, "endLine": 3 }])
+    );
+
+    // Keyword, ID and 3 floats on line 0, keyword on line 1, error on its
+    // 4th cell
+    let data = responses[4]["result"]["data"].as_array().unwrap();
+    assert_eq!(
+      &data[..15],
+      &[0, 0, 8, 4, 0, 0, 8, 8, 5, 0, 0, 8, 16, 10, 0][..]
+    );
+    assert_eq!(&data[25..30], &[1, 0, 8, 4, 0][..]);
+    assert_eq!(&data[40..45], &[0, 16, 16, 2, 0][..]);
+
+    assert_eq!(responses[5]["error"]["code"], -32601);
+    assert_eq!(responses[6]["result"], Value::Null);
+  }
+}
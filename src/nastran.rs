@@ -0,0 +1,412 @@
+//! A converter from Nastran bulk data to Pamcrash cards. Only the small field
+//! fixed format is supported, i.e. 10 fields of 8 characters per line, with
+//! continuation lines starting with `+` or a blank first field.
+//!
+//! Converted are `GRID` to `NODE`, `CQUAD4` and `CTRIA3` to `SHELL`, `CHEXA`
+//! to `SOLID`, `CPENTA` to `PENTA6` and `CTETRA` to `TETR4`. The cards are
+//! laid out according to their definitions in the
+//! [`carddata`](crate::carddata) module.
+use std::fmt;
+
+use crate::card::{cell::Cell, keyword::Keyword, line::Line, Card};
+
+/// A value to put into a cell of a Pamcrash card
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Field {
+  Integer(usize),
+  Float(f64),
+}
+
+/// A card read from the Nastran file. The fields are trimmed, a blank field
+/// is an empty string.
+struct NastranCard<'a> {
+  name: &'a str,
+  linenr: usize,
+  fields: Vec<&'a str>,
+}
+
+impl<'a> NastranCard<'a> {
+  fn field(&self, i: usize) -> &'a str {
+    self.fields.get(i).cloned().unwrap_or("")
+  }
+
+  fn integer(&self, i: usize) -> Result<usize, String> {
+    let f = self.field(i);
+    f.parse()
+      .map_err(|_| format!("Could not read field {} '{}' as integer", i + 2, f))
+  }
+
+  /// Read a float, blank fields default to 0.
+  fn float(&self, i: usize) -> Result<f64, String> {
+    let f = self.field(i);
+    if f.is_empty() {
+      Ok(0.0)
+    } else {
+      parse_float(f).ok_or_else(|| {
+        format!("Could not read field {} '{}' as float", i + 2, f)
+      })
+    }
+  }
+
+  /// Read the element ID, the property ID (defaulting to the element ID) and
+  /// `num` node IDs. Fails if more nodes are given, since those are higher
+  /// order elements we do not convert.
+  fn element(&self, num: usize) -> Result<Vec<Field>, String> {
+    let eid = self.integer(0)?;
+    let pid = if self.field(1).is_empty() {
+      eid
+    } else {
+      self.integer(1)?
+    };
+
+    if self.fields.iter().skip(2 + num).any(|f| !f.is_empty()) {
+      return Err(format!(
+        "{} with more than {} nodes not supported",
+        self.name, num
+      ));
+    }
+
+    let mut values = vec![Field::Integer(eid), Field::Integer(pid)];
+    for i in 2..2 + num {
+      values.push(Field::Integer(self.integer(i)?));
+    }
+
+    Ok(values)
+  }
+
+  /// Convert the card to the [`Keyword`](crate::card::keyword::Keyword) of
+  /// the Pamcrash card and the values to put into it. Returns `None` if the
+  /// card is not supported.
+  fn convert(&self) -> Option<Result<(Keyword, Vec<Field>), String>> {
+    let res = match self.name {
+      "GRID" => self.grid(),
+      "CQUAD4" => self.element(4).map(|v| (Keyword::Shell, v)),
+      "CTRIA3" => self.element(3).map(|mut v| {
+        // Pamcrash triangles repeat the last node
+        let last = v[4];
+        v.push(last);
+        (Keyword::Shell, v)
+      }),
+      "CHEXA" => self.element(8).map(|v| (Keyword::Solid, v)),
+      "CPENTA" => self.element(6).map(|v| (Keyword::Penta6, v)),
+      "CTETRA" => self.element(4).map(|v| (Keyword::Tetr4, v)),
+      _ => return None,
+    };
+
+    Some(res)
+  }
+
+  fn grid(&self) -> Result<(Keyword, Vec<Field>), String> {
+    let id = self.integer(0)?;
+
+    match self.field(1) {
+      "" | "0" => {}
+      cp => return Err(format!("Coordinate system {} not supported", cp)),
+    }
+
+    Ok((
+      Keyword::Node,
+      vec![
+        Field::Integer(id),
+        Field::Float(self.float(2)?),
+        Field::Float(self.float(3)?),
+        Field::Float(self.float(4)?),
+      ],
+    ))
+  }
+}
+
+/// The result of converting a Nastran file
+#[derive(Debug, Default, PartialEq)]
+pub struct Conversion {
+  /// The lines of the converted cards, grouped by keyword in order of first
+  /// occurence
+  pub cards: Vec<(Keyword, Vec<String>)>,
+  /// The cards that could not be converted, given by their line number and a
+  /// message
+  pub errors: Vec<(usize, String)>,
+  /// The names of the cards that are not supported, with their number of
+  /// occurences
+  pub unsupported: Vec<(String, usize)>,
+}
+
+impl Conversion {
+  /// Iterate over all lines of the converted cards
+  pub fn lines(&self) -> impl Iterator<Item = &str> {
+    self
+      .cards
+      .iter()
+      .flat_map(|(_, lines)| lines.iter().map(String::as_str))
+  }
+
+  fn add_card(&mut self, kw: Keyword, lines: Vec<String>) {
+    match self.cards.iter_mut().find(|(k, _)| *k == kw) {
+      Some((_, l)) => l.extend(lines),
+      None => self.cards.push((kw, lines)),
+    }
+  }
+
+  fn add_unsupported(&mut self, name: &str) {
+    match self.unsupported.iter_mut().find(|(n, _)| n == name) {
+      Some((_, n)) => *n += 1,
+      None => self.unsupported.push((name.to_owned(), 1)),
+    }
+  }
+
+  fn add(&mut self, card: &NastranCard) {
+    let res = match card.convert() {
+      None => return self.add_unsupported(card.name),
+      Some(r) => r,
+    };
+
+    match res.and_then(|(kw, values)| Ok((kw, layout(kw, &values)?))) {
+      Ok((kw, lines)) => self.add_card(kw, lines),
+      Err(e) => self.errors.push((card.linenr, e)),
+    }
+  }
+}
+
+impl fmt::Display for Conversion {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    for (kw, lines) in &self.cards {
+      let card: &Card = kw.into();
+      writeln!(
+        f,
+        "Converted {} {:?} cards",
+        lines.len() / card.lines.len(),
+        kw
+      )?;
+    }
+    for (name, n) in &self.unsupported {
+      writeln!(f, "Skipped {} unsupported {} cards", n, name)?;
+    }
+    if !self.errors.is_empty() {
+      writeln!(f, "Could not convert {} cards", self.errors.len())?;
+    }
+
+    Ok(())
+  }
+}
+
+/// Parse a Nastran float. Those may omit the `E` of the exponent, e.g.
+/// `1.5-3` means `1.5E-3`.
+fn parse_float(s: &str) -> Option<f64> {
+  if let Ok(f) = s.parse() {
+    return Some(f);
+  }
+
+  let pos = s
+    .char_indices()
+    .skip(1)
+    .filter(|(_, c)| *c == '+' || *c == '-')
+    .last()?
+    .0;
+  format!("{}E{}", &s[..pos], &s[pos..]).parse().ok()
+}
+
+/// The text of the keyword of the Pamcrash cards we convert to
+fn keyword_text(kw: Keyword) -> &'static str {
+  match kw {
+    Keyword::Node => "NODE  / ",
+    Keyword::Shell => "SHELL / ",
+    Keyword::Solid => "SOLID / ",
+    Keyword::Penta6 => "PENTA6/ ",
+    Keyword::Tetr4 => "TETR4 / ",
+    _ => unreachable!("No Nastran card is converted to {:?}", kw),
+  }
+}
+
+/// Format a float so it fits into a cell of width `width`
+fn format_float(f: f64, width: usize) -> String {
+  let mut s = f.to_string();
+  if !s.contains('.') {
+    s.push('.');
+  }
+
+  let mut prec = width;
+  while s.len() > width && prec > 0 {
+    prec -= 1;
+    s = format!("{:.*E}", prec, f);
+  }
+
+  s
+}
+
+/// Lay out the values according to the cells of the card of the keyword. The
+/// values are put into the integer and float cells in order, other cells are
+/// left blank.
+fn layout(kw: Keyword, values: &[Field]) -> Result<Vec<String>, String> {
+  let card: &Card = (&kw).into();
+  let mut values = values.iter();
+  let mut lines = vec![];
+
+  for line in card.lines {
+    let cells = match line {
+      Line::Cells(c) => c,
+      _ => unreachable!("Converted cards only consist of cells"),
+    };
+    let mut s = String::new();
+
+    for cell in cells.iter() {
+      let width = cell.len() as usize;
+
+      match cell {
+        Cell::Kw(k) => s.push_str(keyword_text(*k)),
+        Cell::Integer(_) | Cell::Float(_) => {
+          let v = match values.next() {
+            Some(Field::Integer(i)) => i.to_string(),
+            Some(Field::Float(f)) => format_float(*f, width),
+            None => break,
+          };
+          if v.len() > width {
+            return Err(format!("Value {} too long for {:?} card", v, kw));
+          }
+          s.push_str(&format!("{:>1$}", v, width));
+        }
+        _ => s.push_str(&" ".repeat(width)),
+      }
+    }
+
+    lines.push(s.trim_end().to_owned());
+  }
+
+  Ok(lines)
+}
+
+/// Convert Nastran bulk data. If the input contains a `BEGIN BULK` line,
+/// everything before it is ignored. Reading stops at `ENDDATA`.
+pub fn convert(input: &str) -> Conversion {
+  let mut conversion = Conversion::default();
+  let mut current: Option<NastranCard> = None;
+  let mut in_bulk = !input.lines().any(|l| l.starts_with("BEGIN BULK"));
+
+  for (i, line) in input.lines().enumerate() {
+    let linenr = i + 1;
+
+    if !in_bulk {
+      in_bulk = line.starts_with("BEGIN BULK");
+      continue;
+    }
+
+    if line.trim().is_empty() || line.starts_with('$') {
+      continue;
+    }
+
+    let name = field(line, 0);
+
+    if name.is_empty() || name.starts_with('+') || name.starts_with('*') {
+      if let Some(ref mut card) = current {
+        card.fields.extend((1..9).map(|i| field(line, i)));
+      }
+      continue;
+    }
+
+    if let Some(card) = current.take() {
+      conversion.add(&card);
+    }
+
+    if line.starts_with("ENDDATA") {
+      break;
+    } else if line.contains(',') {
+      conversion
+        .errors
+        .push((linenr, "Free field format not supported".to_owned()));
+    } else if name.ends_with('*') {
+      conversion
+        .errors
+        .push((linenr, "Large field format not supported".to_owned()));
+    } else {
+      current = Some(NastranCard {
+        name,
+        linenr,
+        fields: (1..9).map(|i| field(line, i)).collect(),
+      });
+    }
+  }
+
+  if let Some(card) = current.take() {
+    conversion.add(&card);
+  }
+
+  conversion
+}
+
+/// Return the trimmed `i`th 8-character field of a line
+fn field(line: &str, i: usize) -> &str {
+  let start = (8 * i).min(line.len());
+  let end = (8 * i + 8).min(line.len());
+  line.get(start..end).unwrap_or("").trim()
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::nastran::{convert, parse_float};
+
+  const NASTRAN: &'static str = "\
+$ Supplier mesh
+BEGIN BULK
+GRID           1              0.      0.      0.
+GRID           2             1.0      0.      0.
+GRID           3              1.   1.5-2      0.
+CQUAD4        10       1       1       2       3       4
+GRID           4                      1.   -2.+1
+CTRIA3        11               1       2       3
+CHEXA         12       2       1       2       3       4       5       6+
++              7       8
+CTETRA        13       3       1       2       3       4       5
+CBAR          14       4       1       2
+RBE2          15       1  123456       2
+GRID*          5
+ENDDATA
+GRID           6              0.      0.      0.
+";
+
+  const PAM: [&'static str; 7] = [
+    "NODE  /        1              0.              0.              0.",
+    "NODE  /        2              1.              0.              0.",
+    "NODE  /        3              1.           0.015              0.",
+    "NODE  /        4              0.              1.            -20.",
+    "SHELL /       10       1       1       2       3       4",
+    "SHELL /       11      11       1       2       3       3",
+    "SOLID /       12       2",
+  ];
+
+  #[test]
+  fn nastran_floats() {
+    assert_eq!(parse_float("1.5"), Some(1.5));
+    assert_eq!(parse_float("-1.5-2"), Some(-0.015));
+    assert_eq!(parse_float("2.+3"), Some(2000.0));
+    assert_eq!(parse_float("1.E-1"), Some(0.1));
+    assert_eq!(parse_float("abc"), None);
+  }
+
+  #[test]
+  fn nastran_conversion() {
+    let conv = convert(NASTRAN);
+    let lines: Vec<_> = conv.lines().collect();
+
+    assert_eq!(&lines[..7], &PAM[..]);
+    assert_eq!(
+      lines[7],
+      concat!(
+        "                       1       2       3       4       5       6",
+        "       7       8"
+      )
+    );
+    assert_eq!(lines.len(), 8);
+    assert_eq!(
+      conv.errors,
+      vec![
+        (11, "CTETRA with more than 4 nodes not supported".to_owned()),
+        (14, "Large field format not supported".to_owned())
+      ]
+    );
+    assert_eq!(
+      conv.unsupported,
+      vec![("CBAR".to_owned(), 1), ("RBE2".to_owned(), 1)]
+    );
+
+    parse_bufdata!(bufdata, lines);
+    assert!(bufdata.highlights.iter().all(|(_, h)| !h.is_error()));
+  }
+}
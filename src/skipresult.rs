@@ -2,7 +2,7 @@
 //! [`LinesIter`](crate::linesiter::LinesIter)
 use std::fmt;
 
-use crate::{linenr::LineNr, lines::ParsedLine};
+use crate::lines::ParsedLine;
 
 /// A data structure returned by several skip methods on
 /// [`LinesIter`](crate::linesiter::LinesIter)
@@ -10,11 +10,11 @@ use crate::{linenr::LineNr, lines::ParsedLine};
 /// `nextline` will be `None` in those cases where the iterator returned `None`
 /// before such a line could be found, i.e. the file ended.
 ///
-/// `skip_end` is the index of the last line we skipped.
+/// `skip_end` is the last line we skipped.
 #[derive(Debug)]
 pub struct SkipResult<'a> {
-  pub nextline: Option<&'a ParsedLine<'a>>,
-  pub skip_end: LineNr,
+  pub nextline: Option<ParsedLine<'a>>,
+  pub skip_end: ParsedLine<'a>,
 }
 
 impl<'a> fmt::Display for SkipResult<'a> {
@@ -22,13 +22,13 @@ impl<'a> fmt::Display for SkipResult<'a> {
     if let Some(ref pl) = self.nextline {
       write!(
         f,
-        "SkipResult {{ nextline: {}, skip_end: {:?} }}",
+        "SkipResult {{ nextline: {}, skip_end: {} }}",
         pl, self.skip_end
       )
     } else {
       write!(
         f,
-        "SkipResult {{ nextline: None, skip_end: {:?} }}",
+        "SkipResult {{ nextline: None, skip_end: {} }}",
         self.skip_end
       )
     }
@@ -350,7 +350,7 @@ describe('nvimpam', function()
     ]])
   end)
 
-  it('starts a new instance for a new buffer', function()
+  it('serves a new buffer from the same instance', function()
     command("set nowrap")
     command('edit ' .. alter_slashes('../files/example.pc'))
     command('NvimPamAttach')
@@ -419,20 +419,77 @@ describe('nvimpam', function()
     }
     --]]
 
-    local chans = meths.list_chans()
-    local client1 = chans[3].client
-    local client2 = chans[4].client
-    eq(client1.name, 'nvimpam')
-    eq(client2.name, 'nvimpam')
+    local nvimpam_chans = function()
+      local ids = {}
+      for _, chan in ipairs(meths.list_chans()) do
+        if chan.client and chan.client.name == 'nvimpam' then
+          table.insert(ids, chan.id)
+        end
+      end
+      return ids
+    end
+    local jobids = function()
+      return meths.execute_lua([[
+        local t = {}
+        for k, v in pairs(require('nvimpam.job').jobids) do
+          table.insert(t, {k, v}) end
+        table.sort(t, function(a, b) return a[1] < b[1] end)
+        return t
+      ]], {})
+    end
+
+    local chans = nvimpam_chans()
+    eq(1, #chans)
+    eq({ {1, chans[1]}, {2, chans[1]} }, jobids())
 
     command('call luaeval("require(\'nvimpam\').detach()")')
     sleep(100)
-    chans = meths.list_chans()
-    eq(chans[3].client.name, 'nvimpam')
-    eq(nil, chans[4])
+    eq(chans, nvimpam_chans())
+    eq({ {1, chans[1]} }, jobids())
 
   end)
 
+  it('keeps serving a buffer when another one is detached', function()
+    command('set hidden')
+    insert(input)
+    command('set ft=pamcrash')
+    command('NvimPamAttach')
+    sleep(10)
+
+    command('enew')
+    insert(input)
+    command('set ft=pamcrash')
+    command('NvimPamAttach')
+    sleep(10)
+
+    command('call luaeval("require(\'nvimpam\').detach()")')
+    sleep(10)
+    command('buffer 1')
+
+    feed("1Gdd")
+    command('NvimPamUpdateFolds')
+    command("echo") -- clear the command line
+    feed("1G")
+
+    screen:expect([[
+      {1:^ 3 lines: Node ··································································}|
+      #Comment here                                                                    |
+      {1: 10 lines: Shell ································································}|
+      $Comment                                                                         |
+      #Comment                                                                         |
+      {1: 3 lines: Node ··································································}|
+      {1: 4 lines: Shell ·································································}|
+                                                                                       |
+      {2:~                                                                                }|
+      {2:~                                                                                }|
+      {2:~                                                                                }|
+      {2:~                                                                                }|
+      {2:~                                                                                }|
+      {2:~                                                                                }|
+      {IGNORE}|
+    ]])
+  end)
+
   it('quits on DetachEvent', function()
     command('edit ' .. alter_slashes('../files/example.pc'))
     command('NvimPamAttach')
@@ -962,7 +1019,12 @@ describe('nvimpam', function()
 
   end)
 
-  it('removes highlights and folds after a DetachEvent', function()
+  it('recreates highlights and folds after reloading the buffer', function()
+    local ns = meths.create_namespace('nvimpam')
+    local marks = function()
+      return #meths.buf_get_extmarks(0, ns, 0, -1, {})
+    end
+
     command('edit ' .. alter_slashes('../files/example.pc'))
     command('NvimPamAttach')
     feed("29G")
@@ -986,7 +1048,12 @@ describe('nvimpam', function()
       $# BLANK                                                     QVM   THDID   IDMPD |
                                                                                        |
     ]])
+    sleep(100)
+    local before = marks()
+    eq(true, before > 0)
+
     command("edit!")
+    sleep(100)
 
     screen:expect([[
       NODPLOT    DFLT                                                                  |
@@ -995,16 +1062,113 @@ describe('nvimpam', function()
       END_OCTRL                                                                        |
       $                                                                                |
       $#         IDNOD               X               Y               Z                 |
-      ^NODE  /        1              0.            50.5              0.                 |
-      NODE  /        2              0.            50.5             10.                 |
-      NODE  /        3              0.            50.5             20.                 |
-      NODE  /        4              0.            50.5             30.                 |
-      NODE  /        5              0.            50.5             40.                 |
-      NODE  /        6              0.            50.5             50.                 |
-      NODE  /        7              0.            50.5             60.                 |
-      NODE  /        8              0.            50.5             70.                 |
+      {1:^ 725 lines: Node ································································}|
+      $----------------------------------------------------------------                |
+      $     MATERIAL DEFINITIONS                                                       |
+      $----------------------------------------------------------------                |
+      $ boxbeam                                                                        |
+      $#         IDMAT   MATYP             RHO   ISINT    ISHG  ISTRAT   IFROZ         |
+      MATER /        3     103         7.85E-6       0       0       0       0         |
+      $# BLANK                                                     QVM   THDID   IDMPD |
                                                                                        |
     ]])
+    eq(before, marks())
+  end)
+
+  it('cleans up and quits when the buffer is wiped', function()
+    command('edit ' .. alter_slashes('../files/example.pc'))
+    command('NvimPamAttach')
+    sleep(100)
+    eq('nvimpam', meths.list_chans()[3].client.name)
+
+    command("bwipeout!")
+    -- sleep needed to let the detaching happen
+    sleep(100)
+    eq(nil, meths.list_chans()[3])
+    eq({ }, meths.execute_lua([[
+         local t = {}
+         for k, v in pairs(require('nvimpam.job').jobids) do
+           table.insert(t, {k, v}) end
+         return t
+       ]], {}))
+  end)
+
+  it('exports the mesh to a VTK file', function()
+    insert([[
+      NODE  /        1              0.              0.              0.
+      NODE  /        2              1.              0.              0.
+      NODE  /        3              1.              1.              0.
+      NODE  /        4              0.              1.              0.
+      SHELL /       10       1       1       2       3       4
+      ]])
+    command('set ft=pamcrash')
+    command('NvimPamAttach')
+    sleep(10)
+
+    local file = 'Xnvimpam.vtk'
+    os.remove(file)
+    command('NvimPamExportVtk ' .. file)
+
+    local f = io.open(file, 'r')
+    local written = f:read('*a')
+    f:close()
+    os.remove(file)
+
+    eq(dedent([[
+      # vtk DataFile Version 3.0
+      Exported by nvimpam
+      ASCII
+      DATASET UNSTRUCTURED_GRID
+      POINTS 4 double
+      0 0 0
+      1 0 0
+      1 1 0
+      0 1 0
+      CELLS 1 5
+      4 0 1 2 3
+      CELL_TYPES 1
+      9
+      CELL_DATA 1
+      SCALARS PartID int 1
+      LOOKUP_TABLE default
+      1
+      ]]), written)
+  end)
+
+  it('imports a Nastran file below the cursor', function()
+    local file = 'Xnvimpam.nas'
+    local f = io.open(file, 'w')
+    f:write(dedent([[
+      BEGIN BULK
+      GRID           1              0.      0.      0.
+      GRID           2              1.      0.      0.
+      GRID           3              1.      1.      0.
+      GRID           4              0.      1.      0.
+      CQUAD4        10       1       1       2       3       4
+      ENDDATA
+      ]]))
+    f:close()
+
+    insert([[
+      $ Imported mesh
+      $ END]])
+    command('set ft=pamcrash')
+    command('NvimPamAttach')
+    sleep(10)
+
+    feed("1G")
+    command('NvimPamImportNastran ' .. file)
+    os.remove(file)
+
+    eq({
+      "$ Imported mesh",
+      "NODE  /        1              0.              0.              0.",
+      "NODE  /        2              1.              0.              0.",
+      "NODE  /        3              1.              1.              0.",
+      "NODE  /        4              0.              1.              0.",
+      "SHELL /       10       1       1       2       3       4",
+      "$ END",
+    }, meths.buf_get_lines(0, 0, -1, true))
   end)
 
   it('properly undoes ftplugin settings', function()
@@ -1026,7 +1190,7 @@ describe('nvimpam', function()
       TITLE /  BoxBeam fine meshed model                                               |
       RUNEND/                                                                          |
        TIME      15.01                                                                 |
-      {14:NvimPamAttach}{4:  NvimPamHighlightScreen  NvimPamMenu  NvimPamUpdateFolds           }|
+      {14:NvimPamAttach}{4:  NvimPamExportVtk  NvimPamHighlightScreen  NvimPamImportNastran  > }|
       :NvimPamAttach^                                                                   |
     ]])
 